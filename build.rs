@@ -1,5 +1,7 @@
 extern crate cc;
 
+use std::env;
+
 fn main() {
     let mut shared_config = cc::Build::new();
 
@@ -13,52 +15,81 @@ fn main() {
         .flag("/GL");
 
     #[cfg(not(target_env = "msvc"))]
-    shared_config.flag("-std=c99").flag("-mtune=native");
-
-    let mut config = shared_config.clone();
-
-    config
-        .file("src/c/sph_shabal.c")
-        .file("src/c/mshabal_128_sse2.c")
-        .file("src/c/shabal_sse2.c")
-        .compile("shabal_sse");
-
-    let mut config = shared_config.clone();
-
-    #[cfg(target_env = "msvc")]
-    config.flag("/arch:AVX");
-
-    #[cfg(not(target_env = "msvc"))]
-    config.flag("-mavx");
-
-    config
-        .file("src/c/mshabal_128_avx.c")
-        .file("src/c/shabal_avx.c")
-        .compile("shabal_avx");
-
+    {
+        shared_config.flag("-std=c99");
+        // native tuning makes no sense when cross compiling, e.g. for arm64
+        if env::var("TARGET") == env::var("HOST") {
+            shared_config.flag("-mtune=native");
+        }
+    }
+
+    // SIMD kernels exist for x86 and arm64, other targets use the portable rust implementation
+    let target_arch = env::var("CARGO_CFG_TARGET_ARCH").unwrap_or_default();
+    if target_arch == "x86" || target_arch == "x86_64" {
+        let mut config = shared_config.clone();
+
+        config
+            .file("src/c/mshabal_128_sse2.c")
+            .file("src/c/shabal_sse2.c")
+            .file("src/c/noncegen_common.c")
+            .file("src/c/noncegen_sse2.c")
+            .compile("shabal_sse");
+
+        let mut config = shared_config.clone();
+
+        #[cfg(target_env = "msvc")]
+        config.flag("/arch:AVX");
+
+        #[cfg(not(target_env = "msvc"))]
+        config.flag("-mavx");
+
+        config
+            .file("src/c/mshabal_128_avx.c")
+            .file("src/c/shabal_avx.c")
+            .file("src/c/noncegen_avx.c")
+            .compile("shabal_avx");
+
+        let mut config = shared_config.clone();
+
+        #[cfg(target_env = "msvc")]
+        config.flag("/arch:AVX2");
+
+        #[cfg(not(target_env = "msvc"))]
+        config.flag("-mavx2");
+
+        config
+            .file("src/c/mshabal_256_avx2.c")
+            .file("src/c/shabal_avx2.c")
+            .file("src/c/noncegen_avx2.c")
+            .compile("shabal_avx2");
+
+        let mut config = shared_config.clone();
+
+        #[cfg(target_env = "msvc")]
+        config.flag("/arch:AVX512F");
+
+        #[cfg(not(target_env = "msvc"))]
+        config.flag("-mavx512f");
+
+        config
+            .file("src/c/mshabal_512_avx512f.c")
+            .file("src/c/shabal_avx512f.c")
+            .file("src/c/noncegen_avx512f.c")
+            .compile("shabal_avx512f");
+    }
+
+    // NEON is part of the arm64 baseline, no extra flags needed
+    if target_arch == "aarch64" {
+        let mut config = shared_config.clone();
+
+        config
+            .file("src/c/mshabal_128_neon.c")
+            .file("src/c/shabal_neon.c")
+            .compile("shabal_neon");
+    }
+
+    // last, the SIMD libs above link against it
     let mut config = shared_config.clone();
 
-    #[cfg(target_env = "msvc")]
-    config.flag("/arch:AVX2");
-
-    #[cfg(not(target_env = "msvc"))]
-    config.flag("-mavx2");
-
-    config
-        .file("src/c/mshabal_256_avx2.c")
-        .file("src/c/shabal_avx2.c")
-        .compile("shabal_avx2");
-
-    let mut config = shared_config.clone();
-
-    #[cfg(target_env = "msvc")]
-    config.flag("/arch:AVX512F");
-
-    #[cfg(not(target_env = "msvc"))]
-    config.flag("-mavx512f");
-
-    config
-        .file("src/c/mshabal_512_avx512f.c")
-        .file("src/c/shabal_avx512f.c")
-        .compile("shabal_avx512f");
+    config.file("src/c/sph_shabal.c").compile("shabal");
 }
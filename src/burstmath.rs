@@ -1,4 +1,12 @@
+extern crate sha2;
+extern crate x25519_dalek;
+
+use self::sha2::{Digest, Sha256};
+use self::x25519_dalek::{x25519, X25519_BASEPOINT_BYTES};
 use hex;
+use noncegen::generate_nonce;
+use plot::SCOOP_SIZE;
+use shabal_portable::Shabal256;
 use shabals;
 use std::mem::transmute;
 
@@ -18,3 +26,254 @@ pub fn calculate_scoop(height: u64, gensig: &[u8; 32]) -> u32 {
     let new_gensig = shabals::shabal256(&data);
     (u32::from(new_gensig[30] & 0x0F) << 8) | u32::from(new_gensig[31])
 }
+
+// recomputes the deadline of a nonce from a freshly generated nonce and the portable shabal, so
+// neither the plot on disk nor the SIMD/GPU kernels are involved, not yet divided by the base target
+pub fn calculate_deadline(
+    account_id: u64,
+    nonce: u64,
+    scoop: u32,
+    poc2: bool,
+    gensig: &[u8; 32],
+) -> u64 {
+    let nonce = generate_nonce(account_id, nonce, poc2);
+    let offset = scoop as usize * SCOOP_SIZE as usize;
+
+    let mut hasher = Shabal256::new();
+    hasher.update(gensig);
+    hasher.update(&nonce[offset..offset + SCOOP_SIZE as usize]);
+    let hash = hasher.finish();
+    hash[..8]
+        .iter()
+        .rev()
+        .fold(0u64, |acc, &b| acc << 8 | u64::from(b))
+}
+
+const RS_ALPHABET: &[u8; 32] = b"23456789ABCDEFGHJKLMNPQRSTUVWXYZ";
+const RS_PREFIX: &str = "BURST-";
+// GF(32) exponent and log tables of the NXT style Reed-Solomon addresses
+const GEXP: [u8; 32] = [
+    1, 2, 4, 8, 16, 5, 10, 20, 13, 26, 17, 7, 14, 28, 29, 31, 27, 19, 3, 6, 12, 24, 21, 15, 30, 25,
+    23, 11, 22, 9, 18, 1,
+];
+const GLOG: [u8; 32] = [
+    0, 0, 1, 18, 2, 5, 19, 11, 3, 29, 6, 27, 20, 8, 12, 23, 4, 10, 30, 17, 7, 22, 28, 26, 21, 25,
+    9, 16, 13, 14, 24, 15,
+];
+// position of each address character in the codeword
+const CODEWORD_MAP: [usize; 17] = [3, 2, 1, 0, 7, 6, 5, 4, 13, 14, 15, 16, 12, 8, 9, 10, 11];
+// 13 base 32 digits of the id followed by 4 parity symbols
+const DATA_LEN: usize = 13;
+const CODEWORD_LEN: usize = 17;
+
+fn gmult(a: u8, b: u8) -> u8 {
+    if a == 0 || b == 0 {
+        0
+    } else {
+        GEXP[(GLOG[a as usize] as usize + GLOG[b as usize] as usize) % 31]
+    }
+}
+
+// BURST-XXXX-XXXX-XXXX-XXXXX address of a numeric account id
+pub fn to_rs_address(account_id: u64) -> String {
+    let mut codeword = [0u8; CODEWORD_LEN];
+    for (i, c) in codeword[..DATA_LEN].iter_mut().enumerate() {
+        *c = (account_id >> (5 * i) & 31) as u8;
+    }
+
+    let mut p = [0u8; 4];
+    for i in (0..DATA_LEN).rev() {
+        let fb = codeword[i] ^ p[3];
+        p[3] = p[2] ^ gmult(30, fb);
+        p[2] = p[1] ^ gmult(6, fb);
+        p[1] = p[0] ^ gmult(9, fb);
+        p[0] = gmult(17, fb);
+    }
+    codeword[DATA_LEN..].clone_from_slice(&p);
+
+    let mut address = RS_PREFIX.to_owned();
+    for (i, pos) in CODEWORD_MAP.iter().enumerate() {
+        address.push(RS_ALPHABET[codeword[*pos] as usize] as char);
+        if i & 3 == 3 && i < DATA_LEN {
+            address.push('-');
+        }
+    }
+    address
+}
+
+// numeric account id of an address, the BURST- prefix and the dashes are optional
+pub fn from_rs_address(address: &str) -> Result<u64, String> {
+    let upper = address.trim().to_uppercase();
+    let chars = if upper.starts_with(RS_PREFIX) {
+        &upper[RS_PREFIX.len()..]
+    } else {
+        &upper[..]
+    };
+
+    let mut codeword = [0u8; CODEWORD_LEN];
+    let mut len = 0;
+    for c in chars.bytes().filter(|&c| c != b'-') {
+        let digit = match RS_ALPHABET.iter().position(|&a| a == c) {
+            Some(digit) => digit as u8,
+            None => return Err(format!("invalid character '{}' in {}", c as char, address)),
+        };
+        if len == CODEWORD_LEN {
+            return Err(format!("{} is too long", address));
+        }
+        codeword[CODEWORD_MAP[len]] = digit;
+        len += 1;
+    }
+    if len != CODEWORD_LEN {
+        return Err(format!("{} is too short", address));
+    }
+
+    // all syndromes of a valid codeword are zero
+    let mut sum = 0;
+    for i in 1..5 {
+        let mut t = 0;
+        for j in 0..31 {
+            if j > 12 && j < 27 {
+                continue;
+            }
+            let pos = if j > 26 { j - 14 } else { j };
+            t ^= gmult(codeword[pos], GEXP[(i * j) % 31]);
+        }
+        sum |= t;
+    }
+    if sum != 0 {
+        return Err(format!("{} has an invalid checksum", address));
+    }
+
+    // the top digit only has 4 bits left in a u64
+    if codeword[DATA_LEN - 1] > 15 {
+        return Err(format!("{} is out of range", address));
+    }
+    Ok(codeword[..DATA_LEN]
+        .iter()
+        .enumerate()
+        .fold(0u64, |acc, (i, &c)| acc | u64::from(c) << (5 * i)))
+}
+
+// accepts a numeric id or an address
+pub fn parse_account_id(s: &str) -> Result<u64, String> {
+    match s.trim().parse::<u64>() {
+        Ok(account_id) => Ok(account_id),
+        Err(_) => from_rs_address(s),
+    }
+}
+
+// NXT style keys: the private key is the SHA-256 of the passphrase (x25519 clamps it), the
+// public key the Curve25519 point of it
+pub fn public_key(secret_phrase: &str) -> [u8; 32] {
+    let mut private_key = [0u8; 32];
+    private_key.copy_from_slice(&Sha256::digest(secret_phrase.as_bytes()));
+    x25519(private_key, X25519_BASEPOINT_BYTES)
+}
+
+// the first 8 bytes of the SHA-256 of the public key, little endian
+pub fn account_id_from_public_key(public_key: &[u8; 32]) -> u64 {
+    Sha256::digest(public_key)[..8]
+        .iter()
+        .rev()
+        .fold(0u64, |acc, &b| acc << 8 | u64::from(b))
+}
+
+pub fn account_id_from_secret_phrase(secret_phrase: &str) -> u64 {
+    account_id_from_public_key(&public_key(secret_phrase))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use plot::read_test_scoop;
+    use shabal_portable;
+
+    #[test]
+    fn test_calculate_deadline() {
+        let gensig = decode_gensig(&"5a".repeat(32));
+        let scoop = calculate_scoop(502_000, &gensig);
+        // the account of the test plot
+        let account_id = 10282355196851764065;
+        let bs = read_test_scoop(scoop);
+
+        for nonce in 0..8 {
+            let (deadline, _) =
+                shabal_portable::find_best_deadline(&bs[nonce as usize * 64..], 1, &gensig);
+            assert_eq!(
+                calculate_deadline(account_id, nonce, scoop, true, &gensig),
+                deadline
+            );
+        }
+    }
+    #[test]
+    fn test_rs_address() {
+        assert_eq!(to_rs_address(0), "BURST-2222-2222-2222-22222");
+        assert_eq!(from_rs_address("BURST-2222-2222-2222-22222"), Ok(0));
+
+        // donation addresses from the README
+        assert_eq!(
+            to_rs_address(7_900_104_405_094_198_526),
+            "BURST-8V9Y-58B4-RVWP-8HQAV"
+        );
+        assert_eq!(
+            from_rs_address("BURST-S338-R6VC-LTFA-2GC6G"),
+            Ok(509_274_790_262_735_910)
+        );
+
+        let address = to_rs_address(10_282_355_196_851_764_065);
+        assert_eq!(address.len(), 26);
+        assert_eq!(from_rs_address(&address), Ok(10_282_355_196_851_764_065));
+        // prefix, dashes and case don't matter
+        let lower = address[6..].replace("-", "").to_lowercase();
+        assert_eq!(from_rs_address(&lower), Ok(10_282_355_196_851_764_065));
+
+        let mut x = 1u64;
+        for _ in 0..1000 {
+            x = x.wrapping_mul(6_364_136_223_846_793_005).wrapping_add(1);
+            assert_eq!(from_rs_address(&to_rs_address(x)), Ok(x));
+        }
+        assert_eq!(from_rs_address(&to_rs_address(u64::MAX)), Ok(u64::MAX));
+
+        // every single wrong character is caught by the checksum
+        for i in 6..address.len() {
+            if &address[i..=i] == "-" {
+                continue;
+            }
+            for c in "23456789ABCDEFGHJKLMNPQRSTUVWXYZ".chars() {
+                let mut broken = address.clone();
+                broken.replace_range(i..=i, &c.to_string());
+                if broken != address {
+                    assert!(from_rs_address(&broken).is_err(), "{}", broken);
+                }
+            }
+        }
+
+        assert!(from_rs_address("BURST-2222-2222-2222-2222").is_err());
+        assert!(from_rs_address("BURST-2222-2222-2222-222222").is_err());
+        assert!(from_rs_address("BURST-2222-2222-2222-2222I").is_err());
+        assert_eq!(parse_account_id(" 42 "), Ok(42));
+        assert_eq!(parse_account_id(&to_rs_address(42)), Ok(42));
+        assert!(parse_account_id("-1").is_err());
+    }
+
+    #[test]
+    fn test_account_id_from_secret_phrase() {
+        // the accounts of config.yaml
+        assert_eq!(
+            account_id_from_secret_phrase(
+                "glad suffer red during single glow shut slam hill death lust although"
+            ),
+            10_282_355_196_851_764_065
+        );
+        assert_eq!(
+            account_id_from_secret_phrase(
+                "stand rude those door invite reflection anywhere lace safe hidden fur horrible"
+            ),
+            1_796_535_821_016_683_299
+        );
+        assert_ne!(
+            account_id_from_secret_phrase("glad suffer red"),
+            10_282_355_196_851_764_065
+        );
+    }
+}
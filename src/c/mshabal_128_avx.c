@@ -284,19 +284,65 @@ void simd128_avx_mshabal(mshabal_context* sc, const void* data0, const void* dat
         }
     }
 
-    num = 1;
+    num = len >> 6;
     if (num != 0) {
         simd128_avx_mshabal_compress(sc, (const unsigned char*)data0, (const unsigned char*)data1,
                                  (const unsigned char*)data2, (const unsigned char*)data3, num);
-        sc->xbuf0 = (unsigned char*)data0 + (num << 6);
-        sc->xbuf1 = (unsigned char*)data1 + (num << 6);
-        sc->xbuf2 = (unsigned char*)data2 + (num << 6);
-        sc->xbuf3 = (unsigned char*)data3 + (num << 6);
+        data0 = (const unsigned char*)data0 + (num << 6);
+        data1 = (const unsigned char*)data1 + (num << 6);
+        data2 = (const unsigned char*)data2 + (num << 6);
+        data3 = (const unsigned char*)data3 + (num << 6);
     }
     len &= 63;
+    memcpy(sc->buf0, data0, len);
+    memcpy(sc->buf1, data1, len);
+    memcpy(sc->buf2, data2, len);
+    memcpy(sc->buf3, data3, len);
     sc->ptr = len;
 }
 
+/* see shabal_small.h */
+void simd128_avx_mshabal_close(mshabal_context* sc, unsigned ub0, unsigned ub1, unsigned ub2,
+                           unsigned ub3, unsigned n, void* dst0, void* dst1, void* dst2,
+                           void* dst3) {
+    size_t ptr, off;
+    unsigned z, out_size_w32;
+
+    z = 0x80 >> n;
+    ptr = sc->ptr;
+    sc->buf0[ptr] = ((ub0 & -z) | z) & 0xFF;
+    sc->buf1[ptr] = ((ub1 & -z) | z) & 0xFF;
+    sc->buf2[ptr] = ((ub2 & -z) | z) & 0xFF;
+    sc->buf3[ptr] = ((ub3 & -z) | z) & 0xFF;
+    ptr++;
+    memset(sc->buf0 + ptr, 0, (sizeof sc->buf0) - ptr);
+    memset(sc->buf1 + ptr, 0, (sizeof sc->buf1) - ptr);
+    memset(sc->buf2 + ptr, 0, (sizeof sc->buf2) - ptr);
+    memset(sc->buf3 + ptr, 0, (sizeof sc->buf3) - ptr);
+    for (z = 0; z < 4; z++) {
+        simd128_avx_mshabal_compress(sc, sc->buf0, sc->buf1, sc->buf2, sc->buf3, 1);
+        if (sc->Wlow-- == 0) sc->Whigh--;
+    }
+    out_size_w32 = sc->out_size >> 5;
+    off = 4 * (28 + (16 - out_size_w32));
+    if (dst0 != NULL) {
+        u32* out = (u32*)dst0;
+        for (z = 0; z < out_size_w32; z++) out[z] = sc->state[off + z * 4 + 0];
+    }
+    if (dst1 != NULL) {
+        u32* out = (u32*)dst1;
+        for (z = 0; z < out_size_w32; z++) out[z] = sc->state[off + z * 4 + 1];
+    }
+    if (dst2 != NULL) {
+        u32* out = (u32*)dst2;
+        for (z = 0; z < out_size_w32; z++) out[z] = sc->state[off + z * 4 + 2];
+    }
+    if (dst3 != NULL) {
+        u32* out = (u32*)dst3;
+        for (z = 0; z < out_size_w32; z++) out[z] = sc->state[off + z * 4 + 3];
+    }
+}
+
 static void simd128_avx_mshabal_compress_fast(mshabal_context_fast* sc, void* u1, void* u2,
                                           size_t num) {
     union input {
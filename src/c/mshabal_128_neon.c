@@ -0,0 +1,563 @@
+/*
+ * Parallel implementation of Shabal, using the NEON unit. This code
+ * compiles and runs on ARM64 (AArch64), where NEON is always available.
+ * It is a port of mshabal_128_sse2.c, processing four instances in parallel.
+ *
+ *
+ * (c) 2010 SAPHIR project. This software is provided 'as-is', without
+ * any epxress or implied warranty. In no event will the authors be held
+ * liable for any damages arising from the use of this software.
+ *
+ * Permission is granted to anyone to use this software for any purpose,
+ * including commercial applications, and to alter it and redistribute it
+ * freely, subject to no restriction.
+ *
+ * Technical remarks and questions can be addressed to:
+ * <thomas.pornin@cryptolog.com>
+ *
+ * Routines have been optimized for and limited to burst mining. Deadline
+ * generation only, no signature generation possible (use sph_shabal for the
+ * deadline). Johnny
+ */
+
+#include <arm_neon.h>
+#include <stddef.h>
+#include <string.h>
+#include "mshabal_128_neon.h"
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+typedef mshabal_u32 u32;
+
+#define C32(x) ((u32)x##UL)
+#define T32(x) ((x)&C32(0xFFFFFFFF))
+#define ROTL32(x, n) T32(((x) << (n)) | ((x) >> (32 - (n))))
+
+static void simd128_neon_mshabal_compress(mshabal_context* sc, const unsigned char* buf0,
+                                     const unsigned char* buf1, const unsigned char* buf2,
+                                     const unsigned char* buf3, size_t num) {
+
+    union {
+        u32 words[64];
+        uint32x4_t data[16];
+    } u;
+    size_t j;
+    uint32x4_t A[12], B[16], C[16];
+    uint32x4_t one;
+
+    for (j = 0; j < 12; j++) A[j] = vld1q_u32(sc->state + 4 * (j));
+    for (j = 0; j < 16; j++) {
+        B[j] = vld1q_u32(sc->state + 4 * (j + 12));
+        C[j] = vld1q_u32(sc->state + 4 * (j + 28));
+    }
+    one = vdupq_n_u32(C32(0xFFFFFFFF));
+
+#define M(i) u.data[(i)]
+
+    while (num-- > 0) {
+        for (j = 0; j < 64; j += 4) {
+            u.words[j + 0] = *(u32*)(buf0 + j);
+            u.words[j + 1] = *(u32*)(buf1 + j);
+            u.words[j + 2] = *(u32*)(buf2 + j);
+            u.words[j + 3] = *(u32*)(buf3 + j);
+        }
+
+        for (j = 0; j < 16; j++) B[j] = vaddq_u32(B[j], M(j));
+
+        A[0] = veorq_u32(A[0], vdupq_n_u32(sc->Wlow));
+        A[1] = veorq_u32(A[1], vdupq_n_u32(sc->Whigh));
+
+        for (j = 0; j < 16; j++)
+            B[j] = vorrq_u32(vshlq_n_u32(B[j], 17), vshrq_n_u32(B[j], 15));
+
+#define PP(xa0, xa1, xb0, xb1, xb2, xb3, xc, xm)                                                   \
+    do {                                                                                           \
+        uint32x4_t tt;                                                                                \
+        tt = vorrq_u32(vshlq_n_u32(xa1, 15), vshrq_n_u32(xa1, 17));                       \
+        tt = vaddq_u32(vshlq_n_u32(tt, 2), tt);                                             \
+        tt = veorq_u32(veorq_u32(xa0, tt), xc);                                            \
+        tt = vaddq_u32(vshlq_n_u32(tt, 1), tt);                                             \
+        tt = veorq_u32(veorq_u32(tt, xb1), veorq_u32(vbicq_u32(xb2, xb3), xm)); \
+        xa0 = tt;                                                                                  \
+        tt = xb0;                                                                                  \
+        tt = vorrq_u32(vshlq_n_u32(tt, 1), vshrq_n_u32(tt, 31));                          \
+        xb0 = veorq_u32(tt, veorq_u32(xa0, one));                                          \
+    } while (0)
+
+        PP(A[0x0], A[0xB], B[0x0], B[0xD], B[0x9], B[0x6], C[0x8], M(0x0));
+        PP(A[0x1], A[0x0], B[0x1], B[0xE], B[0xA], B[0x7], C[0x7], M(0x1));
+        PP(A[0x2], A[0x1], B[0x2], B[0xF], B[0xB], B[0x8], C[0x6], M(0x2));
+        PP(A[0x3], A[0x2], B[0x3], B[0x0], B[0xC], B[0x9], C[0x5], M(0x3));
+        PP(A[0x4], A[0x3], B[0x4], B[0x1], B[0xD], B[0xA], C[0x4], M(0x4));
+        PP(A[0x5], A[0x4], B[0x5], B[0x2], B[0xE], B[0xB], C[0x3], M(0x5));
+        PP(A[0x6], A[0x5], B[0x6], B[0x3], B[0xF], B[0xC], C[0x2], M(0x6));
+        PP(A[0x7], A[0x6], B[0x7], B[0x4], B[0x0], B[0xD], C[0x1], M(0x7));
+        PP(A[0x8], A[0x7], B[0x8], B[0x5], B[0x1], B[0xE], C[0x0], M(0x8));
+        PP(A[0x9], A[0x8], B[0x9], B[0x6], B[0x2], B[0xF], C[0xF], M(0x9));
+        PP(A[0xA], A[0x9], B[0xA], B[0x7], B[0x3], B[0x0], C[0xE], M(0xA));
+        PP(A[0xB], A[0xA], B[0xB], B[0x8], B[0x4], B[0x1], C[0xD], M(0xB));
+        PP(A[0x0], A[0xB], B[0xC], B[0x9], B[0x5], B[0x2], C[0xC], M(0xC));
+        PP(A[0x1], A[0x0], B[0xD], B[0xA], B[0x6], B[0x3], C[0xB], M(0xD));
+        PP(A[0x2], A[0x1], B[0xE], B[0xB], B[0x7], B[0x4], C[0xA], M(0xE));
+        PP(A[0x3], A[0x2], B[0xF], B[0xC], B[0x8], B[0x5], C[0x9], M(0xF));
+
+        PP(A[0x4], A[0x3], B[0x0], B[0xD], B[0x9], B[0x6], C[0x8], M(0x0));
+        PP(A[0x5], A[0x4], B[0x1], B[0xE], B[0xA], B[0x7], C[0x7], M(0x1));
+        PP(A[0x6], A[0x5], B[0x2], B[0xF], B[0xB], B[0x8], C[0x6], M(0x2));
+        PP(A[0x7], A[0x6], B[0x3], B[0x0], B[0xC], B[0x9], C[0x5], M(0x3));
+        PP(A[0x8], A[0x7], B[0x4], B[0x1], B[0xD], B[0xA], C[0x4], M(0x4));
+        PP(A[0x9], A[0x8], B[0x5], B[0x2], B[0xE], B[0xB], C[0x3], M(0x5));
+        PP(A[0xA], A[0x9], B[0x6], B[0x3], B[0xF], B[0xC], C[0x2], M(0x6));
+        PP(A[0xB], A[0xA], B[0x7], B[0x4], B[0x0], B[0xD], C[0x1], M(0x7));
+        PP(A[0x0], A[0xB], B[0x8], B[0x5], B[0x1], B[0xE], C[0x0], M(0x8));
+        PP(A[0x1], A[0x0], B[0x9], B[0x6], B[0x2], B[0xF], C[0xF], M(0x9));
+        PP(A[0x2], A[0x1], B[0xA], B[0x7], B[0x3], B[0x0], C[0xE], M(0xA));
+        PP(A[0x3], A[0x2], B[0xB], B[0x8], B[0x4], B[0x1], C[0xD], M(0xB));
+        PP(A[0x4], A[0x3], B[0xC], B[0x9], B[0x5], B[0x2], C[0xC], M(0xC));
+        PP(A[0x5], A[0x4], B[0xD], B[0xA], B[0x6], B[0x3], C[0xB], M(0xD));
+        PP(A[0x6], A[0x5], B[0xE], B[0xB], B[0x7], B[0x4], C[0xA], M(0xE));
+        PP(A[0x7], A[0x6], B[0xF], B[0xC], B[0x8], B[0x5], C[0x9], M(0xF));
+
+        PP(A[0x8], A[0x7], B[0x0], B[0xD], B[0x9], B[0x6], C[0x8], M(0x0));
+        PP(A[0x9], A[0x8], B[0x1], B[0xE], B[0xA], B[0x7], C[0x7], M(0x1));
+        PP(A[0xA], A[0x9], B[0x2], B[0xF], B[0xB], B[0x8], C[0x6], M(0x2));
+        PP(A[0xB], A[0xA], B[0x3], B[0x0], B[0xC], B[0x9], C[0x5], M(0x3));
+        PP(A[0x0], A[0xB], B[0x4], B[0x1], B[0xD], B[0xA], C[0x4], M(0x4));
+        PP(A[0x1], A[0x0], B[0x5], B[0x2], B[0xE], B[0xB], C[0x3], M(0x5));
+        PP(A[0x2], A[0x1], B[0x6], B[0x3], B[0xF], B[0xC], C[0x2], M(0x6));
+        PP(A[0x3], A[0x2], B[0x7], B[0x4], B[0x0], B[0xD], C[0x1], M(0x7));
+        PP(A[0x4], A[0x3], B[0x8], B[0x5], B[0x1], B[0xE], C[0x0], M(0x8));
+        PP(A[0x5], A[0x4], B[0x9], B[0x6], B[0x2], B[0xF], C[0xF], M(0x9));
+        PP(A[0x6], A[0x5], B[0xA], B[0x7], B[0x3], B[0x0], C[0xE], M(0xA));
+        PP(A[0x7], A[0x6], B[0xB], B[0x8], B[0x4], B[0x1], C[0xD], M(0xB));
+        PP(A[0x8], A[0x7], B[0xC], B[0x9], B[0x5], B[0x2], C[0xC], M(0xC));
+        PP(A[0x9], A[0x8], B[0xD], B[0xA], B[0x6], B[0x3], C[0xB], M(0xD));
+        PP(A[0xA], A[0x9], B[0xE], B[0xB], B[0x7], B[0x4], C[0xA], M(0xE));
+        PP(A[0xB], A[0xA], B[0xF], B[0xC], B[0x8], B[0x5], C[0x9], M(0xF));
+
+        A[0xB] = vaddq_u32(A[0xB], C[0x6]);
+        A[0xA] = vaddq_u32(A[0xA], C[0x5]);
+        A[0x9] = vaddq_u32(A[0x9], C[0x4]);
+        A[0x8] = vaddq_u32(A[0x8], C[0x3]);
+        A[0x7] = vaddq_u32(A[0x7], C[0x2]);
+        A[0x6] = vaddq_u32(A[0x6], C[0x1]);
+        A[0x5] = vaddq_u32(A[0x5], C[0x0]);
+        A[0x4] = vaddq_u32(A[0x4], C[0xF]);
+        A[0x3] = vaddq_u32(A[0x3], C[0xE]);
+        A[0x2] = vaddq_u32(A[0x2], C[0xD]);
+        A[0x1] = vaddq_u32(A[0x1], C[0xC]);
+        A[0x0] = vaddq_u32(A[0x0], C[0xB]);
+        A[0xB] = vaddq_u32(A[0xB], C[0xA]);
+        A[0xA] = vaddq_u32(A[0xA], C[0x9]);
+        A[0x9] = vaddq_u32(A[0x9], C[0x8]);
+        A[0x8] = vaddq_u32(A[0x8], C[0x7]);
+        A[0x7] = vaddq_u32(A[0x7], C[0x6]);
+        A[0x6] = vaddq_u32(A[0x6], C[0x5]);
+        A[0x5] = vaddq_u32(A[0x5], C[0x4]);
+        A[0x4] = vaddq_u32(A[0x4], C[0x3]);
+        A[0x3] = vaddq_u32(A[0x3], C[0x2]);
+        A[0x2] = vaddq_u32(A[0x2], C[0x1]);
+        A[0x1] = vaddq_u32(A[0x1], C[0x0]);
+        A[0x0] = vaddq_u32(A[0x0], C[0xF]);
+        A[0xB] = vaddq_u32(A[0xB], C[0xE]);
+        A[0xA] = vaddq_u32(A[0xA], C[0xD]);
+        A[0x9] = vaddq_u32(A[0x9], C[0xC]);
+        A[0x8] = vaddq_u32(A[0x8], C[0xB]);
+        A[0x7] = vaddq_u32(A[0x7], C[0xA]);
+        A[0x6] = vaddq_u32(A[0x6], C[0x9]);
+        A[0x5] = vaddq_u32(A[0x5], C[0x8]);
+        A[0x4] = vaddq_u32(A[0x4], C[0x7]);
+        A[0x3] = vaddq_u32(A[0x3], C[0x6]);
+        A[0x2] = vaddq_u32(A[0x2], C[0x5]);
+        A[0x1] = vaddq_u32(A[0x1], C[0x4]);
+        A[0x0] = vaddq_u32(A[0x0], C[0x3]);
+
+#define SWAP_AND_SUB(xb, xc, xm)    \
+    do {                            \
+        uint32x4_t tmp;                \
+        tmp = xb;                   \
+        xb = vsubq_u32(xc, xm); \
+        xc = tmp;                   \
+    } while (0)
+
+        SWAP_AND_SUB(B[0x0], C[0x0], M(0x0));
+        SWAP_AND_SUB(B[0x1], C[0x1], M(0x1));
+        SWAP_AND_SUB(B[0x2], C[0x2], M(0x2));
+        SWAP_AND_SUB(B[0x3], C[0x3], M(0x3));
+        SWAP_AND_SUB(B[0x4], C[0x4], M(0x4));
+        SWAP_AND_SUB(B[0x5], C[0x5], M(0x5));
+        SWAP_AND_SUB(B[0x6], C[0x6], M(0x6));
+        SWAP_AND_SUB(B[0x7], C[0x7], M(0x7));
+        SWAP_AND_SUB(B[0x8], C[0x8], M(0x8));
+        SWAP_AND_SUB(B[0x9], C[0x9], M(0x9));
+        SWAP_AND_SUB(B[0xA], C[0xA], M(0xA));
+        SWAP_AND_SUB(B[0xB], C[0xB], M(0xB));
+        SWAP_AND_SUB(B[0xC], C[0xC], M(0xC));
+        SWAP_AND_SUB(B[0xD], C[0xD], M(0xD));
+        SWAP_AND_SUB(B[0xE], C[0xE], M(0xE));
+        SWAP_AND_SUB(B[0xF], C[0xF], M(0xF));
+
+        buf0 += 64;
+        buf1 += 64;
+        buf2 += 64;
+        buf3 += 64;
+        if (++sc->Wlow == 0) sc->Whigh++;
+    }
+
+    for (j = 0; j < 12; j++) vst1q_u32(sc->state + 4 * (j), A[j]);
+    for (j = 0; j < 16; j++) {
+        vst1q_u32(sc->state + 4 * (j + 12), B[j]);
+        vst1q_u32(sc->state + 4 * (j + 28), C[j]);
+    }
+#undef M
+}
+
+/* see shabal_small.h */
+
+void simd128_neon_mshabal_init(mshabal_context* sc, unsigned out_size) {
+    unsigned u;
+
+    // for (u = 0; u < 176; u++)  sc->state[u] = 0;
+    memset(sc->state, 0, sizeof sc->state);
+    memset(sc->buf0, 0, sizeof sc->buf0);
+    memset(sc->buf1, 0, sizeof sc->buf1);
+    memset(sc->buf2, 0, sizeof sc->buf2);
+    memset(sc->buf3, 0, sizeof sc->buf3);
+    for (u = 0; u < 16; u++) {
+        sc->buf0[4 * u + 0] = (out_size + u);
+        sc->buf0[4 * u + 1] = (out_size + u) >> 8;
+        sc->buf1[4 * u + 0] = (out_size + u);
+        sc->buf1[4 * u + 1] = (out_size + u) >> 8;
+        sc->buf2[4 * u + 0] = (out_size + u);
+        sc->buf2[4 * u + 1] = (out_size + u) >> 8;
+        sc->buf3[4 * u + 0] = (out_size + u);
+        sc->buf3[4 * u + 1] = (out_size + u) >> 8;
+    }
+    sc->Whigh = sc->Wlow = C32(0xFFFFFFFF);
+    simd128_neon_mshabal_compress(sc, sc->buf0, sc->buf1, sc->buf2, sc->buf3, 1);
+    for (u = 0; u < 16; u++) {
+        sc->buf0[4 * u + 0] = (out_size + u + 16);
+        sc->buf0[4 * u + 1] = (out_size + u + 16) >> 8;
+        sc->buf1[4 * u + 0] = (out_size + u + 16);
+        sc->buf1[4 * u + 1] = (out_size + u + 16) >> 8;
+        sc->buf2[4 * u + 0] = (out_size + u + 16);
+        sc->buf2[4 * u + 1] = (out_size + u + 16) >> 8;
+        sc->buf3[4 * u + 0] = (out_size + u + 16);
+        sc->buf3[4 * u + 1] = (out_size + u + 16) >> 8;
+    }
+    simd128_neon_mshabal_compress(sc, sc->buf0, sc->buf1, sc->buf2, sc->buf3, 1);
+    sc->ptr = 0;
+    sc->out_size = out_size;
+}
+
+
+static void simd128_neon_mshabal_compress_fast(mshabal_context_fast* sc, void* u1, void* u2,
+                                          size_t num) {
+    union input {
+        u32 words[64];
+        uint32x4_t data[16];
+    };
+    size_t j;
+    uint32x4_t A[12], B[16], C[16];
+    uint32x4_t one;
+
+    for (j = 0; j < 12; j++) A[j] = vld1q_u32(sc->state + 4 * (j));
+    for (j = 0; j < 16; j++) {
+        B[j] = vld1q_u32(sc->state + 4 * (j + 12));
+        C[j] = vld1q_u32(sc->state + 4 * (j + 28));
+    }
+    one = vdupq_n_u32(C32(0xFFFFFFFF));
+
+// Round 1/5
+#define M(i) ((union input*)u1)->data[(i)]
+
+    while (num-- > 0) {
+        for (j = 0; j < 16; j++) B[j] = vaddq_u32(B[j], M(j));
+
+        A[0] = veorq_u32(A[0], vdupq_n_u32(sc->Wlow));
+        A[1] = veorq_u32(A[1], vdupq_n_u32(sc->Whigh));
+
+        for (j = 0; j < 16; j++)
+            B[j] = vorrq_u32(vshlq_n_u32(B[j], 17), vshrq_n_u32(B[j], 15));
+
+#define PP(xa0, xa1, xb0, xb1, xb2, xb3, xc, xm)                                                   \
+    do {                                                                                           \
+        uint32x4_t tt;                                                                                \
+        tt = vorrq_u32(vshlq_n_u32(xa1, 15), vshrq_n_u32(xa1, 17));                       \
+        tt = vaddq_u32(vshlq_n_u32(tt, 2), tt);                                             \
+        tt = veorq_u32(veorq_u32(xa0, tt), xc);                                            \
+        tt = vaddq_u32(vshlq_n_u32(tt, 1), tt);                                             \
+        tt = veorq_u32(veorq_u32(tt, xb1), veorq_u32(vbicq_u32(xb2, xb3), xm)); \
+        xa0 = tt;                                                                                  \
+        tt = xb0;                                                                                  \
+        tt = vorrq_u32(vshlq_n_u32(tt, 1), vshrq_n_u32(tt, 31));                          \
+        xb0 = veorq_u32(tt, veorq_u32(xa0, one));                                          \
+    } while (0)
+
+        PP(A[0x0], A[0xB], B[0x0], B[0xD], B[0x9], B[0x6], C[0x8], M(0x0));
+        PP(A[0x1], A[0x0], B[0x1], B[0xE], B[0xA], B[0x7], C[0x7], M(0x1));
+        PP(A[0x2], A[0x1], B[0x2], B[0xF], B[0xB], B[0x8], C[0x6], M(0x2));
+        PP(A[0x3], A[0x2], B[0x3], B[0x0], B[0xC], B[0x9], C[0x5], M(0x3));
+        PP(A[0x4], A[0x3], B[0x4], B[0x1], B[0xD], B[0xA], C[0x4], M(0x4));
+        PP(A[0x5], A[0x4], B[0x5], B[0x2], B[0xE], B[0xB], C[0x3], M(0x5));
+        PP(A[0x6], A[0x5], B[0x6], B[0x3], B[0xF], B[0xC], C[0x2], M(0x6));
+        PP(A[0x7], A[0x6], B[0x7], B[0x4], B[0x0], B[0xD], C[0x1], M(0x7));
+        PP(A[0x8], A[0x7], B[0x8], B[0x5], B[0x1], B[0xE], C[0x0], M(0x8));
+        PP(A[0x9], A[0x8], B[0x9], B[0x6], B[0x2], B[0xF], C[0xF], M(0x9));
+        PP(A[0xA], A[0x9], B[0xA], B[0x7], B[0x3], B[0x0], C[0xE], M(0xA));
+        PP(A[0xB], A[0xA], B[0xB], B[0x8], B[0x4], B[0x1], C[0xD], M(0xB));
+        PP(A[0x0], A[0xB], B[0xC], B[0x9], B[0x5], B[0x2], C[0xC], M(0xC));
+        PP(A[0x1], A[0x0], B[0xD], B[0xA], B[0x6], B[0x3], C[0xB], M(0xD));
+        PP(A[0x2], A[0x1], B[0xE], B[0xB], B[0x7], B[0x4], C[0xA], M(0xE));
+        PP(A[0x3], A[0x2], B[0xF], B[0xC], B[0x8], B[0x5], C[0x9], M(0xF));
+
+        PP(A[0x4], A[0x3], B[0x0], B[0xD], B[0x9], B[0x6], C[0x8], M(0x0));
+        PP(A[0x5], A[0x4], B[0x1], B[0xE], B[0xA], B[0x7], C[0x7], M(0x1));
+        PP(A[0x6], A[0x5], B[0x2], B[0xF], B[0xB], B[0x8], C[0x6], M(0x2));
+        PP(A[0x7], A[0x6], B[0x3], B[0x0], B[0xC], B[0x9], C[0x5], M(0x3));
+        PP(A[0x8], A[0x7], B[0x4], B[0x1], B[0xD], B[0xA], C[0x4], M(0x4));
+        PP(A[0x9], A[0x8], B[0x5], B[0x2], B[0xE], B[0xB], C[0x3], M(0x5));
+        PP(A[0xA], A[0x9], B[0x6], B[0x3], B[0xF], B[0xC], C[0x2], M(0x6));
+        PP(A[0xB], A[0xA], B[0x7], B[0x4], B[0x0], B[0xD], C[0x1], M(0x7));
+        PP(A[0x0], A[0xB], B[0x8], B[0x5], B[0x1], B[0xE], C[0x0], M(0x8));
+        PP(A[0x1], A[0x0], B[0x9], B[0x6], B[0x2], B[0xF], C[0xF], M(0x9));
+        PP(A[0x2], A[0x1], B[0xA], B[0x7], B[0x3], B[0x0], C[0xE], M(0xA));
+        PP(A[0x3], A[0x2], B[0xB], B[0x8], B[0x4], B[0x1], C[0xD], M(0xB));
+        PP(A[0x4], A[0x3], B[0xC], B[0x9], B[0x5], B[0x2], C[0xC], M(0xC));
+        PP(A[0x5], A[0x4], B[0xD], B[0xA], B[0x6], B[0x3], C[0xB], M(0xD));
+        PP(A[0x6], A[0x5], B[0xE], B[0xB], B[0x7], B[0x4], C[0xA], M(0xE));
+        PP(A[0x7], A[0x6], B[0xF], B[0xC], B[0x8], B[0x5], C[0x9], M(0xF));
+
+        PP(A[0x8], A[0x7], B[0x0], B[0xD], B[0x9], B[0x6], C[0x8], M(0x0));
+        PP(A[0x9], A[0x8], B[0x1], B[0xE], B[0xA], B[0x7], C[0x7], M(0x1));
+        PP(A[0xA], A[0x9], B[0x2], B[0xF], B[0xB], B[0x8], C[0x6], M(0x2));
+        PP(A[0xB], A[0xA], B[0x3], B[0x0], B[0xC], B[0x9], C[0x5], M(0x3));
+        PP(A[0x0], A[0xB], B[0x4], B[0x1], B[0xD], B[0xA], C[0x4], M(0x4));
+        PP(A[0x1], A[0x0], B[0x5], B[0x2], B[0xE], B[0xB], C[0x3], M(0x5));
+        PP(A[0x2], A[0x1], B[0x6], B[0x3], B[0xF], B[0xC], C[0x2], M(0x6));
+        PP(A[0x3], A[0x2], B[0x7], B[0x4], B[0x0], B[0xD], C[0x1], M(0x7));
+        PP(A[0x4], A[0x3], B[0x8], B[0x5], B[0x1], B[0xE], C[0x0], M(0x8));
+        PP(A[0x5], A[0x4], B[0x9], B[0x6], B[0x2], B[0xF], C[0xF], M(0x9));
+        PP(A[0x6], A[0x5], B[0xA], B[0x7], B[0x3], B[0x0], C[0xE], M(0xA));
+        PP(A[0x7], A[0x6], B[0xB], B[0x8], B[0x4], B[0x1], C[0xD], M(0xB));
+        PP(A[0x8], A[0x7], B[0xC], B[0x9], B[0x5], B[0x2], C[0xC], M(0xC));
+        PP(A[0x9], A[0x8], B[0xD], B[0xA], B[0x6], B[0x3], C[0xB], M(0xD));
+        PP(A[0xA], A[0x9], B[0xE], B[0xB], B[0x7], B[0x4], C[0xA], M(0xE));
+        PP(A[0xB], A[0xA], B[0xF], B[0xC], B[0x8], B[0x5], C[0x9], M(0xF));
+
+        A[0xB] = vaddq_u32(A[0xB], C[0x6]);
+        A[0xA] = vaddq_u32(A[0xA], C[0x5]);
+        A[0x9] = vaddq_u32(A[0x9], C[0x4]);
+        A[0x8] = vaddq_u32(A[0x8], C[0x3]);
+        A[0x7] = vaddq_u32(A[0x7], C[0x2]);
+        A[0x6] = vaddq_u32(A[0x6], C[0x1]);
+        A[0x5] = vaddq_u32(A[0x5], C[0x0]);
+        A[0x4] = vaddq_u32(A[0x4], C[0xF]);
+        A[0x3] = vaddq_u32(A[0x3], C[0xE]);
+        A[0x2] = vaddq_u32(A[0x2], C[0xD]);
+        A[0x1] = vaddq_u32(A[0x1], C[0xC]);
+        A[0x0] = vaddq_u32(A[0x0], C[0xB]);
+        A[0xB] = vaddq_u32(A[0xB], C[0xA]);
+        A[0xA] = vaddq_u32(A[0xA], C[0x9]);
+        A[0x9] = vaddq_u32(A[0x9], C[0x8]);
+        A[0x8] = vaddq_u32(A[0x8], C[0x7]);
+        A[0x7] = vaddq_u32(A[0x7], C[0x6]);
+        A[0x6] = vaddq_u32(A[0x6], C[0x5]);
+        A[0x5] = vaddq_u32(A[0x5], C[0x4]);
+        A[0x4] = vaddq_u32(A[0x4], C[0x3]);
+        A[0x3] = vaddq_u32(A[0x3], C[0x2]);
+        A[0x2] = vaddq_u32(A[0x2], C[0x1]);
+        A[0x1] = vaddq_u32(A[0x1], C[0x0]);
+        A[0x0] = vaddq_u32(A[0x0], C[0xF]);
+        A[0xB] = vaddq_u32(A[0xB], C[0xE]);
+        A[0xA] = vaddq_u32(A[0xA], C[0xD]);
+        A[0x9] = vaddq_u32(A[0x9], C[0xC]);
+        A[0x8] = vaddq_u32(A[0x8], C[0xB]);
+        A[0x7] = vaddq_u32(A[0x7], C[0xA]);
+        A[0x6] = vaddq_u32(A[0x6], C[0x9]);
+        A[0x5] = vaddq_u32(A[0x5], C[0x8]);
+        A[0x4] = vaddq_u32(A[0x4], C[0x7]);
+        A[0x3] = vaddq_u32(A[0x3], C[0x6]);
+        A[0x2] = vaddq_u32(A[0x2], C[0x5]);
+        A[0x1] = vaddq_u32(A[0x1], C[0x4]);
+        A[0x0] = vaddq_u32(A[0x0], C[0x3]);
+
+#define SWAP_AND_SUB(xb, xc, xm)    \
+    do {                            \
+        uint32x4_t tmp;                \
+        tmp = xb;                   \
+        xb = vsubq_u32(xc, xm); \
+        xc = tmp;                   \
+    } while (0)
+
+        SWAP_AND_SUB(B[0x0], C[0x0], M(0x0));
+        SWAP_AND_SUB(B[0x1], C[0x1], M(0x1));
+        SWAP_AND_SUB(B[0x2], C[0x2], M(0x2));
+        SWAP_AND_SUB(B[0x3], C[0x3], M(0x3));
+        SWAP_AND_SUB(B[0x4], C[0x4], M(0x4));
+        SWAP_AND_SUB(B[0x5], C[0x5], M(0x5));
+        SWAP_AND_SUB(B[0x6], C[0x6], M(0x6));
+        SWAP_AND_SUB(B[0x7], C[0x7], M(0x7));
+        SWAP_AND_SUB(B[0x8], C[0x8], M(0x8));
+        SWAP_AND_SUB(B[0x9], C[0x9], M(0x9));
+        SWAP_AND_SUB(B[0xA], C[0xA], M(0xA));
+        SWAP_AND_SUB(B[0xB], C[0xB], M(0xB));
+        SWAP_AND_SUB(B[0xC], C[0xC], M(0xC));
+        SWAP_AND_SUB(B[0xD], C[0xD], M(0xD));
+        SWAP_AND_SUB(B[0xE], C[0xE], M(0xE));
+        SWAP_AND_SUB(B[0xF], C[0xF], M(0xF));
+        if (++sc->Wlow == 0) sc->Whigh++;
+    }
+
+// Round 2-5
+#define M2(i) ((union input*)u2)->data[(i)]
+
+    for (int k = 0; k < 4; k++) {
+        for (j = 0; j < 16; j++) B[j] = vaddq_u32(B[j], M2(j));
+
+        A[0] = veorq_u32(A[0], vdupq_n_u32(sc->Wlow));
+        A[1] = veorq_u32(A[1], vdupq_n_u32(sc->Whigh));
+
+        for (j = 0; j < 16; j++)
+            B[j] = vorrq_u32(vshlq_n_u32(B[j], 17), vshrq_n_u32(B[j], 15));
+
+        PP(A[0x0], A[0xB], B[0x0], B[0xD], B[0x9], B[0x6], C[0x8], M2(0x0));
+        PP(A[0x1], A[0x0], B[0x1], B[0xE], B[0xA], B[0x7], C[0x7], M2(0x1));
+        PP(A[0x2], A[0x1], B[0x2], B[0xF], B[0xB], B[0x8], C[0x6], M2(0x2));
+        PP(A[0x3], A[0x2], B[0x3], B[0x0], B[0xC], B[0x9], C[0x5], M2(0x3));
+        PP(A[0x4], A[0x3], B[0x4], B[0x1], B[0xD], B[0xA], C[0x4], M2(0x4));
+        PP(A[0x5], A[0x4], B[0x5], B[0x2], B[0xE], B[0xB], C[0x3], M2(0x5));
+        PP(A[0x6], A[0x5], B[0x6], B[0x3], B[0xF], B[0xC], C[0x2], M2(0x6));
+        PP(A[0x7], A[0x6], B[0x7], B[0x4], B[0x0], B[0xD], C[0x1], M2(0x7));
+        PP(A[0x8], A[0x7], B[0x8], B[0x5], B[0x1], B[0xE], C[0x0], M2(0x8));
+        PP(A[0x9], A[0x8], B[0x9], B[0x6], B[0x2], B[0xF], C[0xF], M2(0x9));
+        PP(A[0xA], A[0x9], B[0xA], B[0x7], B[0x3], B[0x0], C[0xE], M2(0xA));
+        PP(A[0xB], A[0xA], B[0xB], B[0x8], B[0x4], B[0x1], C[0xD], M2(0xB));
+        PP(A[0x0], A[0xB], B[0xC], B[0x9], B[0x5], B[0x2], C[0xC], M2(0xC));
+        PP(A[0x1], A[0x0], B[0xD], B[0xA], B[0x6], B[0x3], C[0xB], M2(0xD));
+        PP(A[0x2], A[0x1], B[0xE], B[0xB], B[0x7], B[0x4], C[0xA], M2(0xE));
+        PP(A[0x3], A[0x2], B[0xF], B[0xC], B[0x8], B[0x5], C[0x9], M2(0xF));
+
+        PP(A[0x4], A[0x3], B[0x0], B[0xD], B[0x9], B[0x6], C[0x8], M2(0x0));
+        PP(A[0x5], A[0x4], B[0x1], B[0xE], B[0xA], B[0x7], C[0x7], M2(0x1));
+        PP(A[0x6], A[0x5], B[0x2], B[0xF], B[0xB], B[0x8], C[0x6], M2(0x2));
+        PP(A[0x7], A[0x6], B[0x3], B[0x0], B[0xC], B[0x9], C[0x5], M2(0x3));
+        PP(A[0x8], A[0x7], B[0x4], B[0x1], B[0xD], B[0xA], C[0x4], M2(0x4));
+        PP(A[0x9], A[0x8], B[0x5], B[0x2], B[0xE], B[0xB], C[0x3], M2(0x5));
+        PP(A[0xA], A[0x9], B[0x6], B[0x3], B[0xF], B[0xC], C[0x2], M2(0x6));
+        PP(A[0xB], A[0xA], B[0x7], B[0x4], B[0x0], B[0xD], C[0x1], M2(0x7));
+        PP(A[0x0], A[0xB], B[0x8], B[0x5], B[0x1], B[0xE], C[0x0], M2(0x8));
+        PP(A[0x1], A[0x0], B[0x9], B[0x6], B[0x2], B[0xF], C[0xF], M2(0x9));
+        PP(A[0x2], A[0x1], B[0xA], B[0x7], B[0x3], B[0x0], C[0xE], M2(0xA));
+        PP(A[0x3], A[0x2], B[0xB], B[0x8], B[0x4], B[0x1], C[0xD], M2(0xB));
+        PP(A[0x4], A[0x3], B[0xC], B[0x9], B[0x5], B[0x2], C[0xC], M2(0xC));
+        PP(A[0x5], A[0x4], B[0xD], B[0xA], B[0x6], B[0x3], C[0xB], M2(0xD));
+        PP(A[0x6], A[0x5], B[0xE], B[0xB], B[0x7], B[0x4], C[0xA], M2(0xE));
+        PP(A[0x7], A[0x6], B[0xF], B[0xC], B[0x8], B[0x5], C[0x9], M2(0xF));
+
+        PP(A[0x8], A[0x7], B[0x0], B[0xD], B[0x9], B[0x6], C[0x8], M2(0x0));
+        PP(A[0x9], A[0x8], B[0x1], B[0xE], B[0xA], B[0x7], C[0x7], M2(0x1));
+        PP(A[0xA], A[0x9], B[0x2], B[0xF], B[0xB], B[0x8], C[0x6], M2(0x2));
+        PP(A[0xB], A[0xA], B[0x3], B[0x0], B[0xC], B[0x9], C[0x5], M2(0x3));
+        PP(A[0x0], A[0xB], B[0x4], B[0x1], B[0xD], B[0xA], C[0x4], M2(0x4));
+        PP(A[0x1], A[0x0], B[0x5], B[0x2], B[0xE], B[0xB], C[0x3], M2(0x5));
+        PP(A[0x2], A[0x1], B[0x6], B[0x3], B[0xF], B[0xC], C[0x2], M2(0x6));
+        PP(A[0x3], A[0x2], B[0x7], B[0x4], B[0x0], B[0xD], C[0x1], M2(0x7));
+        PP(A[0x4], A[0x3], B[0x8], B[0x5], B[0x1], B[0xE], C[0x0], M2(0x8));
+        PP(A[0x5], A[0x4], B[0x9], B[0x6], B[0x2], B[0xF], C[0xF], M2(0x9));
+        PP(A[0x6], A[0x5], B[0xA], B[0x7], B[0x3], B[0x0], C[0xE], M2(0xA));
+        PP(A[0x7], A[0x6], B[0xB], B[0x8], B[0x4], B[0x1], C[0xD], M2(0xB));
+        PP(A[0x8], A[0x7], B[0xC], B[0x9], B[0x5], B[0x2], C[0xC], M2(0xC));
+        PP(A[0x9], A[0x8], B[0xD], B[0xA], B[0x6], B[0x3], C[0xB], M2(0xD));
+        PP(A[0xA], A[0x9], B[0xE], B[0xB], B[0x7], B[0x4], C[0xA], M2(0xE));
+        PP(A[0xB], A[0xA], B[0xF], B[0xC], B[0x8], B[0x5], C[0x9], M2(0xF));
+
+        A[0xB] = vaddq_u32(A[0xB], C[0x6]);
+        A[0xA] = vaddq_u32(A[0xA], C[0x5]);
+        A[0x9] = vaddq_u32(A[0x9], C[0x4]);
+        A[0x8] = vaddq_u32(A[0x8], C[0x3]);
+        A[0x7] = vaddq_u32(A[0x7], C[0x2]);
+        A[0x6] = vaddq_u32(A[0x6], C[0x1]);
+        A[0x5] = vaddq_u32(A[0x5], C[0x0]);
+        A[0x4] = vaddq_u32(A[0x4], C[0xF]);
+        A[0x3] = vaddq_u32(A[0x3], C[0xE]);
+        A[0x2] = vaddq_u32(A[0x2], C[0xD]);
+        A[0x1] = vaddq_u32(A[0x1], C[0xC]);
+        A[0x0] = vaddq_u32(A[0x0], C[0xB]);
+        A[0xB] = vaddq_u32(A[0xB], C[0xA]);
+        A[0xA] = vaddq_u32(A[0xA], C[0x9]);
+        A[0x9] = vaddq_u32(A[0x9], C[0x8]);
+        A[0x8] = vaddq_u32(A[0x8], C[0x7]);
+        A[0x7] = vaddq_u32(A[0x7], C[0x6]);
+        A[0x6] = vaddq_u32(A[0x6], C[0x5]);
+        A[0x5] = vaddq_u32(A[0x5], C[0x4]);
+        A[0x4] = vaddq_u32(A[0x4], C[0x3]);
+        A[0x3] = vaddq_u32(A[0x3], C[0x2]);
+        A[0x2] = vaddq_u32(A[0x2], C[0x1]);
+        A[0x1] = vaddq_u32(A[0x1], C[0x0]);
+        A[0x0] = vaddq_u32(A[0x0], C[0xF]);
+        A[0xB] = vaddq_u32(A[0xB], C[0xE]);
+        A[0xA] = vaddq_u32(A[0xA], C[0xD]);
+        A[0x9] = vaddq_u32(A[0x9], C[0xC]);
+        A[0x8] = vaddq_u32(A[0x8], C[0xB]);
+        A[0x7] = vaddq_u32(A[0x7], C[0xA]);
+        A[0x6] = vaddq_u32(A[0x6], C[0x9]);
+        A[0x5] = vaddq_u32(A[0x5], C[0x8]);
+        A[0x4] = vaddq_u32(A[0x4], C[0x7]);
+        A[0x3] = vaddq_u32(A[0x3], C[0x6]);
+        A[0x2] = vaddq_u32(A[0x2], C[0x5]);
+        A[0x1] = vaddq_u32(A[0x1], C[0x4]);
+        A[0x0] = vaddq_u32(A[0x0], C[0x3]);
+
+        SWAP_AND_SUB(B[0x0], C[0x0], M2(0x0));
+        SWAP_AND_SUB(B[0x1], C[0x1], M2(0x1));
+        SWAP_AND_SUB(B[0x2], C[0x2], M2(0x2));
+        SWAP_AND_SUB(B[0x3], C[0x3], M2(0x3));
+        SWAP_AND_SUB(B[0x4], C[0x4], M2(0x4));
+        SWAP_AND_SUB(B[0x5], C[0x5], M2(0x5));
+        SWAP_AND_SUB(B[0x6], C[0x6], M2(0x6));
+        SWAP_AND_SUB(B[0x7], C[0x7], M2(0x7));
+        SWAP_AND_SUB(B[0x8], C[0x8], M2(0x8));
+        SWAP_AND_SUB(B[0x9], C[0x9], M2(0x9));
+        SWAP_AND_SUB(B[0xA], C[0xA], M2(0xA));
+        SWAP_AND_SUB(B[0xB], C[0xB], M2(0xB));
+        SWAP_AND_SUB(B[0xC], C[0xC], M2(0xC));
+        SWAP_AND_SUB(B[0xD], C[0xD], M2(0xD));
+        SWAP_AND_SUB(B[0xE], C[0xE], M2(0xE));
+        SWAP_AND_SUB(B[0xF], C[0xF], M2(0xF));
+
+        if (++sc->Wlow == 0) sc->Whigh++;
+
+        if (sc->Wlow-- == 0) sc->Whigh--;
+    }
+
+    // transfer results to ram
+    for (j = 0; j < 12; j++) vst1q_u32(sc->state + 4 * (j), A[j]);
+    for (j = 0; j < 16; j++) {
+        vst1q_u32(sc->state + 4 * (j + 12), B[j]);
+        vst1q_u32(sc->state + 4 * (j + 28), C[j]);
+    }
+}
+
+void simd128_neon_mshabal_openclose_fast(mshabal_context_fast* sc, void* u1, void* u2, void* dst0,
+                                    void* dst1, void* dst2, void* dst3) {
+    unsigned z, off, out_size_w32;
+    // run shabal
+    simd128_neon_mshabal_compress_fast(sc, u1, u2, 1);
+    // extract results
+    out_size_w32 = sc->out_size >> 5;
+    off = 4 * (28 + (16 - out_size_w32));
+    for (z = 0; z < 2; z++) {
+        unsigned y = off + (z << 2);
+        ((u32*)dst0)[z] = sc->state[y + 0];
+        ((u32*)dst1)[z] = sc->state[y + 1];
+        ((u32*)dst2)[z] = sc->state[y + 2];
+        ((u32*)dst3)[z] = sc->state[y + 3];
+    }
+}
+
+#ifdef __cplusplus
+}
+#endif
@@ -0,0 +1,77 @@
+/*
+ * A parallel implementation of Shabal, for ARM64 platforms with NEON.
+ *
+ * Port of mshabal_128_sse2.h, it processes four instances of Shabal in
+ * parallel. Only the routines needed for the deadline search are provided.
+ *
+ *
+ * (c) 2010 SAPHIR project. This software is provided 'as-is', without
+ * any epxress or implied warranty. In no event will the authors be held
+ * liable for any damages arising from the use of this software.
+ *
+ * Permission is granted to anyone to use this software for any purpose,
+ * including commercial applications, and to alter it and redistribute it
+ * freely, subject to no restriction.
+ *
+ * Technical remarks and questions can be addressed to:
+ * <thomas.pornin@cryptolog.com>
+ */
+
+#ifndef MSHABAL_NEON_H__
+#define MSHABAL_NEON_H__
+
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+typedef uint32_t mshabal_u32;
+
+/*
+ * The context structure for a Shabal computation. Contents are
+ * private. Such a structure should be allocated and released by
+ * the caller, in any memory area.
+ */
+typedef struct {
+    unsigned char buf0[64];
+    unsigned char buf1[64];
+    unsigned char buf2[64];
+    unsigned char buf3[64];
+    unsigned char* xbuf0;
+    unsigned char* xbuf1;
+    unsigned char* xbuf2;
+    unsigned char* xbuf3;
+    size_t ptr;
+    mshabal_u32 state[(12 + 16 + 16) * 4];
+    mshabal_u32 Whigh, Wlow;
+    unsigned out_size;
+} mshabal_context;
+
+#pragma pack(1)
+typedef struct {
+    mshabal_u32 state[(12 + 16 + 16) * 4];
+    mshabal_u32 Whigh, Wlow;
+    unsigned out_size;
+} mshabal_context_fast;
+#pragma pack()
+
+/*
+ * Initialize a context structure. The output size must be a multiple
+ * of 32, between 32 and 512 (inclusive). The output size is expressed
+ * in bits.
+ */
+void simd128_neon_mshabal_init(mshabal_context* sc, unsigned out_size);
+
+
+/*
+ * Combined open and close routines
+ */
+
+void simd128_neon_mshabal_openclose_fast(mshabal_context_fast* sc, void* u1, void* u2, void* dst0,
+                                    void* dst1, void* dst2, void* dst3);
+#ifdef __cplusplus
+}
+#endif
+
+#endif
@@ -332,25 +332,101 @@ void simd256_mshabal(mshabal256_context* sc, void* data0, void* data1, void* dat
         }
     }
 
-    num = 1;
+    num = len >> 6;
     if (num != 0) {
         simd256_mshabal_compress(sc, (const unsigned char*)data0, (const unsigned char*)data1,
                                  (const unsigned char*)data2, (const unsigned char*)data3,
                                  (const unsigned char*)data4, (const unsigned char*)data5,
                                  (const unsigned char*)data6, (const unsigned char*)data7, num);
-        sc->xbuf0 = (unsigned char*)data0 + (num << 6);
-        sc->xbuf1 = (unsigned char*)data1 + (num << 6);
-        sc->xbuf2 = (unsigned char*)data2 + (num << 6);
-        sc->xbuf3 = (unsigned char*)data3 + (num << 6);
-        sc->xbuf4 = (unsigned char*)data4 + (num << 6);
-        sc->xbuf5 = (unsigned char*)data5 + (num << 6);
-        sc->xbuf6 = (unsigned char*)data6 + (num << 6);
-        sc->xbuf7 = (unsigned char*)data7 + (num << 6);
+        data0 = (unsigned char*)data0 + (num << 6);
+        data1 = (unsigned char*)data1 + (num << 6);
+        data2 = (unsigned char*)data2 + (num << 6);
+        data3 = (unsigned char*)data3 + (num << 6);
+        data4 = (unsigned char*)data4 + (num << 6);
+        data5 = (unsigned char*)data5 + (num << 6);
+        data6 = (unsigned char*)data6 + (num << 6);
+        data7 = (unsigned char*)data7 + (num << 6);
     }
     len &= (size_t)63;
+    memcpy(sc->buf0, data0, len);
+    memcpy(sc->buf1, data1, len);
+    memcpy(sc->buf2, data2, len);
+    memcpy(sc->buf3, data3, len);
+    memcpy(sc->buf4, data4, len);
+    memcpy(sc->buf5, data5, len);
+    memcpy(sc->buf6, data6, len);
+    memcpy(sc->buf7, data7, len);
     sc->ptr = len;
 }
 
+/* see shabal_small.h */
+void simd256_mshabal_close(mshabal256_context* sc, unsigned ub0, unsigned ub1, unsigned ub2,
+                           unsigned ub3, unsigned ub4, unsigned ub5, unsigned ub6, unsigned ub7,
+                           unsigned n, void* dst0, void* dst1, void* dst2, void* dst3, void* dst4,
+                           void* dst5, void* dst6, void* dst7) {
+    size_t ptr, off;
+    unsigned z, out_size_w32;
+
+    z = 0x80 >> n;
+    ptr = sc->ptr;
+    sc->buf0[ptr] = ((ub0 & -z) | z) & 0xFF;
+    sc->buf1[ptr] = ((ub1 & -z) | z) & 0xFF;
+    sc->buf2[ptr] = ((ub2 & -z) | z) & 0xFF;
+    sc->buf3[ptr] = ((ub3 & -z) | z) & 0xFF;
+    sc->buf4[ptr] = ((ub4 & -z) | z) & 0xFF;
+    sc->buf5[ptr] = ((ub5 & -z) | z) & 0xFF;
+    sc->buf6[ptr] = ((ub6 & -z) | z) & 0xFF;
+    sc->buf7[ptr] = ((ub7 & -z) | z) & 0xFF;
+    ptr++;
+    memset(sc->buf0 + ptr, 0, (sizeof sc->buf0) - ptr);
+    memset(sc->buf1 + ptr, 0, (sizeof sc->buf1) - ptr);
+    memset(sc->buf2 + ptr, 0, (sizeof sc->buf2) - ptr);
+    memset(sc->buf3 + ptr, 0, (sizeof sc->buf3) - ptr);
+    memset(sc->buf4 + ptr, 0, (sizeof sc->buf4) - ptr);
+    memset(sc->buf5 + ptr, 0, (sizeof sc->buf5) - ptr);
+    memset(sc->buf6 + ptr, 0, (sizeof sc->buf6) - ptr);
+    memset(sc->buf7 + ptr, 0, (sizeof sc->buf7) - ptr);
+    for (z = 0; z < 4; z++) {
+        simd256_mshabal_compress(sc, sc->buf0, sc->buf1, sc->buf2, sc->buf3, sc->buf4, sc->buf5,
+                                 sc->buf6, sc->buf7, 1);
+        if (sc->Wlow-- == 0) sc->Whigh--;
+    }
+    out_size_w32 = sc->out_size >> 5;
+    off = MSHABAL256_FACTOR * 4 * (28 + (16 - out_size_w32));
+    if (dst0 != NULL) {
+        u32* out = (u32*)dst0;
+        for (z = 0; z < out_size_w32; z++) out[z] = sc->state[off + z * MSHABAL256_FACTOR * 4 + 0];
+    }
+    if (dst1 != NULL) {
+        u32* out = (u32*)dst1;
+        for (z = 0; z < out_size_w32; z++) out[z] = sc->state[off + z * MSHABAL256_FACTOR * 4 + 1];
+    }
+    if (dst2 != NULL) {
+        u32* out = (u32*)dst2;
+        for (z = 0; z < out_size_w32; z++) out[z] = sc->state[off + z * MSHABAL256_FACTOR * 4 + 2];
+    }
+    if (dst3 != NULL) {
+        u32* out = (u32*)dst3;
+        for (z = 0; z < out_size_w32; z++) out[z] = sc->state[off + z * MSHABAL256_FACTOR * 4 + 3];
+    }
+    if (dst4 != NULL) {
+        u32* out = (u32*)dst4;
+        for (z = 0; z < out_size_w32; z++) out[z] = sc->state[off + z * MSHABAL256_FACTOR * 4 + 4];
+    }
+    if (dst5 != NULL) {
+        u32* out = (u32*)dst5;
+        for (z = 0; z < out_size_w32; z++) out[z] = sc->state[off + z * MSHABAL256_FACTOR * 4 + 5];
+    }
+    if (dst6 != NULL) {
+        u32* out = (u32*)dst6;
+        for (z = 0; z < out_size_w32; z++) out[z] = sc->state[off + z * MSHABAL256_FACTOR * 4 + 6];
+    }
+    if (dst7 != NULL) {
+        u32* out = (u32*)dst7;
+        for (z = 0; z < out_size_w32; z++) out[z] = sc->state[off + z * MSHABAL256_FACTOR * 4 + 7];
+    }
+}
+
 // Johnnys double pointer no memmove no register buffering burst mining only
 // optimisation functions (tm) :-p
 
@@ -76,14 +76,14 @@ static void simd512_mshabal_compress(mshabal512_context *sc, const unsigned char
             u.words[j + 5] = *(u32 *)(buf5 + o);
             u.words[j + 6] = *(u32 *)(buf6 + o);
             u.words[j + 7] = *(u32 *)(buf7 + o);
-            u.words[j + 0] = *(u32 *)(buf8 + o);
-            u.words[j + 1] = *(u32 *)(buf9 + o);
-            u.words[j + 2] = *(u32 *)(buf10 + o);
-            u.words[j + 3] = *(u32 *)(buf11 + o);
-            u.words[j + 4] = *(u32 *)(buf12 + o);
-            u.words[j + 5] = *(u32 *)(buf13 + o);
-            u.words[j + 6] = *(u32 *)(buf14 + o);
-            u.words[j + 7] = *(u32 *)(buf15 + o);
+            u.words[j + 8] = *(u32 *)(buf8 + o);
+            u.words[j + 9] = *(u32 *)(buf9 + o);
+            u.words[j + 10] = *(u32 *)(buf10 + o);
+            u.words[j + 11] = *(u32 *)(buf11 + o);
+            u.words[j + 12] = *(u32 *)(buf12 + o);
+            u.words[j + 13] = *(u32 *)(buf13 + o);
+            u.words[j + 14] = *(u32 *)(buf14 + o);
+            u.words[j + 15] = *(u32 *)(buf15 + o);
         }
 
         for (j = 0; j < 16; j++) B[j] = _mm512_add_epi32(B[j], M(j));
@@ -420,7 +420,7 @@ void simd512_mshabal(mshabal512_context *sc, void *data0, void *data1, void *dat
         }
     }
 
-    num = 1;
+    num = len >> 6;
     if (num != 0) {
         simd512_mshabal_compress(sc, (const unsigned char *)data0, (const unsigned char *)data1,
                                  (const unsigned char *)data2, (const unsigned char *)data3,
@@ -430,27 +430,163 @@ void simd512_mshabal(mshabal512_context *sc, void *data0, void *data1, void *dat
                                  (const unsigned char *)data10, (const unsigned char *)data11,
                                  (const unsigned char *)data12, (const unsigned char *)data13,
                                  (const unsigned char *)data14, (const unsigned char *)data15, num);
-        sc->xbuf0 = (unsigned char *)data0 + (num << 6);
-        sc->xbuf1 = (unsigned char *)data1 + (num << 6);
-        sc->xbuf2 = (unsigned char *)data2 + (num << 6);
-        sc->xbuf3 = (unsigned char *)data3 + (num << 6);
-        sc->xbuf4 = (unsigned char *)data4 + (num << 6);
-        sc->xbuf5 = (unsigned char *)data5 + (num << 6);
-        sc->xbuf6 = (unsigned char *)data6 + (num << 6);
-        sc->xbuf7 = (unsigned char *)data7 + (num << 6);
-        sc->xbuf8 = (unsigned char *)data8 + (num << 6);
-        sc->xbuf9 = (unsigned char *)data9 + (num << 6);
-        sc->xbuf10 = (unsigned char *)data10 + (num << 6);
-        sc->xbuf11 = (unsigned char *)data11 + (num << 6);
-        sc->xbuf12 = (unsigned char *)data12 + (num << 6);
-        sc->xbuf13 = (unsigned char *)data13 + (num << 6);
-        sc->xbuf14 = (unsigned char *)data14 + (num << 6);
-        sc->xbuf15 = (unsigned char *)data15 + (num << 6);
+        data0 = (unsigned char *)data0 + (num << 6);
+        data1 = (unsigned char *)data1 + (num << 6);
+        data2 = (unsigned char *)data2 + (num << 6);
+        data3 = (unsigned char *)data3 + (num << 6);
+        data4 = (unsigned char *)data4 + (num << 6);
+        data5 = (unsigned char *)data5 + (num << 6);
+        data6 = (unsigned char *)data6 + (num << 6);
+        data7 = (unsigned char *)data7 + (num << 6);
+        data8 = (unsigned char *)data8 + (num << 6);
+        data9 = (unsigned char *)data9 + (num << 6);
+        data10 = (unsigned char *)data10 + (num << 6);
+        data11 = (unsigned char *)data11 + (num << 6);
+        data12 = (unsigned char *)data12 + (num << 6);
+        data13 = (unsigned char *)data13 + (num << 6);
+        data14 = (unsigned char *)data14 + (num << 6);
+        data15 = (unsigned char *)data15 + (num << 6);
     }
     len &= (size_t)63;
+    memcpy(sc->buf0, data0, len);
+    memcpy(sc->buf1, data1, len);
+    memcpy(sc->buf2, data2, len);
+    memcpy(sc->buf3, data3, len);
+    memcpy(sc->buf4, data4, len);
+    memcpy(sc->buf5, data5, len);
+    memcpy(sc->buf6, data6, len);
+    memcpy(sc->buf7, data7, len);
+    memcpy(sc->buf8, data8, len);
+    memcpy(sc->buf9, data9, len);
+    memcpy(sc->buf10, data10, len);
+    memcpy(sc->buf11, data11, len);
+    memcpy(sc->buf12, data12, len);
+    memcpy(sc->buf13, data13, len);
+    memcpy(sc->buf14, data14, len);
+    memcpy(sc->buf15, data15, len);
     sc->ptr = len;
 }
 
+/* see shabal_small.h */
+void simd512_mshabal_close(mshabal512_context *sc, unsigned ub0, unsigned ub1, unsigned ub2,
+                           unsigned ub3, unsigned ub4, unsigned ub5, unsigned ub6, unsigned ub7,
+                           unsigned ub8, unsigned ub9, unsigned ub10, unsigned ub11, unsigned ub12,
+                           unsigned ub13, unsigned ub14, unsigned ub15, unsigned n, void *dst0,
+                           void *dst1, void *dst2, void *dst3, void *dst4, void *dst5, void *dst6,
+                           void *dst7, void *dst8, void *dst9, void *dst10, void *dst11,
+                           void *dst12, void *dst13, void *dst14, void *dst15) {
+    size_t ptr, off;
+    unsigned z, out_size_w32;
+
+    z = 0x80 >> n;
+    ptr = sc->ptr;
+    sc->buf0[ptr] = ((ub0 & -z) | z) & 0xFF;
+    sc->buf1[ptr] = ((ub1 & -z) | z) & 0xFF;
+    sc->buf2[ptr] = ((ub2 & -z) | z) & 0xFF;
+    sc->buf3[ptr] = ((ub3 & -z) | z) & 0xFF;
+    sc->buf4[ptr] = ((ub4 & -z) | z) & 0xFF;
+    sc->buf5[ptr] = ((ub5 & -z) | z) & 0xFF;
+    sc->buf6[ptr] = ((ub6 & -z) | z) & 0xFF;
+    sc->buf7[ptr] = ((ub7 & -z) | z) & 0xFF;
+    sc->buf8[ptr] = ((ub8 & -z) | z) & 0xFF;
+    sc->buf9[ptr] = ((ub9 & -z) | z) & 0xFF;
+    sc->buf10[ptr] = ((ub10 & -z) | z) & 0xFF;
+    sc->buf11[ptr] = ((ub11 & -z) | z) & 0xFF;
+    sc->buf12[ptr] = ((ub12 & -z) | z) & 0xFF;
+    sc->buf13[ptr] = ((ub13 & -z) | z) & 0xFF;
+    sc->buf14[ptr] = ((ub14 & -z) | z) & 0xFF;
+    sc->buf15[ptr] = ((ub15 & -z) | z) & 0xFF;
+    ptr++;
+    memset(sc->buf0 + ptr, 0, (sizeof sc->buf0) - ptr);
+    memset(sc->buf1 + ptr, 0, (sizeof sc->buf1) - ptr);
+    memset(sc->buf2 + ptr, 0, (sizeof sc->buf2) - ptr);
+    memset(sc->buf3 + ptr, 0, (sizeof sc->buf3) - ptr);
+    memset(sc->buf4 + ptr, 0, (sizeof sc->buf4) - ptr);
+    memset(sc->buf5 + ptr, 0, (sizeof sc->buf5) - ptr);
+    memset(sc->buf6 + ptr, 0, (sizeof sc->buf6) - ptr);
+    memset(sc->buf7 + ptr, 0, (sizeof sc->buf7) - ptr);
+    memset(sc->buf8 + ptr, 0, (sizeof sc->buf8) - ptr);
+    memset(sc->buf9 + ptr, 0, (sizeof sc->buf9) - ptr);
+    memset(sc->buf10 + ptr, 0, (sizeof sc->buf10) - ptr);
+    memset(sc->buf11 + ptr, 0, (sizeof sc->buf11) - ptr);
+    memset(sc->buf12 + ptr, 0, (sizeof sc->buf12) - ptr);
+    memset(sc->buf13 + ptr, 0, (sizeof sc->buf13) - ptr);
+    memset(sc->buf14 + ptr, 0, (sizeof sc->buf14) - ptr);
+    memset(sc->buf15 + ptr, 0, (sizeof sc->buf15) - ptr);
+    for (z = 0; z < 4; z++) {
+        simd512_mshabal_compress(sc, sc->buf0, sc->buf1, sc->buf2, sc->buf3, sc->buf4, sc->buf5,
+                                 sc->buf6, sc->buf7, sc->buf8, sc->buf9, sc->buf10, sc->buf11,
+                                 sc->buf12, sc->buf13, sc->buf14, sc->buf15, 1);
+        if (sc->Wlow-- == 0) sc->Whigh--;
+    }
+    out_size_w32 = sc->out_size >> 5;
+    off = MSHABAL512_FACTOR * 4 * (28 + (16 - out_size_w32));
+    if (dst0 != NULL) {
+        u32 *out = (u32 *)dst0;
+        for (z = 0; z < out_size_w32; z++) out[z] = sc->state[off + z * MSHABAL512_FACTOR * 4 + 0];
+    }
+    if (dst1 != NULL) {
+        u32 *out = (u32 *)dst1;
+        for (z = 0; z < out_size_w32; z++) out[z] = sc->state[off + z * MSHABAL512_FACTOR * 4 + 1];
+    }
+    if (dst2 != NULL) {
+        u32 *out = (u32 *)dst2;
+        for (z = 0; z < out_size_w32; z++) out[z] = sc->state[off + z * MSHABAL512_FACTOR * 4 + 2];
+    }
+    if (dst3 != NULL) {
+        u32 *out = (u32 *)dst3;
+        for (z = 0; z < out_size_w32; z++) out[z] = sc->state[off + z * MSHABAL512_FACTOR * 4 + 3];
+    }
+    if (dst4 != NULL) {
+        u32 *out = (u32 *)dst4;
+        for (z = 0; z < out_size_w32; z++) out[z] = sc->state[off + z * MSHABAL512_FACTOR * 4 + 4];
+    }
+    if (dst5 != NULL) {
+        u32 *out = (u32 *)dst5;
+        for (z = 0; z < out_size_w32; z++) out[z] = sc->state[off + z * MSHABAL512_FACTOR * 4 + 5];
+    }
+    if (dst6 != NULL) {
+        u32 *out = (u32 *)dst6;
+        for (z = 0; z < out_size_w32; z++) out[z] = sc->state[off + z * MSHABAL512_FACTOR * 4 + 6];
+    }
+    if (dst7 != NULL) {
+        u32 *out = (u32 *)dst7;
+        for (z = 0; z < out_size_w32; z++) out[z] = sc->state[off + z * MSHABAL512_FACTOR * 4 + 7];
+    }
+    if (dst8 != NULL) {
+        u32 *out = (u32 *)dst8;
+        for (z = 0; z < out_size_w32; z++) out[z] = sc->state[off + z * MSHABAL512_FACTOR * 4 + 8];
+    }
+    if (dst9 != NULL) {
+        u32 *out = (u32 *)dst9;
+        for (z = 0; z < out_size_w32; z++) out[z] = sc->state[off + z * MSHABAL512_FACTOR * 4 + 9];
+    }
+    if (dst10 != NULL) {
+        u32 *out = (u32 *)dst10;
+        for (z = 0; z < out_size_w32; z++) out[z] = sc->state[off + z * MSHABAL512_FACTOR * 4 + 10];
+    }
+    if (dst11 != NULL) {
+        u32 *out = (u32 *)dst11;
+        for (z = 0; z < out_size_w32; z++) out[z] = sc->state[off + z * MSHABAL512_FACTOR * 4 + 11];
+    }
+    if (dst12 != NULL) {
+        u32 *out = (u32 *)dst12;
+        for (z = 0; z < out_size_w32; z++) out[z] = sc->state[off + z * MSHABAL512_FACTOR * 4 + 12];
+    }
+    if (dst13 != NULL) {
+        u32 *out = (u32 *)dst13;
+        for (z = 0; z < out_size_w32; z++) out[z] = sc->state[off + z * MSHABAL512_FACTOR * 4 + 13];
+    }
+    if (dst14 != NULL) {
+        u32 *out = (u32 *)dst14;
+        for (z = 0; z < out_size_w32; z++) out[z] = sc->state[off + z * MSHABAL512_FACTOR * 4 + 14];
+    }
+    if (dst15 != NULL) {
+        u32 *out = (u32 *)dst15;
+        for (z = 0; z < out_size_w32; z++) out[z] = sc->state[off + z * MSHABAL512_FACTOR * 4 + 15];
+    }
+}
+
 // Johnnys double pointer no memmove no register buffering burst mining only
 // optimisation functions (tm) :-p
 
@@ -141,6 +141,8 @@ typedef struct {
     unsigned out_size;
 } mshabal512_context_fast;
 
+#pragma pack()
+
 /*
  * Initialize a context structure. The output size must be a multiple
  * of 32, between 32 and 512 (inclusive). The output size is expressed
@@ -0,0 +1,46 @@
+#include "noncegen_avx2.h"
+#include <string.h>
+#include "mshabal_256_avx2.h"
+#include "noncegen_common.h"
+
+void noncegen_avx2(char* cache, uint64_t cache_size, uint64_t cache_offset, uint64_t numeric_id,
+                   uint64_t local_startnonce, uint64_t local_nonces) {
+    mshabal256_context init_x, x;
+    simd256_mshabal_init(&init_x, 256);
+
+    char* gendata = malloc(8 * (NONCE_SIZE + SEED_SIZE));
+    char* g[8];
+    char final[8][HASH_SIZE];
+    for (int l = 0; l < 8; l++) g[l] = gendata + l * (NONCE_SIZE + SEED_SIZE);
+
+    for (uint64_t n = 0; n < local_nonces; n += 8) {
+        // surplus lanes of the last round recompute the last nonce and are dropped
+        for (int l = 0; l < 8; l++) {
+            uint64_t offset = n + l < local_nonces ? n + l : local_nonces - 1;
+            write_seed(g[l] + NONCE_SIZE, numeric_id, local_startnonce + offset);
+        }
+
+        for (size_t i = NONCE_SIZE; i > 0; i -= HASH_SIZE) {
+            size_t len = NONCE_SIZE + SEED_SIZE - i;
+            if (len > HASH_CAP) len = HASH_CAP;
+            memcpy(&x, &init_x, sizeof(init_x));
+            simd256_mshabal(&x, g[0] + i, g[1] + i, g[2] + i, g[3] + i, g[4] + i, g[5] + i,
+                            g[6] + i, g[7] + i, len);
+            simd256_mshabal_close(&x, 0, 0, 0, 0, 0, 0, 0, 0, 0, g[0] + i - HASH_SIZE,
+                                  g[1] + i - HASH_SIZE, g[2] + i - HASH_SIZE, g[3] + i - HASH_SIZE,
+                                  g[4] + i - HASH_SIZE, g[5] + i - HASH_SIZE, g[6] + i - HASH_SIZE,
+                                  g[7] + i - HASH_SIZE);
+        }
+
+        memcpy(&x, &init_x, sizeof(init_x));
+        simd256_mshabal(&x, g[0], g[1], g[2], g[3], g[4], g[5], g[6], g[7], NONCE_SIZE + SEED_SIZE);
+        simd256_mshabal_close(&x, 0, 0, 0, 0, 0, 0, 0, 0, 0, final[0], final[1], final[2], final[3],
+                              final[4], final[5], final[6], final[7]);
+
+        for (int l = 0; l < 8 && n + l < local_nonces; l++) {
+            write_nonce(cache, cache_size, cache_offset + n + l, g[l], final[l]);
+        }
+    }
+
+    free(gendata);
+}
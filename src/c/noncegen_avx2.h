@@ -0,0 +1,7 @@
+#pragma once
+
+#include <stdint.h>
+#include <stdlib.h>
+
+void noncegen_avx2(char* cache, uint64_t cache_size, uint64_t cache_offset, uint64_t numeric_id,
+                   uint64_t local_startnonce, uint64_t local_nonces);
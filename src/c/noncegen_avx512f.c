@@ -0,0 +1,54 @@
+#include "noncegen_avx512f.h"
+#include <string.h>
+#include "mshabal_512_avx512f.h"
+#include "noncegen_common.h"
+
+void noncegen_avx512f(char* cache, uint64_t cache_size, uint64_t cache_offset, uint64_t numeric_id,
+                      uint64_t local_startnonce, uint64_t local_nonces) {
+    mshabal512_context init_x, x;
+    simd512_mshabal_init(&init_x, 256);
+
+    char* gendata = malloc(16 * (NONCE_SIZE + SEED_SIZE));
+    char* g[16];
+    char final[16][HASH_SIZE];
+    for (int l = 0; l < 16; l++) g[l] = gendata + l * (NONCE_SIZE + SEED_SIZE);
+
+    for (uint64_t n = 0; n < local_nonces; n += 16) {
+        // surplus lanes of the last round recompute the last nonce and are dropped
+        for (int l = 0; l < 16; l++) {
+            uint64_t offset = n + l < local_nonces ? n + l : local_nonces - 1;
+            write_seed(g[l] + NONCE_SIZE, numeric_id, local_startnonce + offset);
+        }
+
+        for (size_t i = NONCE_SIZE; i > 0; i -= HASH_SIZE) {
+            size_t len = NONCE_SIZE + SEED_SIZE - i;
+            if (len > HASH_CAP) len = HASH_CAP;
+            memcpy(&x, &init_x, sizeof(init_x));
+            simd512_mshabal(&x, g[0] + i, g[1] + i, g[2] + i, g[3] + i, g[4] + i, g[5] + i,
+                            g[6] + i, g[7] + i, g[8] + i, g[9] + i, g[10] + i, g[11] + i, g[12] + i,
+                            g[13] + i, g[14] + i, g[15] + i, len);
+            simd512_mshabal_close(&x, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+                                  g[0] + i - HASH_SIZE, g[1] + i - HASH_SIZE, g[2] + i - HASH_SIZE,
+                                  g[3] + i - HASH_SIZE, g[4] + i - HASH_SIZE, g[5] + i - HASH_SIZE,
+                                  g[6] + i - HASH_SIZE, g[7] + i - HASH_SIZE, g[8] + i - HASH_SIZE,
+                                  g[9] + i - HASH_SIZE, g[10] + i - HASH_SIZE,
+                                  g[11] + i - HASH_SIZE, g[12] + i - HASH_SIZE,
+                                  g[13] + i - HASH_SIZE, g[14] + i - HASH_SIZE,
+                                  g[15] + i - HASH_SIZE);
+        }
+
+        memcpy(&x, &init_x, sizeof(init_x));
+        simd512_mshabal(&x, g[0], g[1], g[2], g[3], g[4], g[5], g[6], g[7], g[8], g[9], g[10],
+                        g[11], g[12], g[13], g[14], g[15], NONCE_SIZE + SEED_SIZE);
+        simd512_mshabal_close(&x, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, final[0],
+                              final[1], final[2], final[3], final[4], final[5], final[6], final[7],
+                              final[8], final[9], final[10], final[11], final[12], final[13],
+                              final[14], final[15]);
+
+        for (int l = 0; l < 16 && n + l < local_nonces; l++) {
+            write_nonce(cache, cache_size, cache_offset + n + l, g[l], final[l]);
+        }
+    }
+
+    free(gendata);
+}
@@ -0,0 +1,24 @@
+#include "noncegen_common.h"
+#include <string.h>
+
+void write_seed(char* seed, uint64_t numeric_id, uint64_t nonce) {
+    for (int i = 0; i < 8; i++) {
+        seed[i] = (char)(numeric_id >> (56 - i * 8));
+        seed[i + 8] = (char)(nonce >> (56 - i * 8));
+    }
+}
+
+void write_nonce(char* cache, uint64_t cache_size, uint64_t cache_offset, char* gendata,
+                 const char* final) {
+    for (int i = 0; i < NONCE_SIZE; i++) gendata[i] ^= final[i % HASH_SIZE];
+
+    // PoC2: the second hash of scoop n belongs to scoop 4095 - n
+    for (uint64_t scoop = 0; scoop < NUM_SCOOPS; scoop++) {
+        char* src = gendata + scoop * SCOOP_SIZE;
+        char* dst = cache + (scoop * cache_size + cache_offset) * SCOOP_SIZE;
+        char* mirror =
+            cache + ((NUM_SCOOPS - 1 - scoop) * cache_size + cache_offset) * SCOOP_SIZE;
+        memcpy(dst, src, HASH_SIZE);
+        memcpy(mirror + HASH_SIZE, src + HASH_SIZE, HASH_SIZE);
+    }
+}
@@ -0,0 +1,18 @@
+#pragma once
+
+#include <stdint.h>
+#include <stdlib.h>
+
+#define NUM_SCOOPS 4096
+#define SCOOP_SIZE 64
+#define NONCE_SIZE (NUM_SCOOPS * SCOOP_SIZE)
+#define HASH_SIZE 32
+#define HASH_CAP 4096
+#define SEED_SIZE 16
+
+// write account id and nonce (big endian) behind the nonce data
+void write_seed(char* seed, uint64_t numeric_id, uint64_t nonce);
+
+// xor the nonce with its final hash and scatter the scoops into the cache in PoC2 layout
+void write_nonce(char* cache, uint64_t cache_size, uint64_t cache_offset, char* gendata,
+                 const char* final);
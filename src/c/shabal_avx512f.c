@@ -71,7 +71,7 @@ void find_best_deadline_avx512f(char *scoops, uint64_t nonce_count, char *gensig
         u2.words[i + 15 + 128] = *(mshabal_u32 *)(end + o);
     }
 
-    for (uint64_t i = 0; i < nonce_count; i += 8) {
+    for (uint64_t i = 0; i < nonce_count; i += 16) {
         // Inititialise Shabal
         memcpy(&x1, &x2,
                sizeof(x2));  // optimization: mshabal512_init(&x, 256);
@@ -0,0 +1,77 @@
+#include "shabal_neon.h"
+#include <arm_neon.h>
+#include <string.h>
+#include "common.h"
+#include "mshabal_128_neon.h"
+
+mshabal_context global_128_neon;
+mshabal_context_fast global_128_neon_fast;
+
+void init_shabal_neon() {
+    simd128_neon_mshabal_init(&global_128_neon, 256);
+    global_128_neon_fast.out_size = global_128_neon.out_size;
+    for (int i = 0; i < 176; i++) global_128_neon_fast.state[i] = global_128_neon.state[i];
+    global_128_neon_fast.Whigh = global_128_neon.Whigh;
+    global_128_neon_fast.Wlow = global_128_neon.Wlow;
+}
+
+void find_best_deadline_neon(char* scoops, uint64_t nonce_count, char* gensig,
+                             uint64_t* best_deadline, uint64_t* best_offset) {
+    uint64_t d0 = 0, d1 = 0, d2 = 0, d3 = 0;
+    char end[32];
+
+    end[0] = -128;
+    memset(&end[1], 0, 31);
+
+    mshabal_context_fast x1, x2;
+    memcpy(&x2, &global_128_neon_fast,
+           sizeof(global_128_neon_fast));  // local copy of global fast context
+
+    // prepare shabal inputs
+    union {
+        mshabal_u32 words[64];
+        uint32x4_t data[16];
+    } u1, u2;
+
+    for (int i = 0; i < 64 / 2; i += 4) {
+        size_t o = i;
+        u1.words[i + 0] = *(mshabal_u32*)(gensig + o);
+        u1.words[i + 1] = *(mshabal_u32*)(gensig + o);
+        u1.words[i + 2] = *(mshabal_u32*)(gensig + o);
+        u1.words[i + 3] = *(mshabal_u32*)(gensig + o);
+        u2.words[i + 0 + 32] = *(mshabal_u32*)(end + o);
+        u2.words[i + 1 + 32] = *(mshabal_u32*)(end + o);
+        u2.words[i + 2 + 32] = *(mshabal_u32*)(end + o);
+        u2.words[i + 3 + 32] = *(mshabal_u32*)(end + o);
+    }
+
+    for (uint64_t i = 0; i < nonce_count; i += 4) {
+        // initialise shabal
+        memcpy(&x1, &x2,
+               sizeof(x2));  // optimization: mshabal256_init(&x, 256);
+
+        // load and shuffle data
+        // NB: this can be further optimised by preshuffling plot files
+        // depending on SIMD length and use avx2 memcpy did not find a away yet
+        // to completely avoid memcpys
+
+        for (uint64_t j = 0; j < 64 / 2; j += 4) {
+            size_t o = j;
+            u1.words[j + 0 + 32] = *(mshabal_u32*)(&scoops[(i + 0) * 64] + o);
+            u1.words[j + 1 + 32] = *(mshabal_u32*)(&scoops[(i + 1) * 64] + o);
+            u1.words[j + 2 + 32] = *(mshabal_u32*)(&scoops[(i + 2) * 64] + o);
+            u1.words[j + 3 + 32] = *(mshabal_u32*)(&scoops[(i + 3) * 64] + o);
+            u2.words[j + 0] = *(mshabal_u32*)(&scoops[(i + 0) * 64 + 32] + o);
+            u2.words[j + 1] = *(mshabal_u32*)(&scoops[(i + 1) * 64 + 32] + o);
+            u2.words[j + 2] = *(mshabal_u32*)(&scoops[(i + 2) * 64 + 32] + o);
+            u2.words[j + 3] = *(mshabal_u32*)(&scoops[(i + 3) * 64 + 32] + o);
+        }
+
+        simd128_neon_mshabal_openclose_fast(&x1, &u1, &u2, &d0, &d1, &d2, &d3);
+
+        SET_BEST_DEADLINE(d0, i + 0);
+        SET_BEST_DEADLINE(d1, i + 1);
+        SET_BEST_DEADLINE(d2, i + 2);
+        SET_BEST_DEADLINE(d3, i + 3);
+    }
+}
@@ -1,18 +1,53 @@
 extern crate serde_yaml;
 extern crate sys_info;
 
-use std::collections::HashMap;
+use burstmath;
+use journal::Format as JournalFormat;
+use miner::{DeadlineVerification, SecretPhraseCheck, TargetDeadlineSource};
+use plot::POC2_START_HEIGHT;
+use pool;
+use secrets::Secrets;
+use serde::de::{self, Deserialize, Deserializer};
+use serde::{Serialize, Serializer};
+use serde_yaml::{Mapping, Value};
+use std::collections::{BTreeMap, HashMap};
+use std::env;
+use std::fmt;
 use std::fs;
+use std::net::SocketAddr;
+use std::str::FromStr;
 use std::u32;
+use url::Url;
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Cfg {
-    #[serde(default = "default_secret_phrase")]
+    #[serde(
+        default = "default_secret_phrase",
+        deserialize_with = "account_id_to_secret_phrase",
+        serialize_with = "sorted_map"
+    )]
     pub account_id_to_secret_phrase: HashMap<u64, String>,
 
+    #[serde(default = "default_secret_phrases")]
+    pub secret_phrases: Vec<String>,
+
+    #[serde(default = "default_secret_phrase_check")]
+    pub secret_phrase_check: String,
+
     pub plot_dirs: Vec<String>,
+
+    #[serde(default = "default_rescan_plot_dirs")]
+    pub rescan_plot_dirs: bool,
+
+    #[serde(default = "default_url")]
     pub url: String,
 
+    #[serde(default = "default_fallback_urls")]
+    pub fallback_urls: Vec<String>,
+
+    #[serde(default = "default_failover_after")]
+    pub failover_after: usize,
+
     #[serde(default = "default_hdd_reader_thread_count")]
     pub hdd_reader_thread_count: usize,
 
@@ -22,6 +57,12 @@ pub struct Cfg {
     #[serde(default = "default_hdd_wakeup_after")]
     pub hdd_wakeup_after: i64,
 
+    #[serde(default = "default_hdd_quarantine_after")]
+    pub hdd_quarantine_after: u32,
+
+    #[serde(default = "default_hdd_quarantine_backoff")]
+    pub hdd_quarantine_backoff: u64,
+
     #[serde(default = "default_cpu_worker_thread_count")]
     pub cpu_worker_thread_count: usize,
 
@@ -31,6 +72,9 @@ pub struct Cfg {
     #[serde(default = "default_cpu_thread_pinning")]
     pub cpu_thread_pinning: bool,
 
+    #[serde(default = "default_cpu_simd_extension")]
+    pub cpu_simd_extension: String,
+
     #[serde(default = "default_gpu_platform")]
     pub gpu_platform: usize,
 
@@ -49,9 +93,21 @@ pub struct Cfg {
     #[serde(default = "default_target_deadline")]
     pub target_deadline: u64,
 
+    #[serde(default = "default_target_deadline_source")]
+    pub target_deadline_source: String,
+
+    #[serde(default = "default_deadline_verification")]
+    pub deadline_verification: String,
+
     #[serde(default = "default_get_mining_info_interval")]
     pub get_mining_info_interval: u64,
 
+    #[serde(default = "default_mining_info_source")]
+    pub mining_info_source: String,
+
+    #[serde(default = "default_nonce_sink")]
+    pub nonce_sink: String,
+
     #[serde(default = "default_timeout")]
     pub timeout: u64,
 
@@ -73,6 +129,15 @@ pub struct Cfg {
     #[serde(default = "default_logfile_log_pattern")]
     pub logfile_log_pattern: String,
 
+    #[serde(default = "default_logfile_dir")]
+    pub logfile_dir: String,
+
+    #[serde(default = "default_logfile_format")]
+    pub logfile_format: String,
+
+    #[serde(default = "default_syslog_log_level")]
+    pub syslog_log_level: String,
+
     #[serde(default = "default_show_progress")]
     pub show_progress: bool,
 
@@ -81,12 +146,238 @@ pub struct Cfg {
 
     #[serde(default = "default_benchmark_only")]
     pub benchmark_only: String,
+
+    #[serde(default = "default_poc2_start_height")]
+    pub poc2_start_height: u64,
+
+    #[serde(default = "default_chains")]
+    pub chains: Vec<ChainCfg>,
+
+    #[serde(default = "default_api_address")]
+    pub api_address: String,
+
+    #[serde(default = "default_journal_file")]
+    pub journal_file: String,
+
+    #[serde(default = "default_journal_format")]
+    pub journal_format: String,
+}
+
+// a chain sharing the plot format, replaces the top level pool settings if configured
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ChainCfg {
+    pub name: String,
+    pub url: String,
+
+    #[serde(default = "default_fallback_urls")]
+    pub fallback_urls: Vec<String>,
+
+    #[serde(
+        default = "default_secret_phrase",
+        deserialize_with = "account_id_to_secret_phrase",
+        serialize_with = "sorted_map"
+    )]
+    pub account_id_to_secret_phrase: HashMap<u64, String>,
+
+    #[serde(default = "default_secret_phrases")]
+    pub secret_phrases: Vec<String>,
+
+    #[serde(default = "default_accounts", deserialize_with = "account_ids")]
+    pub accounts: Vec<u64>,
+
+    #[serde(default = "default_target_deadline")]
+    pub target_deadline: u64,
+
+    #[serde(default = "default_target_deadline_source")]
+    pub target_deadline_source: String,
+
+    #[serde(default = "default_poc2_start_height")]
+    pub poc2_start_height: u64,
+
+    #[serde(default = "default_priority")]
+    pub priority: u8,
+
+    #[serde(default = "default_mining_info_source")]
+    pub mining_info_source: String,
+
+    #[serde(default = "default_nonce_sink")]
+    pub nonce_sink: String,
+}
+
+impl Cfg {
+    // the account ids of secret_phrases are derived and merged into account_id_to_secret_phrase
+    pub fn chains(&self) -> Vec<ChainCfg> {
+        if !self.chains.is_empty() {
+            return self
+                .chains
+                .iter()
+                .map(|chain| ChainCfg {
+                    account_id_to_secret_phrase: with_derived_account_ids(
+                        &chain.account_id_to_secret_phrase,
+                        &chain.secret_phrases,
+                    ),
+                    ..chain.clone()
+                })
+                .collect();
+        }
+        vec![ChainCfg {
+            name: "default".to_owned(),
+            url: self.url.clone(),
+            fallback_urls: self.fallback_urls.clone(),
+            account_id_to_secret_phrase: with_derived_account_ids(
+                &self.account_id_to_secret_phrase,
+                &self.secret_phrases,
+            ),
+            secret_phrases: self.secret_phrases.clone(),
+            accounts: default_accounts(),
+            target_deadline: self.target_deadline,
+            target_deadline_source: self.target_deadline_source.clone(),
+            poc2_start_height: self.poc2_start_height,
+            priority: default_priority(),
+            mining_info_source: self.mining_info_source.clone(),
+            nonce_sink: self.nonce_sink.clone(),
+        }]
+    }
+}
+
+impl Cfg {
+    // the settings the chains are built from are taken from other, e.g. a running config
+    pub fn keep_chain_settings(&mut self, other: &Cfg) {
+        self.account_id_to_secret_phrase = other.account_id_to_secret_phrase.clone();
+        self.secret_phrases = other.secret_phrases.clone();
+        self.secret_phrase_check = other.secret_phrase_check.clone();
+        self.url = other.url.clone();
+        self.fallback_urls = other.fallback_urls.clone();
+        self.failover_after = other.failover_after;
+        self.timeout = other.timeout;
+        self.target_deadline = other.target_deadline;
+        self.target_deadline_source = other.target_deadline_source.clone();
+        self.poc2_start_height = other.poc2_start_height;
+        self.mining_info_source = other.mining_info_source.clone();
+        self.nonce_sink = other.nonce_sink.clone();
+        self.chains = other.chains.clone();
+    }
+}
+
+const MASKED: &str = "<secret>";
+
+impl Cfg {
+    fn settings(&self) -> Value {
+        // serde_yaml::to_value turns u64s into floats, the text keeps them exact
+        serde_yaml::from_str(&serde_yaml::to_string(self).unwrap()).unwrap()
+    }
+
+    // names of the top level settings that differ
+    pub fn changed_settings(&self, other: &Cfg) -> Vec<String> {
+        match (self.settings(), other.settings()) {
+            (Value::Mapping(settings), Value::Mapping(other)) => settings
+                .iter()
+                .filter(|&(name, value)| other.get(name) != Some(value))
+                .filter_map(|(name, _)| name.as_str().map(|name| name.to_owned()))
+                .collect(),
+            _ => unreachable!(),
+        }
+    }
+
+    // the effective config as yaml, secret phrases are masked
+    pub fn to_yaml(&self) -> String {
+        let mut settings = self.settings();
+        mask_secret_phrases(&mut settings);
+        if let Value::Mapping(ref mut settings) = settings {
+            if let Some(&mut Value::Sequence(ref mut chains)) =
+                settings.get_mut(&Value::String("chains".to_owned()))
+            {
+                for chain in chains.iter_mut() {
+                    mask_secret_phrases(chain);
+                }
+            }
+        }
+        serde_yaml::to_string(&settings).unwrap()
+    }
+}
+
+fn mask_secret_phrases(settings: &mut Value) {
+    let settings = match *settings {
+        Value::Mapping(ref mut settings) => settings,
+        _ => return,
+    };
+    if let Some(&mut Value::Mapping(ref mut secret_phrases)) =
+        settings.get_mut(&Value::String("account_id_to_secret_phrase".to_owned()))
+    {
+        for (_, secret_phrase) in secret_phrases.iter_mut() {
+            *secret_phrase = Value::String(MASKED.to_owned());
+        }
+    }
+    if let Some(&mut Value::Sequence(ref mut secret_phrases)) =
+        settings.get_mut(&Value::String("secret_phrases".to_owned()))
+    {
+        for secret_phrase in secret_phrases.iter_mut() {
+            *secret_phrase = Value::String(MASKED.to_owned());
+        }
+    }
+}
+
+impl ChainCfg {
+    // (configured account id, account id the passphrase belongs to) of every wrong passphrase
+    pub fn secret_phrase_mismatches(&self) -> Vec<(u64, u64)> {
+        let mut mismatches: Vec<(u64, u64)> = self
+            .account_id_to_secret_phrase
+            .iter()
+            .map(|(&account_id, secret_phrase)| {
+                (
+                    account_id,
+                    burstmath::account_id_from_secret_phrase(secret_phrase),
+                )
+            })
+            .filter(|&(account_id, derived)| account_id != derived)
+            .collect();
+        mismatches.sort();
+        mismatches
+    }
+}
+
+fn with_derived_account_ids(
+    account_id_to_secret_phrase: &HashMap<u64, String>,
+    secret_phrases: &[String],
+) -> HashMap<u64, String> {
+    let mut account_id_to_secret_phrase = account_id_to_secret_phrase.clone();
+    for secret_phrase in secret_phrases {
+        account_id_to_secret_phrase.insert(
+            burstmath::account_id_from_secret_phrase(secret_phrase),
+            secret_phrase.clone(),
+        );
+    }
+    account_id_to_secret_phrase
 }
 
 fn default_secret_phrase() -> HashMap<u64, String> {
     HashMap::new()
 }
 
+fn default_secret_phrases() -> Vec<String> {
+    Vec::new()
+}
+
+fn default_secret_phrase_check() -> String {
+    "refuse".to_owned()
+}
+
+fn default_rescan_plot_dirs() -> bool {
+    false
+}
+
+fn default_url() -> String {
+    "".to_owned()
+}
+
+fn default_fallback_urls() -> Vec<String> {
+    Vec::new()
+}
+
+fn default_failover_after() -> usize {
+    3
+}
+
 fn default_hdd_reader_thread_count() -> usize {
     0
 }
@@ -99,6 +390,14 @@ fn default_hdd_wakeup_after() -> i64 {
     240
 }
 
+fn default_hdd_quarantine_after() -> u32 {
+    3
+}
+
+fn default_hdd_quarantine_backoff() -> u64 {
+    60
+}
+
 fn default_cpu_worker_thread_count() -> usize {
     0
 }
@@ -111,6 +410,10 @@ fn default_cpu_thread_pinning() -> bool {
     false
 }
 
+fn default_cpu_simd_extension() -> String {
+    "auto".to_owned()
+}
+
 fn default_gpu_platform() -> usize {
     0
 }
@@ -135,6 +438,14 @@ fn default_target_deadline() -> u64 {
     u64::from(u32::MAX)
 }
 
+fn default_target_deadline_source() -> String {
+    "min".to_owned()
+}
+
+fn default_deadline_verification() -> String {
+    "log".to_owned()
+}
+
 fn default_get_mining_info_interval() -> u64 {
     3000
 }
@@ -167,6 +478,18 @@ fn default_logfile_log_pattern() -> String {
     "\r{d(%Y-%m-%dT%H:%M:%S.%3f%z)} [{h({l}):<5}] [{T}] [{f}:{L}] [{t}] - {M}:{m}{n}".to_owned()
 }
 
+fn default_logfile_dir() -> String {
+    "log".to_owned()
+}
+
+fn default_logfile_format() -> String {
+    "pattern".to_owned()
+}
+
+fn default_syslog_log_level() -> String {
+    "Off".to_owned()
+}
+
 fn default_show_progress() -> bool {
     true
 }
@@ -179,16 +502,591 @@ fn default_benchmark_only() -> String {
     "disabled".to_owned()
 }
 
-pub fn load_cfg(config: &str) -> Cfg {
-    let cfg_str = fs::read_to_string(config).expect("failed to open config");
-    let cfg: Cfg = serde_yaml::from_str(&cfg_str).expect("failed to parse config");
-    if cfg.hdd_use_direct_io {
-        assert!(
-            cfg.cpu_nonces_per_cache % 8 == 0 && cfg.gpu_nonces_per_cache % 8 == 0,
-            "nonces_per_cache must be devisable by 8 when using direct io"
+fn default_poc2_start_height() -> u64 {
+    POC2_START_HEIGHT
+}
+
+fn default_chains() -> Vec<ChainCfg> {
+    Vec::new()
+}
+
+fn default_accounts() -> Vec<u64> {
+    Vec::new()
+}
+
+fn default_priority() -> u8 {
+    0
+}
+
+fn default_api_address() -> String {
+    "".to_owned()
+}
+
+fn default_journal_file() -> String {
+    "".to_owned()
+}
+
+fn default_journal_format() -> String {
+    "json".to_owned()
+}
+
+fn default_mining_info_source() -> String {
+    "http".to_owned()
+}
+
+fn default_nonce_sink() -> String {
+    "http".to_owned()
+}
+
+// a numeric account id or a BURST-XXXX-XXXX-XXXX-XXXXX address
+#[derive(Eq, Hash, PartialEq)]
+struct AccountId(u64);
+
+impl<'de> Deserialize<'de> for AccountId {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<AccountId, D::Error> {
+        struct AccountIdVisitor;
+
+        impl<'de> de::Visitor<'de> for AccountIdVisitor {
+            type Value = AccountId;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("numeric account id or address")
+            }
+
+            fn visit_str<E: de::Error>(self, v: &str) -> Result<Self::Value, E> {
+                burstmath::parse_account_id(v)
+                    .map(AccountId)
+                    .map_err(de::Error::custom)
+            }
+
+            fn visit_u64<E>(self, v: u64) -> Result<Self::Value, E> {
+                Ok(AccountId(v))
+            }
+        }
+
+        deserializer.deserialize_any(AccountIdVisitor)
+    }
+}
+
+fn account_id_to_secret_phrase<'de, D>(deserializer: D) -> Result<HashMap<u64, String>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let secret_phrases: HashMap<AccountId, String> = HashMap::deserialize(deserializer)?;
+    Ok(secret_phrases
+        .into_iter()
+        .map(|(account_id, secret_phrase)| (account_id.0, secret_phrase))
+        .collect())
+}
+
+// hash maps serialize in random order, settings are compared and printed sorted
+fn sorted_map<S>(map: &HashMap<u64, String>, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    map.iter().collect::<BTreeMap<_, _>>().serialize(serializer)
+}
+
+fn account_ids<'de, D>(deserializer: D) -> Result<Vec<u64>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let account_ids: Vec<AccountId> = Vec::deserialize(deserializer)?;
+    Ok(account_ids
+        .into_iter()
+        .map(|account_id| account_id.0)
+        .collect())
+}
+
+// a setting the miner can't work with, field is its path in the config, e.g. chains[1].url
+#[derive(Debug, PartialEq)]
+pub struct ConfigError {
+    pub field: String,
+    pub reason: String,
+}
+
+impl ConfigError {
+    fn new(field: &str, reason: &str) -> ConfigError {
+        ConfigError {
+            field: field.to_owned(),
+            reason: reason.to_owned(),
+        }
+    }
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.field.is_empty() {
+            write!(f, "{}", self.reason)
+        } else {
+            write!(f, "{}: {}", self.field, self.reason)
+        }
+    }
+}
+
+const LOG_LEVELS: &[&str] = &["off", "error", "warn", "info", "debug", "trace"];
+
+impl Cfg {
+    // every setting the miner can't work with, empty if the config is fine
+    pub fn validate(&self) -> Vec<ConfigError> {
+        let mut errors = Vec::new();
+
+        if self.url.is_empty() && self.chains.is_empty() {
+            errors.push(ConfigError::new(
+                "url",
+                "either url or chains must be configured",
+            ));
+        }
+        let refuse_wrong_secret_phrases = self.secret_phrase_check.to_lowercase() == "refuse";
+        if self.chains.is_empty() {
+            validate_chain(
+                &mut errors,
+                "",
+                &self.chains()[0],
+                refuse_wrong_secret_phrases,
+            );
+        }
+        for (i, chain) in self.chains.iter().enumerate() {
+            let prefix = format!("chains[{}].", i);
+            if chain.name.is_empty() {
+                errors.push(ConfigError::new(&(prefix.clone() + "name"), "is empty"));
+            } else if self.chains[..i].iter().any(|c| c.name == chain.name) {
+                errors.push(ConfigError::new(
+                    &(prefix.clone() + "name"),
+                    &format!("{} is used by another chain", chain.name),
+                ));
+            }
+            validate_chain(&mut errors, &prefix, chain, refuse_wrong_secret_phrases);
+        }
+
+        for (i, plot_dir) in self.plot_dirs.iter().enumerate() {
+            let field = format!("plot_dirs[{}]", i);
+            match fs::metadata(plot_dir) {
+                Ok(ref metadata) if metadata.is_dir() => {}
+                Ok(_) => errors.push(ConfigError::new(
+                    &field,
+                    &format!("{} is not a directory", plot_dir),
+                )),
+                Err(e) => errors.push(ConfigError::new(&field, &format!("{}: {}", plot_dir, e))),
+            }
+        }
+
+        if self.cpu_worker_thread_count == 0 && self.gpu_worker_thread_count == 0 {
+            errors.push(ConfigError::new(
+                "cpu_worker_thread_count",
+                "no CPU and no GPU workers, nothing would be hashed",
+            ));
+        }
+        if self.gpu_worker_thread_count > 0 && !cfg!(feature = "opencl") {
+            errors.push(ConfigError::new(
+                "gpu_worker_thread_count",
+                "GPU workers need a build with the opencl feature",
+            ));
+        }
+        for &(field, worker_thread_count, nonces_per_cache) in &[
+            (
+                "cpu_nonces_per_cache",
+                self.cpu_worker_thread_count,
+                self.cpu_nonces_per_cache,
+            ),
+            (
+                "gpu_nonces_per_cache",
+                self.gpu_worker_thread_count,
+                self.gpu_nonces_per_cache,
+            ),
+        ] {
+            if worker_thread_count > 0 && nonces_per_cache == 0 {
+                errors.push(ConfigError::new(field, "must be greater than 0"));
+            } else if self.hdd_use_direct_io && nonces_per_cache % 8 != 0 {
+                errors.push(ConfigError::new(
+                    field,
+                    "must be divisible by 8 when using direct io",
+                ));
+            }
+        }
+
+        for &(field, interval) in &[
+            ("get_mining_info_interval", self.get_mining_info_interval),
+            ("timeout", self.timeout),
+        ] {
+            if interval == 0 {
+                errors.push(ConfigError::new(field, "must be greater than 0"));
+            }
+        }
+
+        validate_one_of(
+            &mut errors,
+            "console_log_level",
+            &self.console_log_level,
+            LOG_LEVELS,
+        );
+        validate_one_of(
+            &mut errors,
+            "logfile_log_level",
+            &self.logfile_log_level,
+            LOG_LEVELS,
         );
+        validate_one_of(
+            &mut errors,
+            "logfile_format",
+            &self.logfile_format,
+            &["pattern", "json"],
+        );
+        validate_one_of(
+            &mut errors,
+            "syslog_log_level",
+            &self.syslog_log_level,
+            LOG_LEVELS,
+        );
+        if !cfg!(unix) && self.syslog_log_level.to_lowercase() != "off" {
+            errors.push(ConfigError::new(
+                "syslog_log_level",
+                "syslog is only available on unix",
+            ));
+        }
+        validate_one_of(
+            &mut errors,
+            "cpu_simd_extension",
+            &self.cpu_simd_extension,
+            &["auto", "portable"],
+        );
+        validate_one_of(
+            &mut errors,
+            "benchmark_only",
+            &self.benchmark_only,
+            &["disabled", "i/o", "xpu"],
+        );
+        validate_option::<DeadlineVerification>(
+            &mut errors,
+            "deadline_verification",
+            &self.deadline_verification,
+        );
+        validate_option::<SecretPhraseCheck>(
+            &mut errors,
+            "secret_phrase_check",
+            &self.secret_phrase_check,
+        );
+
+        validate_option::<JournalFormat>(&mut errors, "journal_format", &self.journal_format);
+
+        if !self.api_address.is_empty() && self.api_address.parse::<SocketAddr>().is_err() {
+            errors.push(ConfigError::new(
+                "api_address",
+                &format!("{} is not an ip:port address", self.api_address),
+            ));
+        }
+
+        errors
+    }
+}
+
+// the settings of a chain, prefix is empty for the top level one
+fn validate_chain(
+    errors: &mut Vec<ConfigError>,
+    prefix: &str,
+    chain: &ChainCfg,
+    refuse_wrong_secret_phrases: bool,
+) {
+    let field = |name: &str| prefix.to_owned() + name;
+    if chain.url.is_empty() {
+        // the top level one is covered by the url or chains check
+        if !prefix.is_empty() && (chain.mining_info_source == "http" || chain.nonce_sink == "http")
+        {
+            errors.push(ConfigError::new(
+                &field("url"),
+                "is required by the http mining_info_source and nonce_sink",
+            ));
+        }
+    } else {
+        validate_url(errors, &field("url"), &chain.url);
+    }
+    for (i, url) in chain.fallback_urls.iter().enumerate() {
+        validate_url(errors, &format!("{}[{}]", field("fallback_urls"), i), url);
+    }
+    if !pool::is_valid_source(&chain.mining_info_source) {
+        errors.push(ConfigError::new(
+            &field("mining_info_source"),
+            &format!(
+                "unknown source {}, use http or replay:<file>",
+                chain.mining_info_source
+            ),
+        ));
+    }
+    if !pool::is_valid_sink(&chain.nonce_sink) {
+        errors.push(ConfigError::new(
+            &field("nonce_sink"),
+            &format!("unknown sink {}, use http or mock", chain.nonce_sink),
+        ));
+    }
+    validate_option::<TargetDeadlineSource>(
+        errors,
+        &field("target_deadline_source"),
+        &chain.target_deadline_source,
+    );
+    if refuse_wrong_secret_phrases {
+        for (account_id, derived) in chain.secret_phrase_mismatches() {
+            errors.push(ConfigError::new(
+                &format!("{}.{}", field("account_id_to_secret_phrase"), account_id),
+                &format!(
+                    "the secret phrase belongs to {}, not {}",
+                    burstmath::to_rs_address(derived),
+                    burstmath::to_rs_address(account_id)
+                ),
+            ));
+        }
+    }
+}
+
+fn validate_url(errors: &mut Vec<ConfigError>, field: &str, url: &str) {
+    match Url::parse(url) {
+        Ok(ref url) if url.scheme() == "http" || url.scheme() == "https" => {}
+        Ok(url) => errors.push(ConfigError::new(
+            field,
+            &format!("unsupported scheme {}, use http or https", url.scheme()),
+        )),
+        Err(e) => errors.push(ConfigError::new(
+            field,
+            &format!("{} is not a url: {}", url, e),
+        )),
+    }
+}
+
+// the string options are case insensitive
+pub fn parse_option<T: Copy>(value: &str, options: &[(&str, T)]) -> Result<T, String> {
+    let lowercase = value.to_lowercase();
+    match options.iter().find(|&&(name, _)| name == lowercase) {
+        Some(&(_, option)) => Ok(option),
+        None => {
+            let names: Vec<&str> = options.iter().map(|&(name, _)| name).collect();
+            Err(format!(
+                "unknown value {}, options: {}",
+                value,
+                names.join(", ")
+            ))
+        }
+    }
+}
+
+fn validate_one_of(errors: &mut Vec<ConfigError>, field: &str, value: &str, options: &[&str]) {
+    let options: Vec<(&str, ())> = options.iter().map(|&name| (name, ())).collect();
+    if let Err(e) = parse_option(value, &options) {
+        errors.push(ConfigError::new(field, &e));
+    }
+}
+
+fn validate_option<T: FromStr<Err = String>>(
+    errors: &mut Vec<ConfigError>,
+    field: &str,
+    value: &str,
+) {
+    if let Err(e) = value.parse::<T>() {
+        errors.push(ConfigError::new(field, &e));
+    }
+}
+
+// replaces env:, file: and keystore: references by the secret phrases themselves
+fn resolve_secret_phrases(cfg: &mut Cfg, secrets: &mut Secrets) -> Vec<ConfigError> {
+    let mut errors = resolve_account_secrets(
+        secrets,
+        "",
+        &mut cfg.account_id_to_secret_phrase,
+        &mut cfg.secret_phrases,
+    );
+    for (i, chain) in cfg.chains.iter_mut().enumerate() {
+        errors.extend(resolve_account_secrets(
+            secrets,
+            &format!("chains[{}].", i),
+            &mut chain.account_id_to_secret_phrase,
+            &mut chain.secret_phrases,
+        ));
+    }
+    errors
+}
+
+fn resolve_account_secrets(
+    secrets: &mut Secrets,
+    prefix: &str,
+    account_id_to_secret_phrase: &mut HashMap<u64, String>,
+    secret_phrases: &mut Vec<String>,
+) -> Vec<ConfigError> {
+    let mut errors = Vec::new();
+    for (&account_id, secret_phrase) in account_id_to_secret_phrase.iter_mut() {
+        match secrets.secret_phrase(account_id, secret_phrase) {
+            Ok(resolved) => *secret_phrase = resolved,
+            Err(e) => errors.push(ConfigError::new(
+                &format!("{}account_id_to_secret_phrase.{}", prefix, account_id),
+                &e,
+            )),
+        }
+    }
+    let mut resolved = Vec::new();
+    for (i, value) in secret_phrases.iter().enumerate() {
+        match secrets.secret_phrases(value) {
+            Ok(secret_phrases) => resolved.extend(secret_phrases),
+            Err(e) => errors.push(ConfigError::new(
+                &format!("{}secret_phrases[{}]", prefix, i),
+                &e,
+            )),
+        }
+    }
+    *secret_phrases = resolved;
+    errors
+}
+
+// overrides a setting, e.g. SCAVENGER_TIMEOUT=10000
+pub const ENV_PREFIX: &str = "SCAVENGER_";
+
+// (old name, new name) of renamed settings, config files may still use the old one
+const RENAMED_SETTINGS: &[(&str, &str)] = &[("metrics_address", "api_address")];
+
+// the new name wins if a file has both
+fn rename_settings(settings: &mut Mapping) {
+    for &(old, new) in RENAMED_SETTINGS {
+        if let Some(value) = settings.remove(&Value::String(old.to_owned())) {
+            let new = Value::String(new.to_owned());
+            if !settings.contains_key(&new) {
+                settings.insert(new, value);
+            }
+        }
+    }
+}
+
+// every setting with its default value, only the kind of value counts
+fn default_settings() -> Mapping {
+    let cfg: Cfg = serde_yaml::from_str("plot_dirs: []").unwrap();
+    match serde_yaml::to_value(&cfg).unwrap() {
+        Value::Mapping(settings) => settings,
+        _ => unreachable!(),
+    }
+}
+
+// string settings take the value as is, all others are parsed as yaml, e.g. plot_dirs=[a, b]
+fn override_value(defaults: &Mapping, setting: &str, value: &str) -> Result<Value, String> {
+    match defaults.get(&Value::String(setting.to_owned())) {
+        None => Err("unknown setting".to_owned()),
+        Some(Value::String(_)) => Ok(Value::String(value.to_owned())),
+        Some(_) => serde_yaml::from_str(value).map_err(|e| format!("{}: {}", value, e)),
+    }
+}
+
+// applies SCAVENGER_* variables, then key=value overrides on top of the settings of the file
+fn apply_overrides(
+    settings: &mut Mapping,
+    env_vars: &[(String, String)],
+    overrides: &[String],
+) -> Vec<ConfigError> {
+    let defaults = default_settings();
+    let mut errors = Vec::new();
+    for (name, value) in env_vars {
+        if !name.starts_with(ENV_PREFIX) {
+            continue;
+        }
+        let setting = name[ENV_PREFIX.len()..].to_lowercase();
+        // other SCAVENGER_ variables, e.g. the keystore passphrase
+        if !defaults.contains_key(&Value::String(setting.clone())) {
+            continue;
+        }
+        match override_value(&defaults, &setting, value) {
+            Ok(value) => {
+                settings.insert(Value::String(setting), value);
+            }
+            Err(e) => errors.push(ConfigError::new(&setting, &format!("{}: {}", name, e))),
+        }
+    }
+    for assignment in overrides {
+        let mut parts = assignment.splitn(2, '=');
+        let setting = parts.next().unwrap().trim();
+        let value = match parts.next() {
+            Some(value) => value,
+            None => {
+                errors.push(ConfigError::new(setting, "--set needs key=value"));
+                continue;
+            }
+        };
+        match override_value(&defaults, setting, value) {
+            Ok(value) => {
+                settings.insert(Value::String(setting.to_owned()), value);
+            }
+            Err(e) => errors.push(ConfigError::new(setting, &format!("--set: {}", e))),
+        }
+    }
+    errors
+}
+
+// the config file with the SCAVENGER_* environment variables applied
+pub fn load_cfg(config: &str) -> Result<Cfg, Vec<ConfigError>> {
+    load_cfg_with_overrides(config, &[])
+}
+
+// settings come from the overrides (key=value), then the environment, then the file, then the
+// defaults; the errors of all settings are collected
+pub fn load_cfg_with_overrides(
+    config: &str,
+    overrides: &[String],
+) -> Result<Cfg, Vec<ConfigError>> {
+    load_cfg_with_secrets(config, overrides, &mut Secrets::new())
+}
+
+// keystores unlocked by an earlier load with the same secrets are reused
+pub fn load_cfg_with_secrets(
+    config: &str,
+    overrides: &[String],
+    secrets: &mut Secrets,
+) -> Result<Cfg, Vec<ConfigError>> {
+    // env::vars panics on variables that aren't unicode, those can't be settings anyway
+    let env_vars: Vec<(String, String)> = env::vars_os()
+        .filter_map(|(name, value)| Some((name.into_string().ok()?, value.into_string().ok()?)))
+        .filter(|(name, _)| name.starts_with(ENV_PREFIX))
+        .collect();
+    load_merged_cfg(config, &env_vars, overrides, secrets)
+}
+
+fn load_merged_cfg(
+    config: &str,
+    env_vars: &[(String, String)],
+    overrides: &[String],
+    secrets: &mut Secrets,
+) -> Result<Cfg, Vec<ConfigError>> {
+    let cfg_str = fs::read_to_string(config).map_err(|e| {
+        vec![ConfigError::new(
+            "",
+            &format!("failed to open {}: {}", config, e),
+        )]
+    })?;
+    let mut settings = match serde_yaml::from_str(&cfg_str) {
+        Ok(Value::Mapping(settings)) => settings,
+        Ok(Value::Null) => Mapping::new(),
+        Ok(_) => {
+            return Err(vec![ConfigError::new(
+                "",
+                &format!("failed to parse {}: not a mapping of settings", config),
+            )])
+        }
+        Err(e) => {
+            return Err(vec![ConfigError::new(
+                "",
+                &format!("failed to parse {}: {}", config, e),
+            )])
+        }
+    };
+    rename_settings(&mut settings);
+    let errors = apply_overrides(&mut settings, env_vars, overrides);
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+    let mut cfg: Cfg = serde_yaml::from_value(Value::Mapping(settings)).map_err(|e| {
+        vec![ConfigError::new(
+            "",
+            &format!("failed to parse {} and its overrides: {}", config, e),
+        )]
+    })?;
+    let mut errors = resolve_secret_phrases(&mut cfg, secrets);
+    errors.extend(cfg.validate());
+    if errors.is_empty() {
+        Ok(cfg)
+    } else {
+        Err(errors)
     }
-    cfg
 }
 
 #[cfg(test)]
@@ -197,8 +1095,295 @@ mod tests {
 
     #[test]
     fn test_load_cfg() {
-        let cfg = load_cfg("config.yaml");
+        let cfg = load_cfg("config.yaml").unwrap();
         assert_eq!(cfg.timeout, 5000);
         assert_eq!(cfg.plot_dirs, vec!["test_data"]);
     }
+
+    #[test]
+    fn test_validate() {
+        assert!(load_cfg("config.yaml").unwrap().validate().is_empty());
+        assert!(load_cfg("nonexistent.yaml").is_err());
+
+        let cfg: Cfg = serde_yaml::from_str(
+            "plot_dirs: ['test_data', 'nonexistent']
+url: 'ftp://pool'
+fallback_urls: ['no url']
+cpu_worker_thread_count: 0
+cpu_nonces_per_cache: 65535
+timeout: 0
+console_log_level: 'loud'
+logfile_log_level: 'WARN'
+logfile_format: 'xml'
+target_deadline_source: 'max'
+api_address: 'localhost'
+chains:
+  - name: 'a'
+    url: 'http://a'
+  - name: 'a'
+    url: ''
+    nonce_sink: 'file'",
+        )
+        .unwrap();
+        let errors = cfg.validate();
+        let fields: Vec<&str> = errors.iter().map(|e| e.field.as_str()).collect();
+        assert_eq!(
+            fields,
+            vec![
+                "chains[1].name",
+                "chains[1].url",
+                "chains[1].nonce_sink",
+                "plot_dirs[1]",
+                "cpu_worker_thread_count",
+                "cpu_nonces_per_cache",
+                "timeout",
+                "console_log_level",
+                "logfile_format",
+                "api_address",
+            ]
+        );
+        assert_eq!(
+            errors[2].to_string(),
+            "chains[1].nonce_sink: unknown sink file, use http or mock"
+        );
+
+        // the top level pool settings count without chains
+        let cfg: Cfg = serde_yaml::from_str(
+            "plot_dirs: []
+url: 'ftp://pool'
+fallback_urls: ['no url']
+cpu_worker_thread_count: 1
+target_deadline_source: 'max'",
+        )
+        .unwrap();
+        let fields: Vec<String> = cfg.validate().into_iter().map(|e| e.field).collect();
+        assert_eq!(
+            fields,
+            vec!["url", "fallback_urls[0]", "target_deadline_source"]
+        );
+    }
+
+    #[test]
+    fn test_overrides() {
+        let env_vars = vec![
+            ("SCAVENGER_TIMEOUT".to_owned(), "7000".to_owned()),
+            ("SCAVENGER_URL".to_owned(), "http://env".to_owned()),
+            (
+                "SCAVENGER_KEYSTORE_PASSPHRASE".to_owned(),
+                "ignored".to_owned(),
+            ),
+            ("HOME".to_owned(), "/root".to_owned()),
+        ];
+        let overrides = vec![
+            "url=http://cli".to_owned(),
+            "plot_dirs=[test_data, .]".to_owned(),
+            "console_log_level=off".to_owned(),
+        ];
+        let cfg =
+            load_merged_cfg("config.yaml", &env_vars, &overrides, &mut Secrets::new()).unwrap();
+        // the command line wins over the environment, which wins over the file
+        assert_eq!(cfg.url, "http://cli");
+        assert_eq!(cfg.timeout, 7000);
+        assert_eq!(cfg.plot_dirs, vec!["test_data", "."]);
+        assert_eq!(cfg.console_log_level, "off");
+        assert_eq!(cfg.get_mining_info_interval, 3000);
+
+        let errors = load_merged_cfg(
+            "config.yaml",
+            &[("SCAVENGER_TIMEOUT".to_owned(), "[".to_owned())],
+            &["no_such_setting=1".to_owned(), "timeout".to_owned()],
+            &mut Secrets::new(),
+        )
+        .err()
+        .unwrap();
+        let fields: Vec<&str> = errors.iter().map(|e| e.field.as_str()).collect();
+        assert_eq!(fields, vec!["timeout", "no_such_setting", "timeout"]);
+    }
+
+    #[test]
+    fn test_rename_settings() {
+        let mut settings: Mapping =
+            serde_yaml::from_str("plot_dirs: []\nmetrics_address: '127.0.0.1:9090'").unwrap();
+        rename_settings(&mut settings);
+        let cfg: Cfg = serde_yaml::from_value(Value::Mapping(settings)).unwrap();
+        assert_eq!(cfg.api_address, "127.0.0.1:9090");
+
+        let mut settings: Mapping = serde_yaml::from_str(
+            "metrics_address: '127.0.0.1:9090'\napi_address: '127.0.0.1:8080'",
+        )
+        .unwrap();
+        rename_settings(&mut settings);
+        assert_eq!(settings.len(), 1);
+        assert_eq!(
+            settings[&Value::String("api_address".to_owned())],
+            Value::String("127.0.0.1:8080".to_owned())
+        );
+    }
+
+    #[test]
+    fn test_changed_settings() {
+        let cfg = load_cfg("config.yaml").unwrap();
+        let overrides = vec![
+            "target_deadline=1000".to_owned(),
+            "account_id_to_secret_phrase={10282355196851764065: 'glad suffer red during single \
+             glow shut slam hill death lust although'}"
+                .to_owned(),
+        ];
+        let changed = load_merged_cfg("config.yaml", &[], &overrides, &mut Secrets::new()).unwrap();
+        assert!(cfg
+            .changed_settings(&load_cfg("config.yaml").unwrap())
+            .is_empty());
+        assert_eq!(
+            cfg.changed_settings(&changed),
+            vec!["account_id_to_secret_phrase", "target_deadline"]
+        );
+    }
+
+    #[test]
+    fn test_to_yaml() {
+        let cfg = load_cfg("config.yaml").unwrap();
+        let yaml = cfg.to_yaml();
+        assert!(!yaml.contains("glad suffer red"));
+        assert!(yaml.contains(MASKED));
+        let printed: Cfg = serde_yaml::from_str(&yaml).unwrap();
+        assert_eq!(printed.url, cfg.url);
+        assert!(printed
+            .account_id_to_secret_phrase
+            .contains_key(&10_282_355_196_851_764_065));
+    }
+
+    #[test]
+    fn test_chains() {
+        let cfg: Cfg = serde_yaml::from_str(
+            "plot_dirs: []
+url: 'http://pool'
+target_deadline: 1000
+chains:
+  - name: 'a'
+    url: 'http://a'
+  - name: 'b'
+    url: 'http://b'
+    accounts: [1, 2]
+    priority: 1
+    mining_info_source: 'replay:-'",
+        ).unwrap();
+        let chains = cfg.chains();
+        assert_eq!(chains.len(), 2);
+        assert_eq!(chains[0].url, "http://a");
+        assert_eq!(chains[0].target_deadline, u64::from(u32::MAX));
+        assert_eq!(chains[1].accounts, vec![1, 2]);
+        assert_eq!(chains[1].priority, 1);
+        assert_eq!(chains[0].mining_info_source, "http");
+        assert_eq!(chains[1].mining_info_source, "replay:-");
+        assert_eq!(chains[1].nonce_sink, "http");
+
+        let cfg: Cfg =
+            serde_yaml::from_str("plot_dirs: []\nurl: 'http://pool'\ntarget_deadline: 1000")
+                .unwrap();
+        let chains = cfg.chains();
+        assert_eq!(chains.len(), 1);
+        assert_eq!(chains[0].url, "http://pool");
+        assert_eq!(chains[0].target_deadline, 1000);
+    }
+
+    #[test]
+    fn test_account_ids() {
+        let address = burstmath::to_rs_address(10_282_355_196_851_764_065);
+        let cfg: Cfg = serde_yaml::from_str(&format!(
+            "plot_dirs: []
+url: 'http://pool'
+account_id_to_secret_phrase:
+  {}: 'a'
+  1796535821016683299: 'b'
+chains:
+  - name: 'a'
+    url: 'http://a'
+    accounts: ['{}', 42]",
+            address, address
+        ))
+        .unwrap();
+        assert_eq!(cfg.account_id_to_secret_phrase.len(), 2);
+        assert_eq!(
+            cfg.account_id_to_secret_phrase[&10_282_355_196_851_764_065],
+            "a"
+        );
+        assert_eq!(
+            cfg.account_id_to_secret_phrase[&1_796_535_821_016_683_299],
+            "b"
+        );
+        assert_eq!(cfg.chains[0].accounts, vec![10_282_355_196_851_764_065, 42]);
+
+        let cfg = "plot_dirs: []\naccount_id_to_secret_phrase:\n  x: 'a'";
+        assert!(serde_yaml::from_str::<Cfg>(cfg).is_err());
+    }
+
+    #[test]
+    fn test_secret_phrases() {
+        let cfg: Cfg = serde_yaml::from_str(
+            "plot_dirs: []
+url: 'http://wallet'
+account_id_to_secret_phrase:
+  10282355196851764065: 'glad suffer red during single glow shut slam hill death lust although'
+  42: 'stand rude those door invite reflection anywhere lace safe hidden fur horrible'
+secret_phrases:
+  - 'stand rude those door invite reflection anywhere lace safe hidden fur horrible'",
+        )
+        .unwrap();
+        assert_eq!(cfg.secret_phrase_check, "refuse");
+        let chain = &cfg.chains()[0];
+        assert_eq!(chain.account_id_to_secret_phrase.len(), 3);
+        assert!(chain
+            .account_id_to_secret_phrase
+            .contains_key(&1_796_535_821_016_683_299));
+        assert_eq!(
+            chain.secret_phrase_mismatches(),
+            vec![(42, 1_796_535_821_016_683_299)]
+        );
+
+        let chains = load_cfg("config.yaml").unwrap().chains();
+        assert!(chains[0].secret_phrase_mismatches().is_empty());
+
+        let mut cfg = Cfg {
+            plot_dirs: vec!["test_data".to_owned()],
+            cpu_worker_thread_count: 1,
+            ..cfg
+        };
+        let errors = cfg.validate();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].field, "account_id_to_secret_phrase.42");
+        cfg.secret_phrase_check = "warn".to_owned();
+        assert!(cfg.validate().is_empty());
+    }
+
+    #[test]
+    fn test_resolve_secret_phrases() {
+        env::set_var(
+            "SCAVENGER_TEST_CFG_SECRET_PHRASE",
+            "glad suffer red during single glow shut slam hill death lust although",
+        );
+        let mut cfg: Cfg = serde_yaml::from_str(
+            "plot_dirs: []
+chains:
+  - name: 'a'
+    url: 'http://a'
+    account_id_to_secret_phrase:
+      10282355196851764065: 'env:SCAVENGER_TEST_CFG_SECRET_PHRASE'
+    secret_phrases: ['env:SCAVENGER_TEST_CFG_SECRET_PHRASE', 'plain']",
+        )
+        .unwrap();
+        assert!(resolve_secret_phrases(&mut cfg, &mut Secrets::new()).is_empty());
+        let chain = &cfg.chains()[0];
+        assert_eq!(
+            chain.account_id_to_secret_phrase[&10_282_355_196_851_764_065],
+            "glad suffer red during single glow shut slam hill death lust although"
+        );
+        assert_eq!(chain.secret_phrases[1], "plain");
+        assert!(chain.secret_phrase_mismatches().is_empty());
+
+        cfg.secret_phrases = vec!["env:SCAVENGER_TEST_CFG_UNSET".to_owned()];
+        assert_eq!(
+            resolve_secret_phrases(&mut cfg, &mut Secrets::new())[0].field,
+            "secret_phrases[0]"
+        );
+    }
 }
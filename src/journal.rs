@@ -0,0 +1,534 @@
+// append-only record of blocks, submissions and finished rounds, one event per line as json or
+// csv, summarised by `scavenger history`
+
+use burstmath::to_rs_address;
+use chrono::{DateTime, Utc};
+use config::parse_option;
+use metrics::Submission;
+use serde_json::{self, Map, Value};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::str::FromStr;
+use std::sync::Mutex;
+use std::u64;
+
+// column order of csv journals, also the header line
+const CSV_COLUMNS: &[&str] = &[
+    "time",
+    "event",
+    "chain",
+    "height",
+    "base_target",
+    "scoop",
+    "url",
+    "account_id",
+    "nonce",
+    "deadline",
+    "pool_deadline",
+    "accepted",
+    "error",
+    "round_time_ms",
+];
+const CSV_STRING_COLUMNS: &[&str] = &["time", "event", "chain", "url", "error"];
+
+// best deadlines listed per chain by the summary
+const BEST_DEADLINES: usize = 5;
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EventKind {
+    Block,
+    Submission,
+    Round,
+}
+
+// a flat record so that json lines and csv share one layout, unused fields stay empty
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Event {
+    pub time: String,
+    pub event: EventKind,
+    pub chain: String,
+    pub height: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub base_target: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scoop: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub account_id: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub nonce: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub deadline: Option<u64>,
+    // the deadline the pool or wallet confirmed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pool_deadline: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub accepted: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub round_time_ms: Option<i64>,
+}
+
+impl Event {
+    pub fn new(event: EventKind, chain: &str, height: u64) -> Event {
+        Event {
+            time: Utc::now().to_rfc3339(),
+            event,
+            chain: chain.to_owned(),
+            height,
+            base_target: None,
+            scoop: None,
+            url: None,
+            account_id: None,
+            nonce: None,
+            deadline: None,
+            pool_deadline: None,
+            accepted: None,
+            error: None,
+            round_time_ms: None,
+        }
+    }
+
+    pub fn block(chain: &str, height: u64, base_target: u64, scoop: u32) -> Event {
+        let mut event = Event::new(EventKind::Block, chain, height);
+        event.base_target = Some(base_target);
+        event.scoop = Some(scoop);
+        event
+    }
+
+    pub fn round(chain: &str, height: u64, round_time_ms: i64) -> Event {
+        let mut event = Event::new(EventKind::Round, chain, height);
+        event.round_time_ms = Some(round_time_ms);
+        event
+    }
+
+    pub fn submission(submission: &Submission) -> Event {
+        let mut event = Event::new(EventKind::Submission, &submission.chain, submission.height);
+        event.time = submission.time.clone();
+        event.url = Some(submission.url.clone());
+        event.account_id = Some(submission.account_id);
+        event.nonce = Some(submission.nonce);
+        event.deadline = Some(submission.deadline);
+        event.pool_deadline = submission.pool_deadline;
+        event.accepted = Some(submission.accepted);
+        event.error = submission.error.clone();
+        event
+    }
+
+    fn to_csv(&self) -> String {
+        let fields = match serde_json::to_value(self).unwrap() {
+            Value::Object(fields) => fields,
+            _ => unreachable!(),
+        };
+        CSV_COLUMNS
+            .iter()
+            .map(|column| match fields.get(*column) {
+                Some(Value::String(s)) => csv_quote(s),
+                Some(value) => value.to_string(),
+                None => String::new(),
+            }).collect::<Vec<String>>()
+            .join(",")
+    }
+
+    fn from_csv(line: &str) -> Result<Event, String> {
+        let values = csv_split(line);
+        if values.len() != CSV_COLUMNS.len() {
+            return Err(format!(
+                "{} columns instead of {}",
+                values.len(),
+                CSV_COLUMNS.len()
+            ));
+        }
+        let mut fields = Map::new();
+        for (column, value) in CSV_COLUMNS.iter().zip(values) {
+            if value.is_empty() {
+                continue;
+            }
+            let value = if CSV_STRING_COLUMNS.contains(column) {
+                Value::String(value)
+            } else {
+                serde_json::from_str(&value).map_err(|e| format!("{}: {}", column, e))?
+            };
+            fields.insert(column.to_string(), value);
+        }
+        serde_json::from_value(Value::Object(fields)).map_err(|e| e.to_string())
+    }
+}
+
+// records are lines, line breaks in values become spaces
+fn csv_quote(s: &str) -> String {
+    let s = s.replace(&['\n', '\r'][..], " ");
+    if s.contains(&[',', '"'][..]) {
+        format!("\"{}\"", s.replace('"', "\"\""))
+    } else {
+        s
+    }
+}
+
+fn csv_split(line: &str) -> Vec<String> {
+    let mut values = vec![String::new()];
+    let mut quoted = false;
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                chars.next();
+                values.last_mut().unwrap().push('"');
+            }
+            '"' => quoted = !quoted,
+            ',' if !quoted => values.push(String::new()),
+            c => values.last_mut().unwrap().push(c),
+        }
+    }
+    values
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Format {
+    Json,
+    Csv,
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Format, String> {
+        parse_option(s, &[("json", Format::Json), ("csv", Format::Csv)])
+    }
+}
+
+// shared like the metrics, a disabled journal records nothing
+pub struct Journal {
+    path: String,
+    format: Format,
+    file: Option<Mutex<File>>,
+}
+
+impl Journal {
+    // appends to an existing journal, a new csv journal starts with the header
+    pub fn open(path: &str, format: Format) -> io::Result<Journal> {
+        let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+        if format == Format::Csv && file.metadata()?.len() == 0 {
+            writeln!(file, "{}", CSV_COLUMNS.join(","))?;
+        }
+        Ok(Journal {
+            path: path.to_owned(),
+            format,
+            file: Some(Mutex::new(file)),
+        })
+    }
+
+    pub fn disabled() -> Journal {
+        Journal {
+            path: String::new(),
+            format: Format::Json,
+            file: None,
+        }
+    }
+
+    pub fn record(&self, event: &Event) {
+        let file = match self.file {
+            Some(ref file) => file,
+            None => return,
+        };
+        let line = match self.format {
+            Format::Json => serde_json::to_string(event).unwrap(),
+            Format::Csv => event.to_csv(),
+        };
+        if let Err(e) = writeln!(file.lock().unwrap(), "{}", line) {
+            warn!("journal {}: {}", self.path, e);
+        }
+    }
+}
+
+// reads json and csv journals alike, broken lines are skipped with a warning
+pub fn read(path: &str) -> io::Result<Vec<Event>> {
+    let mut events = Vec::new();
+    for (i, line) in fs::read_to_string(path)?.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line == CSV_COLUMNS.join(",") {
+            continue;
+        }
+        let event = if line.starts_with('{') {
+            serde_json::from_str(line).map_err(|e| e.to_string())
+        } else {
+            Event::from_csv(line)
+        };
+        match event {
+            Ok(event) => events.push(event),
+            Err(e) => warn!("journal {}: line {}: {}", path, i + 1, e),
+        }
+    }
+    Ok(events)
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct Summary {
+    pub blocks: u64,
+    // heights where a confirmed deadline ran out before the next block, only an estimate: a
+    // miner with a lower deadline or the pool's own forging decide who really won
+    pub within_block_time: Vec<u64>,
+    pub rounds: u64,
+    pub round_time_ms: i64,
+    pub submissions: u64,
+    pub accepted: u64,
+    // (deadline, height, account_id), lowest first
+    pub best_deadlines: Vec<(u64, u64, u64)>,
+}
+
+impl Summary {
+    pub fn avg_round_time_ms(&self) -> i64 {
+        if self.rounds == 0 {
+            0
+        } else {
+            self.round_time_ms / self.rounds as i64
+        }
+    }
+}
+
+impl fmt::Display for Summary {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "  blocks: {}, deadline within block time (estimate): {}{}",
+            self.blocks,
+            self.within_block_time.len(),
+            if self.within_block_time.is_empty() {
+                String::new()
+            } else {
+                format!(
+                    " ({})",
+                    self.within_block_time
+                        .iter()
+                        .map(|height| height.to_string())
+                        .collect::<Vec<String>>()
+                        .join(", ")
+                )
+            }
+        )?;
+        writeln!(
+            f,
+            "  rounds: {}, average round time: {}ms",
+            self.rounds,
+            self.avg_round_time_ms()
+        )?;
+        writeln!(
+            f,
+            "  submissions: {}, accepted: {}",
+            self.submissions, self.accepted
+        )?;
+        writeln!(f, "  best deadlines:")?;
+        for &(deadline, height, account_id) in &self.best_deadlines {
+            writeln!(
+                f,
+                "    {}s at height {} by {}",
+                deadline,
+                height,
+                to_rs_address(account_id)
+            )?;
+        }
+        Ok(())
+    }
+}
+
+// per chain summary, a block counts as within block time if the best confirmed deadline for its
+// height was not longer than the time until the next block arrived
+pub fn summarize(events: &[Event]) -> BTreeMap<String, Summary> {
+    let mut summaries: BTreeMap<String, Summary> = BTreeMap::new();
+    // (chain, height) -> arrival of the block
+    let mut blocks: BTreeMap<(String, u64), DateTime<Utc>> = BTreeMap::new();
+    // (chain, height) -> best confirmed deadline
+    let mut confirmed: BTreeMap<(String, u64), u64> = BTreeMap::new();
+
+    for event in events {
+        let summary = summaries.entry(event.chain.clone()).or_default();
+        match event.event {
+            EventKind::Block => {
+                summary.blocks += 1;
+                if let Ok(time) = DateTime::parse_from_rfc3339(&event.time) {
+                    blocks.insert(
+                        (event.chain.clone(), event.height),
+                        time.with_timezone(&Utc),
+                    );
+                }
+            }
+            EventKind::Round => {
+                summary.rounds += 1;
+                summary.round_time_ms += event.round_time_ms.unwrap_or(0);
+            }
+            EventKind::Submission => {
+                summary.submissions += 1;
+                if event.accepted != Some(true) {
+                    continue;
+                }
+                summary.accepted += 1;
+                let deadline = event.pool_deadline.or(event.deadline).unwrap_or(u64::MAX);
+                let best = confirmed
+                    .entry((event.chain.clone(), event.height))
+                    .or_insert(deadline);
+                if deadline < *best {
+                    *best = deadline;
+                }
+                if let Some(account_id) = event.account_id {
+                    summary
+                        .best_deadlines
+                        .push((deadline, event.height, account_id));
+                }
+            }
+        }
+    }
+
+    for (&(ref chain, height), &deadline) in &confirmed {
+        let block_time = match (
+            blocks.get(&(chain.clone(), height)),
+            blocks.get(&(chain.clone(), height + 1)),
+        ) {
+            (Some(start), Some(next)) => next.signed_duration_since(*start).num_seconds(),
+            _ => continue,
+        };
+        if block_time >= 0 && deadline <= block_time as u64 {
+            summaries
+                .get_mut(chain)
+                .unwrap()
+                .within_block_time
+                .push(height);
+        }
+    }
+    for summary in summaries.values_mut() {
+        summary.best_deadlines.sort();
+        summary.best_deadlines.dedup();
+        summary.best_deadlines.truncate(BEST_DEADLINES);
+    }
+    summaries
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::env;
+
+    fn event(kind: EventKind, time: &str, height: u64) -> Event {
+        let mut event = Event::new(kind, "burst", height);
+        event.time = time.to_owned();
+        event
+    }
+
+    fn submission(time: &str, height: u64, deadline: u64, pool_deadline: u64) -> Event {
+        let mut event = event(EventKind::Submission, time, height);
+        event.url = Some("http://pool".to_owned());
+        event.account_id = Some(42);
+        event.nonce = Some(7);
+        event.deadline = Some(deadline);
+        event.pool_deadline = Some(pool_deadline);
+        event.accepted = Some(true);
+        event
+    }
+
+    fn events() -> Vec<Event> {
+        let mut block = event(EventKind::Block, "2018-10-01T10:00:00+00:00", 500);
+        block.base_target = Some(70_000);
+        block.scoop = Some(12);
+        let mut round = event(EventKind::Round, "2018-10-01T10:00:20+00:00", 500);
+        round.round_time_ms = Some(20_000);
+        let mut failed = event(EventKind::Submission, "2018-10-01T10:01:20+00:00", 501);
+        failed.url = Some("http://pool".to_owned());
+        failed.error = Some("1: \"late\", really".to_owned());
+        failed.accepted = Some(false);
+        let mut round2 = event(EventKind::Round, "2018-10-01T10:01:40+00:00", 501);
+        round2.round_time_ms = Some(10_000);
+        vec![
+            block,
+            submission("2018-10-01T10:00:10+00:00", 500, 300, 300),
+            submission("2018-10-01T10:00:15+00:00", 500, 50, 50),
+            round,
+            // block 500 took 60s, the deadline of 50s ran out before it
+            event(EventKind::Block, "2018-10-01T10:01:00+00:00", 501),
+            submission("2018-10-01T10:01:10+00:00", 501, 100, 100),
+            failed,
+            round2,
+            // block 501 took 30s
+            event(EventKind::Block, "2018-10-01T10:01:30+00:00", 502),
+        ]
+    }
+
+    #[test]
+    fn test_csv() {
+        for event in events() {
+            assert_eq!(Event::from_csv(&event.to_csv()), Ok(event));
+        }
+        assert_eq!(
+            csv_split("a,\"b,\"\"c\"\"\",,d"),
+            vec!["a", "b,\"c\"", "", "d"]
+        );
+        assert!(Event::from_csv("a,b").is_err());
+    }
+
+    #[test]
+    fn test_journal() {
+        for &(format, name) in &[
+            (Format::Json, "scavenger_test_journal.jsonl"),
+            (Format::Csv, "scavenger_test_journal.csv"),
+        ] {
+            let path = env::temp_dir().join(name);
+            let path = path.to_str().unwrap();
+            let _ = fs::remove_file(path);
+            let events = events();
+            let (first, second) = events.split_at(4);
+            // reopening appends, the csv header is only written once
+            let journal = Journal::open(path, format).unwrap();
+            for event in first {
+                journal.record(event);
+            }
+            let journal = Journal::open(path, format).unwrap();
+            for event in second {
+                journal.record(event);
+            }
+            assert_eq!(read(path).unwrap(), events);
+            fs::remove_file(path).unwrap();
+        }
+        Journal::disabled().record(&events()[0]);
+    }
+
+    #[test]
+    fn test_events() {
+        let block = Event::block("burst", 500, 70_000, 12);
+        assert_eq!((block.base_target, block.scoop), (Some(70_000), Some(12)));
+        assert_eq!(Event::round("burst", 500, 1500).round_time_ms, Some(1500));
+
+        let mut submission = Submission::new("burst", "http://pool", 42, 500, 7, 1234);
+        submission.accepted = true;
+        submission.pool_deadline = Some(1234);
+        let event = Event::submission(&submission);
+        assert_eq!(event.event, EventKind::Submission);
+        assert_eq!(event.time, submission.time);
+        assert_eq!(event.url, Some("http://pool".to_owned()));
+        assert_eq!((event.nonce, event.deadline), (Some(7), Some(1234)));
+        assert_eq!(event.pool_deadline, Some(1234));
+        assert_eq!(event.accepted, Some(true));
+    }
+
+    #[test]
+    fn test_summarize() {
+        let summaries = summarize(&events());
+        assert_eq!(summaries.len(), 1);
+        let summary = &summaries["burst"];
+        assert_eq!(summary.blocks, 3);
+        assert_eq!(summary.within_block_time, vec![500]);
+        assert_eq!(summary.rounds, 2);
+        assert_eq!(summary.avg_round_time_ms(), 15_000);
+        assert_eq!(summary.submissions, 4);
+        assert_eq!(summary.accepted, 3);
+        assert_eq!(
+            summary.best_deadlines,
+            vec![(50, 500, 42), (100, 501, 42), (300, 500, 42)]
+        );
+        assert!(summary
+            .to_string()
+            .contains("deadline within block time (estimate): 1 (500)"));
+    }
+}
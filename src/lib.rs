@@ -0,0 +1,78 @@
+//! Scavenger's mining engine: plot reading, deadline search and pool communication.
+//!
+//! `MinerBuilder` turns a `Cfg` into a running `Miner`. Block info comes from a
+//! `MiningInfoSource` and found deadlines go to a `NonceSink`, both default to the
+//! http pool/wallet client of the configured chain.
+
+#[macro_use]
+extern crate serde_derive;
+extern crate crossbeam_channel as chan;
+extern crate futures;
+extern crate hex;
+extern crate hyper;
+extern crate libc;
+extern crate serde;
+extern crate serde_json;
+extern crate serde_yaml;
+extern crate stopwatch;
+extern crate tokio;
+extern crate tokio_core;
+extern crate url;
+#[macro_use]
+extern crate cfg_if;
+extern crate filetime;
+#[macro_use]
+extern crate clap;
+extern crate rand;
+#[macro_use]
+extern crate log;
+extern crate chrono;
+extern crate core_affinity;
+extern crate log4rs;
+extern crate num_cpus;
+
+mod burstmath;
+mod config;
+mod journal;
+mod logger;
+mod metrics;
+mod miner;
+#[cfg(test)]
+mod mock_wallet;
+mod noncegen;
+mod plot;
+mod plotter;
+mod pool;
+mod quarantine;
+mod reader;
+mod requests;
+mod secrets;
+mod shabal_portable;
+mod shabals;
+mod utils;
+mod verifier;
+mod worker;
+
+#[cfg(feature = "opencl")]
+mod ocl;
+
+pub use config::{load_cfg, load_cfg_with_overrides, load_cfg_with_secrets, Cfg, ConfigError};
+pub use miner::{Miner, MinerBuilder};
+pub use plot::Plot;
+pub use pool::{
+    replay_source, ChannelSink, ChannelSource, MiningInfoSource, MockPool, NonceSink,
+    SubmittedNonce,
+};
+pub use reader::Reader;
+pub use requests::{FetchError, MiningInfo};
+pub use worker::{NonceData, SimdExtension};
+
+// the subcommands of the scavenger binary
+pub use burstmath::{account_id_from_secret_phrase, parse_account_id, to_rs_address};
+pub use journal::{read as read_journal, summarize as summarize_journal};
+pub use logger::{init_console_logger, init_logger};
+#[cfg(feature = "opencl")]
+pub use ocl::{gpu_info, platform_info};
+pub use plotter::{parse_size, plot, PlotterTask};
+pub use secrets::{prompt_passphrase, write_keystore, Secrets, KEYSTORE_PASSPHRASE_VAR};
+pub use verifier::verify;
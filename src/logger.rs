@@ -1,16 +1,34 @@
 extern crate log;
 extern crate log4rs;
+extern crate log_mdc;
 use config::Cfg;
 
-use log::LevelFilter;
+#[cfg(unix)]
+use log::{Level, Record};
 use log4rs::append::console::ConsoleAppender;
 use log4rs::append::rolling_file::policy::compound::roll::fixed_window::FixedWindowRoller;
 use log4rs::append::rolling_file::policy::compound::trigger::size::SizeTrigger;
 use log4rs::append::rolling_file::policy::compound::CompoundPolicy;
 use log4rs::append::rolling_file::RollingFileAppender;
+#[cfg(unix)]
+use log4rs::append::Append;
 use log4rs::config::{Appender, Config, Root};
+use log4rs::encode::json::JsonEncoder;
 use log4rs::encode::pattern::PatternEncoder;
+#[cfg(unix)]
+use log4rs::encode::writer::simple::SimpleWriter;
+use log4rs::encode::Encode;
 use log4rs::filter::threshold::ThresholdFilter;
+#[cfg(unix)]
+use std::error::Error;
+#[cfg(unix)]
+use std::os::unix::net::UnixDatagram;
+use std::path::Path;
+#[cfg(unix)]
+use std::process;
+
+#[cfg(unix)]
+const SYSLOG_SOCKET: &str = "/dev/log";
 
 fn to_log_level(s: &str, default: log::LevelFilter) -> log::LevelFilter {
     match s.to_lowercase().as_str() {
@@ -24,9 +42,27 @@ fn to_log_level(s: &str, default: log::LevelFilter) -> log::LevelFilter {
     }
 }
 
-pub fn init_logger(cfg: &Cfg) -> log4rs::Handle {
+// json writes one object per line, event fields end up in its "mdc" object
+fn encoder(format: &str, pattern: &str) -> Box<Encode> {
+    match format.to_lowercase().as_str() {
+        "json" => Box::new(JsonEncoder::new()),
+        _ => Box::new(PatternEncoder::new(pattern)),
+    }
+}
+
+// structured fields of the events logged while the guard lives, e.g. for log shippers
+pub fn event(name: &str, fields: &[(&str, String)]) -> log_mdc::ExtendGuard {
+    log_mdc::extend_scoped(
+        Some(("event", name.to_owned()))
+            .into_iter()
+            .chain(fields.iter().map(|&(key, ref value)| (key, value.clone()))),
+    )
+}
+
+fn log_config(cfg: &Cfg) -> Config {
     let level_console = to_log_level(&cfg.console_log_level, log::LevelFilter::Info);
     let level_logfile = to_log_level(&cfg.logfile_log_level, log::LevelFilter::Warn);
+    let level_syslog = to_log_level(&cfg.syslog_log_level, log::LevelFilter::Off);
     let mut console_log_pattern = if cfg.show_progress {
         "\r".to_owned()
     } else {
@@ -43,46 +79,126 @@ pub fn init_logger(cfg: &Cfg) -> log4rs::Handle {
     let stdout = ConsoleAppender::builder()
         .encoder(Box::new(PatternEncoder::new(&console_log_pattern)))
         .build();
+    let mut config = Config::builder().appender(
+        Appender::builder()
+            .filter(Box::new(ThresholdFilter::new(level_console)))
+            .build("stdout", Box::new(stdout)),
+    );
+    let mut root = Root::builder().appender("stdout");
 
-    let roller = FixedWindowRoller::builder()
-        .base(1)
-        .build("log/scavenger.{}.log", cfg.logfile_max_count)
-        .unwrap();
-    let trigger = SizeTrigger::new(&cfg.logfile_max_size * 1024 * 1024);
-    let policy = Box::new(CompoundPolicy::new(Box::new(trigger), Box::new(roller)));
-
-    let config = if level_logfile == log::LevelFilter::Off {
-        Config::builder()
-            .appender(
-                Appender::builder()
-                    .filter(Box::new(ThresholdFilter::new(level_console)))
-                    .build("stdout", Box::new(stdout)),
-            ).build(Root::builder().appender("stdout").build(LevelFilter::Info))
-            .unwrap()
-    } else {
+    if level_logfile != log::LevelFilter::Off {
+        let log_dir = Path::new(&cfg.logfile_dir);
+        let roller = FixedWindowRoller::builder()
+            .base(1)
+            .build(
+                &log_dir.join("scavenger.{}.log").to_string_lossy(),
+                cfg.logfile_max_count,
+            ).unwrap();
+        let trigger = SizeTrigger::new(&cfg.logfile_max_size * 1024 * 1024);
+        let policy = Box::new(CompoundPolicy::new(Box::new(trigger), Box::new(roller)));
         let logfile = RollingFileAppender::builder()
-            .encoder(Box::new(PatternEncoder::new(&logfile_log_pattern)))
-            .build("log/scavenger.1.log", policy)
+            .encoder(encoder(&cfg.logfile_format, &logfile_log_pattern))
+            .build(log_dir.join("scavenger.1.log"), policy)
             .unwrap();
-        Config::builder()
-            .appender(
-                Appender::builder()
-                    .filter(Box::new(ThresholdFilter::new(level_console)))
-                    .build("stdout", Box::new(stdout)),
-            ).appender(
-                Appender::builder()
-                    .filter(Box::new(ThresholdFilter::new(level_logfile)))
-                    .build("logfile", Box::new(logfile)),
-            ).build(
-                Root::builder()
-                    .appender("stdout")
-                    .appender("logfile")
-                    .build(LevelFilter::Trace),
-            ).unwrap()
-    };
+        config = config.appender(
+            Appender::builder()
+                .filter(Box::new(ThresholdFilter::new(level_logfile)))
+                .build("logfile", Box::new(logfile)),
+        );
+        root = root.appender("logfile");
+    }
+
+    #[cfg(unix)]
+    {
+        if level_syslog != log::LevelFilter::Off {
+            // the daemon adds time and host, the message alone is enough
+            match SyslogAppender::new(encoder(&cfg.logfile_format, "{m}")) {
+                Ok(syslog) => {
+                    config = config.appender(
+                        Appender::builder()
+                            .filter(Box::new(ThresholdFilter::new(level_syslog)))
+                            .build("syslog", Box::new(syslog)),
+                    );
+                    root = root.appender("syslog");
+                }
+                Err(e) => eprintln!("syslog: {}: {}", SYSLOG_SOCKET, e),
+            }
+        }
+    }
+
+    let level = level_console.max(level_logfile).max(level_syslog);
+    config.build(root.build(level)).unwrap()
+}
+
+pub fn init_logger(cfg: &Cfg) -> log4rs::Handle {
+    log4rs::init_config(log_config(cfg)).unwrap()
+}
+
+// info and above on the console, for subcommands that don't read the config
+pub fn init_console_logger() -> log4rs::Handle {
+    let stdout = ConsoleAppender::builder()
+        .encoder(Box::new(PatternEncoder::new(
+            "{d(%H:%M:%S)} [{h({l}):<5}] {m}{n}",
+        )))
+        .build();
+    let config = Config::builder()
+        .appender(Appender::builder().build("stdout", Box::new(stdout)))
+        .build(
+            Root::builder()
+                .appender("stdout")
+                .build(log::LevelFilter::Info),
+        )
+        .unwrap();
     log4rs::init_config(config).unwrap()
 }
 
+// applies changed log levels and patterns of a reloaded config
+pub fn reload_logger(handle: &log4rs::Handle, cfg: &Cfg) {
+    handle.set_config(log_config(cfg));
+}
+
+// sends records to the local syslog daemon, journald reads the same socket
+#[cfg(unix)]
+#[derive(Debug)]
+struct SyslogAppender {
+    socket: UnixDatagram,
+    encoder: Box<Encode>,
+}
+
+#[cfg(unix)]
+impl SyslogAppender {
+    fn new(encoder: Box<Encode>) -> Result<SyslogAppender, Box<Error + Sync + Send>> {
+        let socket = UnixDatagram::unbound()?;
+        socket.connect(SYSLOG_SOCKET)?;
+        Ok(SyslogAppender { socket, encoder })
+    }
+}
+
+#[cfg(unix)]
+impl Append for SyslogAppender {
+    fn append(&self, record: &Record) -> Result<(), Box<Error + Sync + Send>> {
+        let mut w = SimpleWriter(syslog_header(record.level()).into_bytes());
+        self.encoder.encode(&mut w, record)?;
+        let msg = String::from_utf8_lossy(&w.0);
+        self.socket.send(msg.trim_end().as_bytes())?;
+        Ok(())
+    }
+
+    fn flush(&self) {}
+}
+
+// "<PRI>TAG[PID]: " of RFC 3164 with facility user
+#[cfg(unix)]
+fn syslog_header(level: Level) -> String {
+    let severity = match level {
+        Level::Error => 3,
+        Level::Warn => 4,
+        Level::Info => 6,
+        Level::Debug | Level::Trace => 7,
+    };
+    format!("<{}>scavenger[{}]: ", 8 + severity, process::id())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -122,7 +238,7 @@ mod tests {
     #[test]
     fn test_init_logger() {
         use config::load_cfg;
-        let mut cfg = load_cfg("config.yaml");
+        let mut cfg = load_cfg("config.yaml").unwrap();
 
         // we dont want to see this during tests
         cfg.console_log_level = log::LevelFilter::Error.to_string();
@@ -132,4 +248,45 @@ mod tests {
         debug!("DEBUG");
         info!("INFO");
     }
+
+    #[test]
+    fn test_json_event() {
+        use log::Record;
+        use log4rs::encode::writer::simple::SimpleWriter;
+        use serde_json;
+
+        let guard = event("round_finished", &[("height", 5.to_string())]);
+        let mut w = SimpleWriter(Vec::new());
+        encoder("JSON", "{m}")
+            .encode(
+                &mut w,
+                &Record::builder()
+                    .args(format_args!("round finished"))
+                    .level(log::Level::Info)
+                    .build(),
+            ).unwrap();
+        let line: serde_json::Value = serde_json::from_slice(&w.0).unwrap();
+        assert_eq!(line["message"], "round finished");
+        assert_eq!(line["mdc"]["event"], "round_finished");
+        assert_eq!(line["mdc"]["height"], "5");
+        drop(guard);
+
+        let mut w = SimpleWriter(Vec::new());
+        encoder("pattern", "{l} {m}")
+            .encode(
+                &mut w,
+                &Record::builder()
+                    .args(format_args!("round finished"))
+                    .level(log::Level::Info)
+                    .build(),
+            ).unwrap();
+        assert_eq!(String::from_utf8(w.0).unwrap(), "INFO round finished");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_syslog_header() {
+        assert!(syslog_header(log::Level::Error).starts_with("<11>scavenger["));
+        assert!(syslog_header(log::Level::Trace).starts_with("<15>scavenger["));
+    }
 }
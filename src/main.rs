@@ -1,74 +1,21 @@
 #[macro_use]
-extern crate serde_derive;
-extern crate crossbeam_channel as chan;
-extern crate futures;
-extern crate hex;
-extern crate hyper;
-extern crate libc;
-extern crate serde;
-extern crate serde_json;
-extern crate serde_yaml;
-extern crate stopwatch;
-extern crate tokio;
-extern crate tokio_core;
-extern crate url;
-#[macro_use]
-extern crate cfg_if;
-extern crate filetime;
-#[macro_use]
 extern crate clap;
-extern crate rand;
 #[macro_use]
 extern crate log;
-extern crate chrono;
-extern crate core_affinity;
-extern crate log4rs;
-
-mod burstmath;
-mod config;
-mod logger;
-mod miner;
-mod plot;
-mod reader;
-mod requests;
-mod shabals;
-mod utils;
-mod worker;
-
-#[cfg(feature = "opencl")]
-mod ocl;
-
-use clap::{App, Arg};
-use config::load_cfg;
-use miner::Miner;
-use std::process;
-
-extern "C" {
-    pub fn init_shabal_avx2() -> ();
+extern crate num_cpus;
+extern crate scavenger;
 
-    pub fn init_shabal_avx() -> ();
-
-    pub fn init_shabal_sse2() -> ();
-}
-
-fn init_simd_extensions() {
-    if is_x86_feature_detected!("avx2") {
-        info!("SIMD extensions: AVX2");
-        unsafe {
-            init_shabal_avx2();
-        }
-    } else if is_x86_feature_detected!("avx") {
-        info!("SIMD extensions: AVX");
-        unsafe {
-            init_shabal_avx();
-        }
-    } else {
-        info!("SIMD extensions: SSE2");
-        unsafe {
-            init_shabal_sse2();
-        }
-    }
-}
+use clap::{App, Arg, SubCommand};
+use scavenger::{
+    account_id_from_secret_phrase, init_console_logger, init_logger, load_cfg_with_secrets,
+    parse_account_id, parse_size, plot, prompt_passphrase, read_journal, summarize_journal,
+    to_rs_address, verify, write_keystore, MinerBuilder, PlotterTask, Secrets, SimdExtension,
+    KEYSTORE_PASSPHRASE_VAR,
+};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::env;
+use std::process;
 
 fn main() {
     let arg = App::new("Scavenger - a Burst miner")
@@ -83,6 +30,126 @@ fn main() {
                 .help("Location of the config file")
                 .takes_value(true)
                 .default_value("config.yaml"),
+        ).arg(
+            Arg::with_name("set")
+                .long("set")
+                .value_name("KEY=VALUE")
+                .help("Overrides a config setting, e.g. --set timeout=10000 (repeatable)")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1),
+        ).arg(
+            Arg::with_name("print config")
+                .long("print-config")
+                .help("Prints the effective config (file, SCAVENGER_* env, --set) and exits")
+                .takes_value(false),
+        ).subcommand(
+            SubCommand::with_name("verify")
+                .about("Verifies a plot file by regenerating sample nonces")
+                .arg(
+                    Arg::with_name("file")
+                        .value_name("FILE")
+                        .help("Plot file to verify")
+                        .required(true),
+                ).arg(
+                    Arg::with_name("sample")
+                        .short("s")
+                        .long("sample")
+                        .value_name("NONCES")
+                        .help("Number of nonces to check (0=all)")
+                        .takes_value(true)
+                        .default_value("1000"),
+                ),
+        ).subcommand(
+            SubCommand::with_name("plot")
+                .about("Creates an optimized PoC2 plot file, resumes interrupted plots")
+                .arg(
+                    Arg::with_name("id")
+                        .long("id")
+                        .value_name("ACCOUNT")
+                        .help("Numeric account id or address")
+                        .takes_value(true)
+                        .required(true),
+                ).arg(
+                    Arg::with_name("start nonce")
+                        .long("sn")
+                        .value_name("START_NONCE")
+                        .help("First nonce of the plot file")
+                        .takes_value(true)
+                        .required(true),
+                ).arg(
+                    Arg::with_name("nonces")
+                        .short("n")
+                        .long("nonces")
+                        .value_name("NONCES")
+                        .help("Number of nonces to plot")
+                        .takes_value(true)
+                        .required(true),
+                ).arg(
+                    Arg::with_name("path")
+                        .short("p")
+                        .long("path")
+                        .value_name("DIR")
+                        .help("Output directory")
+                        .takes_value(true)
+                        .default_value("."),
+                ).arg(
+                    Arg::with_name("mem")
+                        .short("m")
+                        .long("mem")
+                        .value_name("SIZE")
+                        .help("Memory budget for the nonce cache, e.g. 2GiB")
+                        .takes_value(true)
+                        .default_value("1GiB"),
+                ).arg(
+                    Arg::with_name("threads")
+                        .short("t")
+                        .long("threads")
+                        .value_name("THREADS")
+                        .help("Number of CPU threads (0=all cores)")
+                        .takes_value(true)
+                        .default_value("0"),
+                ).arg(
+                    Arg::with_name("disable direct io")
+                        .short("d")
+                        .long("ddio")
+                        .help("Disables direct io for writing")
+                        .takes_value(false),
+                ).arg(
+                    Arg::with_name("hide progress")
+                        .long("hide-progress")
+                        .help("Hides the progress bar, e.g. when logging to a file")
+                        .takes_value(false),
+                ),
+        ).subcommand(
+            SubCommand::with_name("address")
+                .about("Converts between numeric account ids and BURST- addresses")
+                .arg(
+                    Arg::with_name("account")
+                        .value_name("ACCOUNT")
+                        .help("Numeric account id or address")
+                        .required(true),
+                ),
+        ).subcommand(
+            SubCommand::with_name("check-config")
+                .about("Validates the config file, exits non-zero if it has errors"),
+        ).subcommand(
+            SubCommand::with_name("history")
+                .about("Summarises the mining journal: best deadlines, estimated wins, round times")
+                .arg(
+                    Arg::with_name("file")
+                        .value_name("FILE")
+                        .help("Journal to read, defaults to journal_file of the config"),
+                ),
+        ).subcommand(
+            SubCommand::with_name("keystore")
+                .about("Creates an encrypted keystore of secret phrases for keystore:FILE")
+                .arg(
+                    Arg::with_name("file")
+                        .value_name("FILE")
+                        .help("Keystore to create")
+                        .required(true),
+                ),
         );
     #[cfg(feature = "opencl")]
     let arg = arg.arg(
@@ -94,10 +161,109 @@ fn main() {
         );
 
     let matches = &arg.get_matches();
+
+    // doesn't need a config
+    if let Some(matches) = matches.subcommand_matches("address") {
+        match parse_account_id(matches.value_of("account").unwrap()) {
+            Ok(account_id) => {
+                println!("{}", account_id);
+                println!("{}", to_rs_address(account_id));
+                process::exit(0);
+            }
+            Err(e) => {
+                eprintln!("{}", e);
+                process::exit(1);
+            }
+        }
+    }
+
+    if let Some(matches) = matches.subcommand_matches("keystore") {
+        match create_keystore(matches.value_of("file").unwrap()) {
+            Ok(()) => process::exit(0),
+            Err(e) => {
+                eprintln!("{}", e);
+                process::exit(1);
+            }
+        }
+    }
+
+    // the config is only read for its journal_file
+    if let Some(path) = matches
+        .subcommand_matches("history")
+        .and_then(|matches| matches.value_of("file"))
+    {
+        print_history(path);
+    }
+
+    if let Some(matches) = matches.subcommand_matches("verify") {
+        init_console_logger();
+        let sample = value_t!(matches, "sample", u64).unwrap_or_else(|e| e.exit());
+        if !verify(matches.value_of("file").unwrap(), sample) {
+            process::exit(1);
+        }
+        process::exit(0);
+    }
+
+    if let Some(matches) = matches.subcommand_matches("plot") {
+        init_console_logger();
+        let threads = value_t!(matches, "threads", usize).unwrap_or_else(|e| e.exit());
+        let task = PlotterTask {
+            numeric_id: parse_account_id(matches.value_of("id").unwrap())
+                .unwrap_or_else(|e| {
+                    error!("{}", e);
+                    process::exit(1)
+                }),
+            start_nonce: value_t!(matches, "start nonce", u64).unwrap_or_else(|e| e.exit()),
+            nonces: value_t!(matches, "nonces", u64).unwrap_or_else(|e| e.exit()),
+            output_path: matches.value_of("path").unwrap().to_owned(),
+            mem: parse_size(matches.value_of("mem").unwrap()).unwrap_or_else(|e| {
+                error!("{}", e);
+                process::exit(1)
+            }),
+            cpu_threads: if threads == 0 {
+                num_cpus::get()
+            } else {
+                threads
+            },
+            direct_io: !matches.is_present("disable direct io"),
+            show_progress: !matches.is_present("hide progress"),
+        };
+        if let Err(e) = plot(&task) {
+            error!("plotter: {}", e);
+            process::exit(1);
+        }
+        process::exit(0);
+    }
+
     let config = matches.value_of("config").unwrap();
 
-    let cfg_loaded = load_cfg(config);
-    logger::init_logger(&cfg_loaded);
+    let overrides: Vec<String> = matches
+        .values_of("set")
+        .map(|values| values.map(|value| value.to_owned()).collect())
+        .unwrap_or_default();
+
+    let mut secrets = Secrets::new();
+    let cfg_loaded = match load_cfg_with_secrets(config, &overrides, &mut secrets) {
+        Ok(cfg) => {
+            if matches.is_present("print config") {
+                println!("{}", cfg.to_yaml());
+                process::exit(0);
+            }
+            if matches.subcommand_matches("check-config").is_some() {
+                println!("{}: ok", config);
+                process::exit(0);
+            }
+            cfg
+        }
+        Err(errors) => {
+            eprintln!("{}: {} errors", config, errors.len());
+            for e in errors {
+                eprintln!("  {}", e);
+            }
+            process::exit(1);
+        }
+    };
+    let log_handle = init_logger(&cfg_loaded);
 
     info!("Scavenger v.{}", crate_version!());
     #[cfg(feature = "opencl")]
@@ -105,13 +271,99 @@ fn main() {
 
     if matches.is_present("opencl") {
         #[cfg(feature = "opencl")]
-        ocl::platform_info();
+        scavenger::platform_info();
         process::exit(0);
     }
-    init_simd_extensions();
+
+    if matches.subcommand_matches("history").is_some() {
+        if cfg_loaded.journal_file.is_empty() {
+            eprintln!("history: no journal, set journal_file or pass FILE");
+            process::exit(1);
+        }
+        print_history(&cfg_loaded.journal_file);
+    }
+
+    let simd_ext = SimdExtension::from_cfg(&cfg_loaded.cpu_simd_extension);
+    info!("SIMD extensions: {}", simd_ext);
     #[cfg(feature = "opencl")]
-    ocl::gpu_info(&cfg_loaded);
+    scavenger::gpu_info(&cfg_loaded);
+
+    // SIGHUP reloads the config with the same env and --set overrides, the keystores stay
+    // unlocked and a reload never waits for a passphrase
+    let config = config.to_owned();
+    secrets.disable_prompt();
+    let secrets = RefCell::new(secrets);
+    let m = MinerBuilder::new(cfg_loaded)
+        .simd_extension(simd_ext)
+        .config_loader(move || {
+            load_cfg_with_secrets(&config, &overrides, &mut secrets.borrow_mut())
+        })
+        .log_handle(log_handle)
+        .build();
+    match m {
+        Ok(m) => m.run(),
+        Err(e) => {
+            error!("{}", e);
+            process::exit(1);
+        }
+    }
+}
+
+fn print_history(path: &str) -> ! {
+    match read_journal(path) {
+        Ok(events) => {
+            for (chain, summary) in summarize_journal(&events) {
+                println!("{}:\n{}", chain, summary);
+            }
+            process::exit(0);
+        }
+        Err(e) => {
+            eprintln!("history: {}: {}", path, e);
+            process::exit(1);
+        }
+    }
+}
+
+// prompts for the keystore passphrase and the secret phrases to store, the ids are derived
+fn create_keystore(path: &str) -> Result<(), String> {
+    let passphrase = match env::var(KEYSTORE_PASSPHRASE_VAR) {
+        Ok(passphrase) => passphrase,
+        Err(_) => {
+            let passphrase = prompt("keystore passphrase: ")?;
+            if passphrase != prompt("repeat keystore passphrase: ")? {
+                return Err("passphrases don't match".to_owned());
+            }
+            passphrase
+        }
+    };
+    if passphrase.is_empty() {
+        return Err("empty keystore passphrase".to_owned());
+    }
+
+    let mut account_id_to_secret_phrase = HashMap::new();
+    loop {
+        let secret_phrase = prompt("secret phrase (empty to finish): ")?;
+        if secret_phrase.is_empty() {
+            break;
+        }
+        let account_id = account_id_from_secret_phrase(&secret_phrase);
+        eprintln!("{}", to_rs_address(account_id));
+        account_id_to_secret_phrase.insert(account_id, secret_phrase);
+    }
+    if account_id_to_secret_phrase.is_empty() {
+        return Err("no secret phrases".to_owned());
+    }
+
+    write_keystore(path, &account_id_to_secret_phrase, &passphrase)
+        .map_err(|e| format!("{}: {}", path, e))?;
+    println!(
+        "{} accounts written to {}",
+        account_id_to_secret_phrase.len(),
+        path
+    );
+    Ok(())
+}
 
-    let m = Miner::new(cfg_loaded);
-    m.run();
+fn prompt(prompt: &str) -> Result<String, String> {
+    prompt_passphrase(prompt).map_err(|e| e.to_string())
 }
@@ -0,0 +1,590 @@
+use chrono::Utc;
+use futures::{Future, Stream};
+use hyper::server::conn::Http;
+use hyper::service::service_fn_ok;
+use hyper::{Body, Response, StatusCode};
+use quarantine::Kind;
+use serde_json;
+use std::collections::{BTreeMap, VecDeque};
+use std::fmt::Write;
+use std::net;
+use std::net::SocketAddr;
+use std::rc::Rc;
+use std::sync::Mutex;
+use std::time::Instant;
+use tokio::net::TcpListener;
+use tokio::reactor;
+use tokio_core::reactor::Handle;
+
+// number of submissions kept for the status api
+const MAX_SUBMISSIONS: usize = 50;
+
+// miner state exposed in the prometheus text format and as json status
+#[derive(Default)]
+pub struct Metrics {
+    inner: Mutex<Inner>,
+}
+
+#[derive(Default)]
+struct Inner {
+    chains: BTreeMap<String, ChainMetrics>,
+    // (chain, account) -> deadline in seconds
+    best_deadlines: BTreeMap<(String, u64), u64>,
+    drives: BTreeMap<String, DriveMetrics>,
+    // (url, result) -> count
+    submissions: BTreeMap<(String, &'static str), u64>,
+    last_submissions: VecDeque<Submission>,
+    plots: Vec<PlotInfo>,
+    // (kind, name) -> end of the back-off
+    quarantined: BTreeMap<(String, String), Instant>,
+}
+
+#[derive(Default)]
+struct ChainMetrics {
+    height: u64,
+    base_target: u64,
+    scoop: u32,
+    gensig: String,
+    rounds: u64,
+    round_duration_ms: i64,
+    round_start: Option<Instant>,
+    scanning: bool,
+}
+
+#[derive(Default)]
+struct DriveMetrics {
+    bytes_read: u64,
+    read_ms: i64,
+    throughput: u64,
+    round_bytes_read: u64,
+    round_bytes_total: u64,
+    read_errors: u64,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct PlotInfo {
+    pub name: String,
+    pub drive: String,
+    pub account_id: u64,
+    pub start_nonce: u64,
+    pub nonces: u64,
+    pub poc2: bool,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct Submission {
+    pub time: String,
+    pub chain: String,
+    pub url: String,
+    pub account_id: u64,
+    pub height: u64,
+    pub nonce: u64,
+    pub deadline: u64,
+    pub accepted: bool,
+    // the deadline the pool computed, if it accepted the nonce
+    pub pool_deadline: Option<u64>,
+    pub error: Option<String>,
+}
+
+impl Submission {
+    pub fn new(
+        chain: &str,
+        url: &str,
+        account_id: u64,
+        height: u64,
+        nonce: u64,
+        deadline: u64,
+    ) -> Submission {
+        Submission {
+            time: Utc::now().to_rfc3339(),
+            chain: chain.to_owned(),
+            url: url.to_owned(),
+            account_id,
+            height,
+            nonce,
+            deadline,
+            accepted: false,
+            pool_deadline: None,
+            error: None,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct Status {
+    rounds: Vec<RoundStatus>,
+    drives: Vec<DriveStatus>,
+    plots: Vec<PlotInfo>,
+    submissions: Vec<Submission>,
+    quarantined: Vec<QuarantineStatus>,
+}
+
+#[derive(Debug, Serialize)]
+struct RoundStatus {
+    chain: String,
+    height: u64,
+    base_target: u64,
+    scoop: u32,
+    gensig: String,
+    scanning: bool,
+    // time spent on the current round, or the duration of the last one if idle
+    elapsed_ms: i64,
+}
+
+#[derive(Debug, Serialize)]
+struct DriveStatus {
+    drive: String,
+    read_bytes: u64,
+    total_bytes: u64,
+    progress: f64,
+    throughput: u64,
+    read_errors: u64,
+}
+
+#[derive(Debug, Serialize)]
+struct QuarantineStatus {
+    kind: String,
+    name: String,
+    // 0 once the next read probes it again
+    retry_in_s: u64,
+}
+
+impl Metrics {
+    pub fn new_block(&self, chain: &str, height: u64, base_target: u64, scoop: u32, gensig: &str) {
+        let mut inner = self.inner.lock().unwrap();
+        {
+            let chain_metrics = inner.chains.entry(chain.to_owned()).or_default();
+            chain_metrics.height = height;
+            chain_metrics.base_target = base_target;
+            chain_metrics.scoop = scoop;
+            chain_metrics.gensig = gensig.to_owned();
+        }
+        inner.best_deadlines.retain(|(c, _), _| c != chain);
+    }
+
+    // starting a round interrupts the round of any other chain
+    pub fn round_started(&self, chain: &str) {
+        let mut inner = self.inner.lock().unwrap();
+        for (name, chain_metrics) in &mut inner.chains {
+            chain_metrics.scanning = name == chain;
+        }
+        let chain_metrics = inner.chains.entry(chain.to_owned()).or_default();
+        chain_metrics.scanning = true;
+        chain_metrics.round_start = Some(Instant::now());
+    }
+
+    pub fn round_finished(&self, chain: &str, duration_ms: i64) {
+        let mut inner = self.inner.lock().unwrap();
+        let chain_metrics = inner.chains.entry(chain.to_owned()).or_default();
+        chain_metrics.rounds += 1;
+        chain_metrics.round_duration_ms = duration_ms;
+        chain_metrics.scanning = false;
+    }
+
+    pub fn best_deadline(&self, chain: &str, account_id: u64, deadline: u64) {
+        let mut inner = self.inner.lock().unwrap();
+        inner
+            .best_deadlines
+            .insert((chain.to_owned(), account_id), deadline);
+    }
+
+    pub fn drive_started(&self, drive: &str, total_bytes: u64) {
+        let mut inner = self.inner.lock().unwrap();
+        let drive_metrics = inner.drives.entry(drive.to_owned()).or_default();
+        drive_metrics.round_bytes_read = 0;
+        drive_metrics.round_bytes_total = total_bytes;
+    }
+
+    pub fn drive_read(&self, drive: &str, bytes_read: u64) {
+        let mut inner = self.inner.lock().unwrap();
+        let drive_metrics = inner.drives.entry(drive.to_owned()).or_default();
+        drive_metrics.round_bytes_read += bytes_read;
+    }
+
+    pub fn read_error(&self, drive: &str) {
+        let mut inner = self.inner.lock().unwrap();
+        inner
+            .drives
+            .entry(drive.to_owned())
+            .or_default()
+            .read_errors += 1;
+    }
+
+    pub fn quarantined(&self, kind: Kind, name: &str, until: Instant) {
+        let mut inner = self.inner.lock().unwrap();
+        inner
+            .quarantined
+            .insert((kind.to_string(), name.to_owned()), until);
+    }
+
+    pub fn released(&self, kind: Kind, name: &str) {
+        let mut inner = self.inner.lock().unwrap();
+        inner
+            .quarantined
+            .remove(&(kind.to_string(), name.to_owned()));
+    }
+
+    pub fn drive_finished(&self, drive: &str, bytes_read: u64, read_ms: i64) {
+        let mut inner = self.inner.lock().unwrap();
+        let drive_metrics = inner.drives.entry(drive.to_owned()).or_default();
+        drive_metrics.bytes_read += bytes_read;
+        drive_metrics.read_ms += read_ms;
+        drive_metrics.throughput = bytes_read * 1000 / (read_ms + 1) as u64;
+    }
+
+    pub fn submitted(&self, submission: Submission) {
+        let mut inner = self.inner.lock().unwrap();
+        let result = if submission.accepted {
+            "success"
+        } else {
+            "failure"
+        };
+        *inner
+            .submissions
+            .entry((submission.url.clone(), result))
+            .or_insert(0) += 1;
+
+        if inner.last_submissions.len() == MAX_SUBMISSIONS {
+            inner.last_submissions.pop_front();
+        }
+        inner.last_submissions.push_back(submission);
+    }
+
+    pub fn set_plots(&self, plots: Vec<PlotInfo>) {
+        self.inner.lock().unwrap().plots = plots;
+    }
+
+    pub fn status(&self) -> Status {
+        let inner = self.inner.lock().unwrap();
+        Status {
+            rounds: inner
+                .chains
+                .iter()
+                .map(|(chain, m)| RoundStatus {
+                    chain: chain.clone(),
+                    height: m.height,
+                    base_target: m.base_target,
+                    scoop: m.scoop,
+                    gensig: m.gensig.clone(),
+                    scanning: m.scanning,
+                    elapsed_ms: match m.round_start {
+                        Some(start) if m.scanning => {
+                            let elapsed = start.elapsed();
+                            elapsed.as_secs() as i64 * 1000 + i64::from(elapsed.subsec_millis())
+                        }
+                        _ => m.round_duration_ms,
+                    },
+                })
+                .collect(),
+            drives: inner
+                .drives
+                .iter()
+                .map(|(drive, m)| DriveStatus {
+                    drive: drive.clone(),
+                    read_bytes: m.round_bytes_read,
+                    total_bytes: m.round_bytes_total,
+                    progress: if m.round_bytes_total == 0 {
+                        0.0
+                    } else {
+                        m.round_bytes_read as f64 / m.round_bytes_total as f64
+                    },
+                    throughput: m.throughput,
+                    read_errors: m.read_errors,
+                })
+                .collect(),
+            plots: inner.plots.clone(),
+            // newest first
+            submissions: inner.last_submissions.iter().rev().cloned().collect(),
+            quarantined: inner
+                .quarantined
+                .iter()
+                .map(|((kind, name), until)| {
+                    let now = Instant::now();
+                    QuarantineStatus {
+                        kind: kind.clone(),
+                        name: name.clone(),
+                        retry_in_s: if *until > now {
+                            until.duration_since(now).as_secs()
+                        } else {
+                            0
+                        },
+                    }
+                })
+                .collect(),
+        }
+    }
+
+    // queue_depths are sampled by the caller, they are not owned by the miner state
+    pub fn render(&self, queue_depths: &[(&str, usize)]) -> String {
+        let inner = self.inner.lock().unwrap();
+        let mut out = String::new();
+
+        header(&mut out, "height", "gauge", "current block height");
+        for (chain, m) in &inner.chains {
+            sample(&mut out, "height", &[("chain", chain)], m.height);
+        }
+        header(&mut out, "base_target", "gauge", "current base target");
+        for (chain, m) in &inner.chains {
+            sample(&mut out, "base_target", &[("chain", chain)], m.base_target);
+        }
+        header(&mut out, "rounds_total", "counter", "finished rounds");
+        for (chain, m) in &inner.chains {
+            sample(&mut out, "rounds_total", &[("chain", chain)], m.rounds);
+        }
+        header(
+            &mut out,
+            "round_duration_seconds",
+            "gauge",
+            "scan duration of the last finished round",
+        );
+        for (chain, m) in &inner.chains {
+            sample(
+                &mut out,
+                "round_duration_seconds",
+                &[("chain", chain)],
+                m.round_duration_ms as f64 / 1000.0,
+            );
+        }
+
+        header(
+            &mut out,
+            "best_deadline_seconds",
+            "gauge",
+            "best deadline of the current round",
+        );
+        for ((chain, account_id), deadline) in &inner.best_deadlines {
+            sample(
+                &mut out,
+                "best_deadline_seconds",
+                &[("chain", chain), ("account", &account_id.to_string())],
+                deadline,
+            );
+        }
+
+        header(&mut out, "drive_read_bytes_total", "counter", "bytes read");
+        for (drive, m) in &inner.drives {
+            sample(
+                &mut out,
+                "drive_read_bytes_total",
+                &[("drive", drive)],
+                m.bytes_read,
+            );
+        }
+        header(
+            &mut out,
+            "drive_read_seconds_total",
+            "counter",
+            "time spent reading",
+        );
+        for (drive, m) in &inner.drives {
+            sample(
+                &mut out,
+                "drive_read_seconds_total",
+                &[("drive", drive)],
+                m.read_ms as f64 / 1000.0,
+            );
+        }
+        header(
+            &mut out,
+            "drive_throughput_bytes_per_second",
+            "gauge",
+            "read throughput of the last finished round",
+        );
+        for (drive, m) in &inner.drives {
+            sample(
+                &mut out,
+                "drive_throughput_bytes_per_second",
+                &[("drive", drive)],
+                m.throughput,
+            );
+        }
+
+        header(
+            &mut out,
+            "submissions_total",
+            "counter",
+            "nonce submissions",
+        );
+        for ((url, result), count) in &inner.submissions {
+            sample(
+                &mut out,
+                "submissions_total",
+                &[("url", url), ("result", result)],
+                count,
+            );
+        }
+
+        header(
+            &mut out,
+            "buffer_queue_depth",
+            "gauge",
+            "buffers waiting in the queue",
+        );
+        for (queue, depth) in queue_depths {
+            sample(&mut out, "buffer_queue_depth", &[("queue", queue)], depth);
+        }
+
+        out
+    }
+}
+
+fn header(out: &mut String, name: &str, kind: &str, help: &str) {
+    writeln!(out, "# HELP scavenger_{} {}", name, help).unwrap();
+    writeln!(out, "# TYPE scavenger_{} {}", name, kind).unwrap();
+}
+
+fn sample<T: ::std::fmt::Display>(out: &mut String, name: &str, labels: &[(&str, &str)], v: T) {
+    let labels: Vec<String> = labels
+        .iter()
+        .map(|(k, v)| format!("{}=\"{}\"", k, escape(v)))
+        .collect();
+    writeln!(out, "scavenger_{}{{{}}} {}", name, labels.join(","), v).unwrap();
+}
+
+fn escape(v: &str) -> String {
+    v.replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+// serves /metrics and /status, runs on the miner's core
+pub fn serve<F, G>(handle: &Handle, addr: &SocketAddr, render: F, status: G)
+where
+    F: Fn() -> String + 'static,
+    G: Fn() -> Status + 'static,
+{
+    // bound through std and handed over to the reactor the core is driving
+    let listener = match net::TcpListener::bind(addr)
+        .and_then(|l| TcpListener::from_std(l, &reactor::Handle::default()))
+    {
+        Ok(listener) => listener,
+        Err(e) => {
+            error!("api: can't listen on {}: {}", addr, e);
+            return;
+        }
+    };
+    info!("api: listening on http://{}/metrics and /status", addr);
+
+    let render = Rc::new(render);
+    let status = Rc::new(status);
+    let inner_handle = handle.clone();
+    handle.spawn(
+        listener
+            .incoming()
+            .for_each(move |socket| {
+                let render = render.clone();
+                let status = status.clone();
+                let service = service_fn_ok(move |req| match req.uri().path() {
+                    "/metrics" => Response::builder()
+                        .header("Content-Type", "text/plain; version=0.0.4")
+                        .body(Body::from(render()))
+                        .unwrap(),
+                    "/status" => Response::builder()
+                        .header("Content-Type", "application/json")
+                        .body(Body::from(serde_json::to_string(&status()).unwrap()))
+                        .unwrap(),
+                    _ => Response::builder()
+                        .status(StatusCode::NOT_FOUND)
+                        .body(Body::empty())
+                        .unwrap(),
+                });
+                inner_handle.spawn(
+                    Http::new()
+                        .serve_connection(socket, service)
+                        .map_err(|e| warn!("api: connection error: {}", e)),
+                );
+                Ok(())
+            })
+            .map_err(|e| error!("api: listener error: {}", e)),
+    );
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_render() {
+        let metrics = Metrics::default();
+        metrics.new_block("burst", 500_000, 70_000, 7, "abcd");
+        metrics.best_deadline("burst", 42, 1234);
+        metrics.round_finished("burst", 1500);
+        metrics.drive_finished("/dev/sda", 2048, 1999);
+        let mut submission = Submission::new("burst", "http://pool", 42, 500_000, 1, 1234);
+        submission.accepted = true;
+        metrics.submitted(submission.clone());
+        metrics.submitted(submission.clone());
+        submission.accepted = false;
+        metrics.submitted(submission);
+
+        let out = metrics.render(&[("cpu", 3)]);
+        assert!(out.contains("# TYPE scavenger_height gauge\n"));
+        assert!(out.contains("scavenger_height{chain=\"burst\"} 500000\n"));
+        assert!(out.contains("scavenger_base_target{chain=\"burst\"} 70000\n"));
+        assert!(out.contains("scavenger_round_duration_seconds{chain=\"burst\"} 1.5\n"));
+        assert!(
+            out.contains("scavenger_best_deadline_seconds{chain=\"burst\",account=\"42\"} 1234\n")
+        );
+        assert!(
+            out.contains("scavenger_drive_throughput_bytes_per_second{drive=\"/dev/sda\"} 1024\n")
+        );
+        assert!(
+            out.contains("scavenger_submissions_total{url=\"http://pool\",result=\"success\"} 2\n")
+        );
+        assert!(
+            out.contains("scavenger_submissions_total{url=\"http://pool\",result=\"failure\"} 1\n")
+        );
+        assert!(out.contains("scavenger_buffer_queue_depth{queue=\"cpu\"} 3\n"));
+
+        // a new block resets the best deadlines of its chain
+        metrics.new_block("burst", 500_001, 70_000, 8, "ef01");
+        assert!(!metrics.render(&[]).contains("best_deadline_seconds{"));
+    }
+
+    #[test]
+    fn test_status() {
+        let metrics = Metrics::default();
+        metrics.new_block("burst", 500_000, 70_000, 7, "abcd");
+        metrics.new_block("other", 100, 60_000, 9, "ef01");
+        metrics.round_started("other");
+        metrics.round_started("burst");
+        metrics.drive_started("/dev/sda", 4096);
+        metrics.drive_read("/dev/sda", 1024);
+        for i in 0..MAX_SUBMISSIONS as u64 + 5 {
+            metrics.submitted(Submission::new(
+                "burst",
+                "http://pool",
+                42,
+                500_000,
+                i,
+                1234,
+            ));
+        }
+
+        metrics.read_error("/dev/sda");
+        metrics.quarantined(Kind::Drive, "/dev/sda", Instant::now());
+        metrics.quarantined(Kind::Plot, "1_0_8", Instant::now());
+        metrics.released(Kind::Plot, "1_0_8");
+
+        let status = metrics.status();
+        assert_eq!(status.drives[0].read_errors, 1);
+        assert_eq!(status.quarantined.len(), 1);
+        assert_eq!(status.quarantined[0].kind, "drive");
+        assert_eq!(status.quarantined[0].retry_in_s, 0);
+        assert_eq!(status.rounds.len(), 2);
+        assert!(status.rounds[0].scanning);
+        assert_eq!(status.rounds[0].scoop, 7);
+        assert!(!status.rounds[1].scanning);
+        assert_eq!(status.drives[0].progress, 0.25);
+        assert_eq!(status.submissions.len(), MAX_SUBMISSIONS);
+        assert_eq!(status.submissions[0].nonce, MAX_SUBMISSIONS as u64 + 4);
+
+        let json = serde_json::to_string(&status).unwrap();
+        assert!(json.contains("\"gensig\":\"abcd\""));
+    }
+
+    #[test]
+    fn test_escape() {
+        assert_eq!(escape("a\"b\\c\nd"), "a\\\"b\\\\c\\nd");
+    }
+}
@@ -3,20 +3,34 @@ extern crate num_cpus;
 #[cfg(feature = "opencl")]
 extern crate ocl_core as core;
 extern crate page_size;
+#[cfg(unix)]
+extern crate signal_hook;
 
 use burstmath;
 use chan;
-use config::Cfg;
+use config::{parse_option, Cfg, ChainCfg, ConfigError};
 use core_affinity;
-use futures::sync::mpsc;
+use futures::sync::{mpsc, oneshot};
+use journal::{Event, Journal};
+use log4rs;
+use logger;
+use metrics;
+use metrics::{Metrics, PlotInfo};
 use plot::{Plot, SCOOP_SIZE};
-use reader::Reader;
-use requests::RequestHandler;
+use pool;
+use pool::{MiningInfoSource, MockPool, NonceSink};
+use quarantine::Quarantine;
+use reader::{ReadReply, Reader};
+use requests::{FetchError, RequestHandler};
 use std::cell::RefCell;
+use std::cmp::min;
 use std::collections::HashMap;
 use std::fs::read_dir;
-use std::path::Path;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
 use std::rc::Rc;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::RwLock;
 use std::sync::{Arc, Mutex};
 use std::thread;
@@ -25,9 +39,9 @@ use std::u64;
 use stopwatch::Stopwatch;
 use tokio::prelude::*;
 use tokio::timer::Interval;
-use tokio_core::reactor::Core;
+use tokio_core::reactor::{Core, Handle};
 use utils::get_device_id;
-use worker::{create_worker_task, NonceData};
+use worker::{cpu_buffer_nonces, create_worker_task, NonceData, SimdExtension};
 
 #[cfg(feature = "opencl")]
 use ocl::GpuBuffer;
@@ -36,27 +50,452 @@ use ocl::GpuContext;
 
 pub struct Miner {
     reader: Reader,
-    request_handler: RequestHandler,
+    chains: Vec<Chain>,
     rx_nonce_data: mpsc::Receiver<NonceData>,
-    target_deadline: u64,
     state: Arc<Mutex<State>>,
-    reader_task_count: usize,
+    plot_scanner: PlotScanner,
+    config_reload: ConfigReload,
     get_mining_info_interval: u64,
     core: Core,
     wakeup_after: i64,
+    deadline_verification: DeadlineVerification,
+    metrics: Arc<Metrics>,
+    journal: Arc<Journal>,
+    api_address: Option<SocketAddr>,
+    // only sampled for the metrics
+    rx_empty_buffers: chan::Receiver<Box<Buffer + Send>>,
+    rx_read_replies_cpu: chan::Receiver<ReadReply>,
+    rx_read_replies_gpu: chan::Receiver<ReadReply>,
+}
+
+// pool settings of a chain, its round state lives in ChainState
+#[derive(Clone)]
+struct Chain {
+    name: String,
+    source: Rc<MiningInfoSource>,
+    sink: Rc<NonceSink>,
+    accounts: Vec<u64>,
+    target_deadline: u64,
+    target_deadline_source: TargetDeadlineSource,
+    poc2_start_height: u64,
+    // accounts mined solo, their deadlines are verified before submitting
+    solo_accounts: Vec<u64>,
+}
+
+impl Chain {
+    // source and sink default to the ones of the config, fails on unknown ones
+    fn new(
+        chain_cfg: &ChainCfg,
+        cfg: &Cfg,
+        metrics: &Arc<Metrics>,
+        journal: &Arc<Journal>,
+        handle: &Handle,
+        source: Option<Rc<MiningInfoSource>>,
+        sink: Option<Rc<NonceSink>>,
+    ) -> Result<Chain, String> {
+        let mut urls = vec![chain_cfg.url.clone()];
+        urls.extend(chain_cfg.fallback_urls.iter().cloned());
+        let request_handler = Rc::new(RequestHandler::new(
+            &chain_cfg.name,
+            urls,
+            chain_cfg.account_id_to_secret_phrase.clone(),
+            cfg.timeout,
+            cfg.failover_after,
+            metrics.clone(),
+            journal.clone(),
+            handle.clone(),
+        ));
+        let source =
+            match source {
+                Some(source) => source,
+                None => match chain_cfg.mining_info_source.as_str() {
+                    "http" => request_handler.clone() as Rc<MiningInfoSource>,
+                    s if s.starts_with("replay:") => {
+                        let path = &s["replay:".len()..];
+                        Rc::new(pool::replay_source(path).map_err(|e| {
+                            format!("chain {}: replay {}: {}", chain_cfg.name, path, e)
+                        })?)
+                    }
+                    s => {
+                        return Err(format!(
+                            "chain {}: unknown mining_info_source {}",
+                            chain_cfg.name, s
+                        ))
+                    }
+                },
+            };
+        let sink = match sink {
+            Some(sink) => sink,
+            None => match chain_cfg.nonce_sink.as_str() {
+                "http" => request_handler as Rc<NonceSink>,
+                "mock" => Rc::new(MockPool::new()),
+                s => {
+                    return Err(format!(
+                        "chain {}: unknown nonce_sink {}",
+                        chain_cfg.name, s
+                    ))
+                }
+            },
+        };
+        Ok(Chain {
+            name: chain_cfg.name.clone(),
+            source,
+            sink,
+            accounts: chain_cfg.accounts.clone(),
+            target_deadline: chain_cfg.target_deadline,
+            target_deadline_source: parse_cfg_option(
+                &format!("chain {}: target_deadline_source", chain_cfg.name),
+                &chain_cfg.target_deadline_source,
+            )?,
+            poc2_start_height: chain_cfg.poc2_start_height,
+            solo_accounts: chain_cfg
+                .account_id_to_secret_phrase
+                .keys()
+                .cloned()
+                .collect(),
+        })
+    }
 }
 
 pub struct State {
-    height: u64,
-    account_id_to_best_deadline: HashMap<u64, u64>,
-    base_target: u64,
+    chains: Vec<ChainState>,
+    // chain the reader is currently scanning
+    scanning: Option<usize>,
+    // chains with a new block waiting for the reader
+    queue: Vec<usize>,
     sw: Stopwatch,
-    scanning: bool,
 
     // count how many reader's scoops have been processed
     processed_reader_tasks: usize,
 }
 
+#[derive(Clone)]
+pub struct ChainState {
+    name: String,
+    // prefixed to log messages if more than one chain is mined
+    prefix: String,
+    priority: u8,
+    height: u64,
+    account_id_to_best_deadline: HashMap<u64, u64>,
+    base_target: u64,
+    target_deadline: u64,
+    gensig: [u8; 32],
+    scoop: u32,
+    poc2: bool,
+    // submissions go to the url that issued the current block
+    endpoint: String,
+}
+
+impl ChainState {
+    fn new(chain_cfg: &ChainCfg, multi_chain: bool) -> ChainState {
+        ChainState {
+            name: chain_cfg.name.clone(),
+            prefix: if multi_chain {
+                format!("{}: ", chain_cfg.name)
+            } else {
+                "".to_owned()
+            },
+            priority: chain_cfg.priority,
+            height: 0,
+            account_id_to_best_deadline: HashMap::new(),
+            base_target: 1,
+            target_deadline: chain_cfg.target_deadline,
+            gensig: [0; 32],
+            scoop: 0,
+            poc2: false,
+            endpoint: String::new(),
+        }
+    }
+
+    // a deadline of the current block that beats the best one of its account and is wanted
+    fn improves(&self, chain: &Chain, nonce_data: &NonceData, deadline: u64) -> bool {
+        let best_deadline = *self
+            .account_id_to_best_deadline
+            .get(&nonce_data.account_id)
+            .unwrap_or(&u64::MAX);
+        nonce_data.height == self.height
+            && best_deadline > deadline
+            && deadline < self.target_deadline
+            && (chain.accounts.is_empty() || chain.accounts.contains(&nonce_data.account_id))
+    }
+
+    fn submit(
+        &mut self,
+        chain: &Chain,
+        nonce_data: &NonceData,
+        deadline: u64,
+        metrics: &Metrics,
+        handle: &Handle,
+    ) {
+        self.account_id_to_best_deadline
+            .insert(nonce_data.account_id, deadline);
+        metrics.best_deadline(&chain.name, nonce_data.account_id, deadline);
+        chain.sink.submit_nonce(
+            handle,
+            &self.endpoint,
+            nonce_data.account_id,
+            nonce_data.nonce,
+            nonce_data.height,
+            deadline,
+        );
+
+        let _event = logger::event(
+            "deadline_found",
+            &[
+                ("chain", chain.name.clone()),
+                ("height", nonce_data.height.to_string()),
+                ("account", nonce_data.account_id.to_string()),
+                ("nonce", nonce_data.nonce.to_string()),
+                ("deadline", deadline.to_string()),
+            ],
+        );
+        info!(
+            "{}deadline found: account={}, nonce={}, deadline={}",
+            self.prefix,
+            burstmath::to_rs_address(nonce_data.account_id),
+            nonce_data.nonce,
+            deadline
+        );
+    }
+}
+
+impl State {
+    fn new(chains: Vec<ChainState>) -> State {
+        State {
+            chains,
+            scanning: None,
+            queue: Vec::new(),
+            sw: Stopwatch::new(),
+            processed_reader_tasks: 0,
+        }
+    }
+
+    // decides whether a new block of chain can be scanned right away, otherwise it is queued
+    fn schedule(&mut self, chain: usize) -> bool {
+        match self.scanning {
+            None => true,
+            Some(scanning) if scanning == chain => true,
+            Some(scanning) if self.chains[chain].priority > self.chains[scanning].priority => {
+                // the interrupted round is scanned again once the reader is idle
+                self.enqueue(scanning);
+                true
+            }
+            Some(_) => {
+                self.enqueue(chain);
+                false
+            }
+        }
+    }
+
+    fn enqueue(&mut self, chain: usize) {
+        if !self.queue.contains(&chain) {
+            self.queue.push(chain);
+        }
+    }
+
+    // highest priority first, first come first served among equals
+    fn next_queued(&mut self) -> Option<usize> {
+        let chains = &self.chains;
+        let next = self
+            .queue
+            .iter()
+            .enumerate()
+            .max_by_key(|&(i, &chain)| (chains[chain].priority, -(i as isize)))
+            .map(|(i, _)| i);
+        next.map(|i| self.queue.remove(i))
+    }
+
+    fn start_round(&mut self, reader: &mut Reader, metrics: &Metrics, chain: usize) {
+        self.queue.retain(|&c| c != chain);
+        {
+            let chain_state = &self.chains[chain];
+            metrics.round_started(&chain_state.name);
+            reader.start_reading(
+                chain,
+                chain_state.height,
+                chain_state.scoop,
+                chain_state.poc2,
+                &Arc::new(chain_state.gensig),
+            );
+        }
+        self.sw.restart();
+        self.processed_reader_tasks = 0;
+        self.scanning = Some(chain);
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TargetDeadlineSource {
+    // the lower of the pool's and the configured target deadline
+    Min,
+    Pool,
+    Config,
+}
+
+impl FromStr for TargetDeadlineSource {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<TargetDeadlineSource, String> {
+        parse_option(
+            s,
+            &[
+                ("min", TargetDeadlineSource::Min),
+                ("pool", TargetDeadlineSource::Pool),
+                ("config", TargetDeadlineSource::Config),
+            ],
+        )
+    }
+}
+
+impl TargetDeadlineSource {
+    // pools that don't send a target deadline are treated as u64::MAX
+    fn target_deadline(self, cfg_target_deadline: u64, pool_target_deadline: u64) -> u64 {
+        match self {
+            TargetDeadlineSource::Min => min(cfg_target_deadline, pool_target_deadline),
+            TargetDeadlineSource::Pool => pool_target_deadline,
+            TargetDeadlineSource::Config => cfg_target_deadline,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DeadlineVerification {
+    Off,
+    // log mismatches, submit anyway
+    Log,
+    Refuse,
+}
+
+impl FromStr for DeadlineVerification {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<DeadlineVerification, String> {
+        parse_option(
+            s,
+            &[
+                ("off", DeadlineVerification::Off),
+                ("log", DeadlineVerification::Log),
+                ("refuse", DeadlineVerification::Refuse),
+            ],
+        )
+    }
+}
+
+impl DeadlineVerification {
+    // recomputes a deadline before it is submitted, false if it has to be dropped
+    fn check(self, chain_state: &ChainState, nonce_data: &NonceData, deadline: u64) -> bool {
+        if self == DeadlineVerification::Off {
+            return true;
+        }
+        let verified = burstmath::calculate_deadline(
+            nonce_data.account_id,
+            nonce_data.nonce,
+            chain_state.scoop,
+            chain_state.poc2,
+            &chain_state.gensig,
+        ) / chain_state.base_target;
+        if verified == deadline {
+            return true;
+        }
+        error!(
+            "{}deadline mismatch (plot or kernel broken?): height={}, account={}, nonce={}, \
+             deadline={}, verified={}{}",
+            chain_state.prefix,
+            nonce_data.height,
+            burstmath::to_rs_address(nonce_data.account_id),
+            nonce_data.nonce,
+            deadline,
+            verified,
+            if self == DeadlineVerification::Refuse {
+                " -> not submitted"
+            } else {
+                ""
+            }
+        );
+        self != DeadlineVerification::Refuse
+    }
+}
+
+// regenerating the nonce takes a while, it runs on its own thread so that neither the event loop
+// nor the state lock wait for it
+fn verify_and_submit(
+    deadline_verification: DeadlineVerification,
+    state: &Arc<Mutex<State>>,
+    chain_state: ChainState,
+    chain: Chain,
+    nonce_data: NonceData,
+    metrics: &Arc<Metrics>,
+    handle: &Handle,
+) {
+    let deadline = nonce_data.deadline / chain_state.base_target;
+    let (tx_verified, rx_verified) = oneshot::channel();
+    let nonce = nonce_data.clone();
+    thread::spawn(move || {
+        let _ = tx_verified.send(deadline_verification.check(&chain_state, &nonce, deadline));
+    });
+    let state = state.clone();
+    let metrics = metrics.clone();
+    let inner_handle = handle.clone();
+    handle.spawn(rx_verified.then(move |verified| {
+        if let Ok(true) = verified {
+            let mut state = state.lock().unwrap();
+            let chain_state = &mut state.chains[nonce_data.chain];
+            // the next block or a better deadline may have come in meanwhile
+            if chain_state.improves(&chain, &nonce_data, deadline) {
+                chain_state.submit(&chain, &nonce_data, deadline, &metrics, &inner_handle);
+            }
+        }
+        Ok(())
+    }));
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SecretPhraseCheck {
+    Off,
+    Warn,
+    // don't start with a passphrase that doesn't belong to its account id
+    Refuse,
+}
+
+impl FromStr for SecretPhraseCheck {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<SecretPhraseCheck, String> {
+        parse_option(
+            s,
+            &[
+                ("off", SecretPhraseCheck::Off),
+                ("warn", SecretPhraseCheck::Warn),
+                ("refuse", SecretPhraseCheck::Refuse),
+            ],
+        )
+    }
+}
+
+impl SecretPhraseCheck {
+    // false if a wrong passphrase is refused
+    fn check(self, chain_cfg: &ChainCfg) -> bool {
+        if self == SecretPhraseCheck::Off {
+            return true;
+        }
+        let mismatches = chain_cfg.secret_phrase_mismatches();
+        for &(account_id, derived) in &mismatches {
+            let msg = format!(
+                "{}: the secret phrase of {} belongs to {}",
+                chain_cfg.name,
+                burstmath::to_rs_address(account_id),
+                burstmath::to_rs_address(derived)
+            );
+            if self == SecretPhraseCheck::Refuse {
+                error!("{}", msg);
+            } else {
+                warn!("{}", msg);
+            }
+        }
+        self != SecretPhraseCheck::Refuse || mismatches.is_empty()
+    }
+}
+
 pub trait Buffer {
     fn get_buffer(&mut self) -> Arc<Mutex<Vec<u8>>>;
 
@@ -129,6 +568,14 @@ fn scan_plots(
         for file in read_dir(dir).unwrap() {
             let file = &file.unwrap().path();
 
+            // the plotter removes the progress file once the plot is complete
+            let mut progress = file.clone().into_os_string();
+            progress.push(".progress");
+            if Path::new(&progress).exists() {
+                info!("path={} is still being plotted, skipped", file.display());
+                continue;
+            }
+
             if let Ok(p) = Plot::new(file, use_direct_io, dummy) {
                 let drive_id = get_device_id(&file.to_str().unwrap().to_string());
                 let plots = drive_id_to_plots
@@ -162,13 +609,411 @@ fn scan_plots(
     (drive_id_to_plots, global_capacity * 64)
 }
 
-impl Miner {
-    pub fn new(cfg: Cfg) -> Miner {
-        let (drive_id_to_plots, total_size) = scan_plots(
-            &cfg.plot_dirs,
-            cfg.hdd_use_direct_io,
-            cfg.benchmark_only.to_uppercase() == "XPU",
+// keeps the reader's plots in sync with plot_dirs
+struct PlotScanner {
+    plot_dirs: Vec<String>,
+    use_direct_io: bool,
+    dummy: bool,
+    rescan_after_round: bool,
+    // files of the last scan, a changed listing triggers a rescan
+    listing: Vec<PathBuf>,
+    // set by SIGHUP
+    rescan_requested: Arc<AtomicBool>,
+}
+
+impl PlotScanner {
+    fn list_files(&self) -> Vec<PathBuf> {
+        let mut listing = Vec::new();
+        for plot_dir in &self.plot_dirs {
+            if let Ok(entries) = read_dir(plot_dir) {
+                listing.extend(entries.filter_map(|e| e.ok()).map(|e| e.path()));
+            }
+        }
+        listing.sort();
+        listing
+    }
+
+    fn scan(&mut self) -> (HashMap<String, Arc<Mutex<Vec<RwLock<Plot>>>>>, u64) {
+        self.listing = self.list_files();
+        scan_plots(&self.plot_dirs, self.use_direct_io, self.dummy)
+    }
+
+    // must only be called while no scan is in progress
+    fn rescan(&mut self, reader: &mut Reader, metrics: &Metrics, round_finished: bool) {
+        let requested = self.rescan_requested.swap(false, Ordering::Relaxed);
+        if !(requested || round_finished && self.rescan_after_round) {
+            return;
+        }
+        if !requested && self.list_files() == self.listing {
+            return;
+        }
+
+        info!("{: <80}", "rescanning plot dirs");
+        let (drive_id_to_plots, total_size) = self.scan();
+        metrics.set_plots(plot_inventory(&drive_id_to_plots));
+        reader.update_plots(drive_id_to_plots, total_size);
+    }
+}
+
+fn plot_inventory(
+    drive_id_to_plots: &HashMap<String, Arc<Mutex<Vec<RwLock<Plot>>>>>,
+) -> Vec<PlotInfo> {
+    let mut inventory = Vec::new();
+    for (drive, plots) in drive_id_to_plots {
+        for p in plots.lock().unwrap().iter() {
+            let p = p.read().unwrap();
+            inventory.push(PlotInfo {
+                name: p.name.clone(),
+                drive: drive.clone(),
+                account_id: p.account_id,
+                start_nonce: p.start_nonce,
+                nonces: p.nonces,
+                poc2: p.poc2,
+            });
+        }
+    }
+    inventory.sort_by(|a, b| a.name.cmp(&b.name));
+    inventory
+}
+
+// settings applied by a reload, a change of any other one is only reported
+const RELOADABLE_SETTINGS: &[&str] = &[
+    "account_id_to_secret_phrase",
+    "secret_phrases",
+    "secret_phrase_check",
+    "plot_dirs",
+    "rescan_plot_dirs",
+    "url",
+    "fallback_urls",
+    "failover_after",
+    "timeout",
+    "target_deadline",
+    "target_deadline_source",
+    "poc2_start_height",
+    "mining_info_source",
+    "nonce_sink",
+    "chains",
+    "console_log_level",
+    "logfile_log_level",
+    "logfile_max_count",
+    "logfile_max_size",
+    "logfile_dir",
+    "logfile_format",
+    "syslog_log_level",
+    "console_log_pattern",
+    "logfile_log_pattern",
+];
+
+// settings the chains are built from, they only apply together
+const CHAIN_SETTINGS: &[&str] = &[
+    "account_id_to_secret_phrase",
+    "secret_phrases",
+    "secret_phrase_check",
+    "url",
+    "fallback_urls",
+    "failover_after",
+    "timeout",
+    "target_deadline",
+    "target_deadline_source",
+    "poc2_start_height",
+    "mining_info_source",
+    "nonce_sink",
+    "chains",
+];
+
+type ConfigLoader = Box<Fn() -> Result<Cfg, Vec<ConfigError>>>;
+
+// re-reads the config on SIGHUP, the changes are applied between rounds
+struct ConfigReload {
+    requested: Arc<AtomicBool>,
+    load: Option<ConfigLoader>,
+    log_handle: Option<log4rs::Handle>,
+    cfg: Cfg,
+    // handed to the builder, they survive reloads
+    sources: HashMap<String, Rc<MiningInfoSource>>,
+    sinks: HashMap<String, Rc<NonceSink>>,
+}
+
+impl ConfigReload {
+    // must only be called while no scan is in progress
+    fn reload(
+        &mut self,
+        chains: &mut [Chain],
+        state: &mut State,
+        plot_scanner: &mut PlotScanner,
+        metrics: &Arc<Metrics>,
+        journal: &Arc<Journal>,
+        handle: &Handle,
+    ) {
+        if !self.requested.swap(false, Ordering::Relaxed) {
+            return;
+        }
+        let cfg = match self.load {
+            Some(ref load) => load(),
+            None => return,
+        };
+        let cfg = match cfg {
+            Ok(cfg) => cfg,
+            Err(errors) => {
+                for e in errors {
+                    error!("reload: {}", e);
+                }
+                warn!("{: <80}", "reload: config invalid, keeping the old one");
+                return;
+            }
+        };
+
+        let mut cfg = cfg;
+        let changed = self.cfg.changed_settings(&cfg);
+        let (mut applied, restart): (Vec<String>, Vec<String>) = changed
+            .into_iter()
+            .partition(|setting| RELOADABLE_SETTINGS.contains(&setting.as_str()));
+        if !restart.is_empty() {
+            warn!(
+                "{: <80}",
+                format!("reload: {} only change on restart", restart.join(", "))
+            );
+        }
+
+        if !self.reload_chains(&cfg, chains, state, metrics, journal, handle) {
+            // the next reload compares against the chains that are actually running
+            cfg.keep_chain_settings(&self.cfg);
+            applied.retain(|setting| !CHAIN_SETTINGS.contains(&setting.as_str()));
+        }
+
+        plot_scanner.plot_dirs = cfg.plot_dirs.clone();
+        plot_scanner.rescan_after_round = cfg.rescan_plot_dirs;
+        if let Some(ref log_handle) = self.log_handle {
+            logger::reload_logger(log_handle, &cfg);
+        }
+        self.cfg = cfg;
+        info!(
+            "{: <80}",
+            if applied.is_empty() {
+                "reload: nothing to apply".to_owned()
+            } else {
+                format!("reload: applied {}", applied.join(", "))
+            }
         );
+    }
+
+    // all chains are rebuilt or none, false if the old ones are kept
+    fn reload_chains(
+        &self,
+        cfg: &Cfg,
+        chains: &mut [Chain],
+        state: &mut State,
+        metrics: &Arc<Metrics>,
+        journal: &Arc<Journal>,
+        handle: &Handle,
+    ) -> bool {
+        let chain_cfgs = cfg.chains();
+        let old_chain_cfgs = self.cfg.chains();
+        if chain_cfgs.len() != chains.len()
+            || chain_cfgs
+                .iter()
+                .zip(chains.iter())
+                .any(|(chain_cfg, chain)| chain_cfg.name != chain.name)
+        {
+            warn!(
+                "{: <80}",
+                "reload: added, removed or renamed chains need a restart"
+            );
+            return false;
+        }
+        let new_chains = chain_cfgs
+            .iter()
+            .enumerate()
+            .map(|(i, chain_cfg)| {
+                let secret_phrase_check: SecretPhraseCheck =
+                    parse_cfg_option("secret_phrase_check", &cfg.secret_phrase_check)?;
+                if !secret_phrase_check.check(chain_cfg) {
+                    return Err(format!(
+                        "chain {}: wrong secret phrases in account_id_to_secret_phrase",
+                        chain_cfg.name
+                    ));
+                }
+                // keep the endpoints and their failover state if they are the same
+                let keep = same_endpoints(chain_cfg, &old_chain_cfgs[i])
+                    && cfg.timeout == self.cfg.timeout
+                    && cfg.failover_after == self.cfg.failover_after;
+                let source = self.sources.get(&chain_cfg.name).cloned().or_else(|| {
+                    if keep {
+                        Some(chains[i].source.clone())
+                    } else {
+                        None
+                    }
+                });
+                let sink = self.sinks.get(&chain_cfg.name).cloned().or_else(|| {
+                    if keep {
+                        Some(chains[i].sink.clone())
+                    } else {
+                        None
+                    }
+                });
+                Chain::new(chain_cfg, cfg, metrics, journal, handle, source, sink)
+            })
+            .collect::<Result<Vec<Chain>, String>>();
+        match new_chains {
+            Ok(new_chains) => {
+                for (i, chain) in new_chains.into_iter().enumerate() {
+                    chains[i] = chain;
+                    state.chains[i].priority = chain_cfgs[i].priority;
+                }
+                true
+            }
+            Err(e) => {
+                error!("reload: {}", e);
+                warn!(
+                    "{: <80}",
+                    "reload: chains not reloaded, keeping the old ones"
+                );
+                false
+            }
+        }
+    }
+}
+
+// validated configs always parse, the error is for configs that skipped the validation
+fn parse_cfg_option<T: FromStr<Err = String>>(field: &str, value: &str) -> Result<T, String> {
+    value.parse().map_err(|e| format!("{}: {}", field, e))
+}
+
+fn same_endpoints(a: &ChainCfg, b: &ChainCfg) -> bool {
+    a.url == b.url
+        && a.fallback_urls == b.fallback_urls
+        && a.account_id_to_secret_phrase == b.account_id_to_secret_phrase
+        && a.mining_info_source == b.mining_info_source
+        && a.nonce_sink == b.nonce_sink
+}
+
+// builds a Miner from a config, the http RequestHandler is the default source and sink of a chain
+pub struct MinerBuilder {
+    cfg: Cfg,
+    simd_ext: SimdExtension,
+    core: Core,
+    metrics: Arc<Metrics>,
+    sources: HashMap<String, Rc<MiningInfoSource>>,
+    sinks: HashMap<String, Rc<NonceSink>>,
+    load: Option<ConfigLoader>,
+    log_handle: Option<log4rs::Handle>,
+}
+
+impl MinerBuilder {
+    pub fn new(cfg: Cfg) -> MinerBuilder {
+        MinerBuilder {
+            simd_ext: SimdExtension::from_cfg(&cfg.cpu_simd_extension),
+            cfg,
+            core: Core::new().unwrap(),
+            metrics: Arc::new(Metrics::default()),
+            sources: HashMap::new(),
+            sinks: HashMap::new(),
+            load: None,
+            log_handle: None,
+        }
+    }
+
+    // the event loop the miner runs on, for sources and sinks that need to spawn futures
+    pub fn handle(&self) -> Handle {
+        self.core.handle()
+    }
+
+    pub fn simd_extension(mut self, simd_ext: SimdExtension) -> MinerBuilder {
+        self.simd_ext = simd_ext;
+        self
+    }
+
+    // chain is the name of a configured chain, "default" if there is no chains section
+    pub fn mining_info_source(mut self, chain: &str, source: Rc<MiningInfoSource>) -> MinerBuilder {
+        self.sources.insert(chain.to_owned(), source);
+        self
+    }
+
+    pub fn nonce_sink(mut self, chain: &str, sink: Rc<NonceSink>) -> MinerBuilder {
+        self.sinks.insert(chain.to_owned(), sink);
+        self
+    }
+
+    // re-reads the config on SIGHUP, without it SIGHUP only rescans the plot dirs
+    pub fn config_loader<F>(mut self, load: F) -> MinerBuilder
+    where
+        F: Fn() -> Result<Cfg, Vec<ConfigError>> + 'static,
+    {
+        self.load = Some(Box::new(load));
+        self
+    }
+
+    // log levels and patterns of a reloaded config are applied to it
+    pub fn log_handle(mut self, log_handle: log4rs::Handle) -> MinerBuilder {
+        self.log_handle = Some(log_handle);
+        self
+    }
+
+    // fails on a source or sink that can't be set up and on refused secret phrases
+    pub fn build(self) -> Result<Miner, String> {
+        for chain in self.sources.keys().chain(self.sinks.keys()) {
+            if !self.cfg.chains().iter().any(|c| &c.name == chain) {
+                warn!("source or sink for unknown chain {} ignored", chain);
+            }
+        }
+        let cfg = self.cfg;
+        let simd_ext = self.simd_ext;
+        simd_ext.init();
+        let core = self.core;
+        let metrics = self.metrics;
+        let sources = self.sources;
+        let sinks = self.sinks;
+
+        let chain_cfgs = cfg.chains();
+        let secret_phrase_check: SecretPhraseCheck =
+            parse_cfg_option("secret_phrase_check", &cfg.secret_phrase_check)?;
+        let deadline_verification =
+            parse_cfg_option("deadline_verification", &cfg.deadline_verification)?;
+        let journal_format = parse_cfg_option("journal_format", &cfg.journal_format)?;
+        for chain_cfg in &chain_cfgs {
+            if !secret_phrase_check.check(chain_cfg) {
+                return Err(format!(
+                    "chain {}: wrong secret phrases in account_id_to_secret_phrase",
+                    chain_cfg.name
+                ));
+            }
+        }
+        // blocks, rounds and submissions are recorded from now on
+        let journal = if cfg.journal_file.is_empty() {
+            Journal::disabled()
+        } else {
+            match Journal::open(&cfg.journal_file, journal_format) {
+                Ok(journal) => journal,
+                Err(e) => {
+                    warn!("journal: {}: {} -> disabled", cfg.journal_file, e);
+                    Journal::disabled()
+                }
+            }
+        };
+        let journal = Arc::new(journal);
+        let chains = chain_cfgs
+            .iter()
+            .map(|chain_cfg| {
+                Chain::new(
+                    chain_cfg,
+                    &cfg,
+                    &metrics,
+                    &journal,
+                    &core.handle(),
+                    sources.get(&chain_cfg.name).cloned(),
+                    sinks.get(&chain_cfg.name).cloned(),
+                )
+            }).collect::<Result<Vec<Chain>, String>>()?;
+
+        let mut plot_scanner = PlotScanner {
+            plot_dirs: cfg.plot_dirs.clone(),
+            use_direct_io: cfg.hdd_use_direct_io,
+            dummy: cfg.benchmark_only.to_uppercase() == "XPU",
+            rescan_after_round: cfg.rescan_plot_dirs,
+            listing: Vec::new(),
+            rescan_requested: Arc::new(AtomicBool::new(false)),
+        };
+        let (drive_id_to_plots, total_size) = plot_scanner.scan();
 
         let reader_thread_count = if cfg.hdd_reader_thread_count == 0 {
             drive_id_to_plots.len()
@@ -185,7 +1030,7 @@ impl Miner {
         );
 
         let buffer_count = cpu_worker_thread_count * 2 + gpu_worker_thread_count * 2;
-        let buffer_size_cpu = cfg.cpu_nonces_per_cache * SCOOP_SIZE as usize;
+        let buffer_size_cpu = cpu_buffer_nonces(cfg.cpu_nonces_per_cache) * SCOOP_SIZE as usize;
 
         let (tx_empty_buffers, rx_empty_buffers) = chan::bounded(buffer_count as usize);
         let (tx_read_replies_cpu, rx_read_replies_cpu) = chan::bounded(cpu_worker_thread_count * 2);
@@ -229,6 +1074,7 @@ impl Miner {
                 }
                 create_worker_task(
                     cfg.benchmark_only.to_uppercase() == "I/O",
+                    simd_ext,
                     rx_read_replies_cpu.clone(),
                     tx_empty_buffers.clone(),
                     tx_nonce_data.clone(),
@@ -240,6 +1086,7 @@ impl Miner {
             thread::spawn({
                 create_worker_task(
                     cfg.benchmark_only.to_uppercase() == "I/O",
+                    simd_ext,
                     rx_read_replies_gpu.clone(),
                     tx_empty_buffers.clone(),
                     tx_nonce_data.clone(),
@@ -247,160 +1094,809 @@ impl Miner {
             });
         }
 
-        let core = Core::new().unwrap();
-        Miner {
-            reader_task_count: drive_id_to_plots.len(),
+        let api_address = if cfg.api_address.is_empty() {
+            None
+        } else {
+            match cfg.api_address.parse() {
+                Ok(addr) => Some(addr),
+                Err(e) => {
+                    warn!(
+                        "api: invalid address {}: {} -> disabled",
+                        cfg.api_address, e
+                    );
+                    None
+                }
+            }
+        };
+
+        metrics.set_plots(plot_inventory(&drive_id_to_plots));
+
+        let multi_chain = chain_cfgs.len() > 1;
+        let chain_states = chain_cfgs
+            .iter()
+            .map(|chain_cfg| ChainState::new(chain_cfg, multi_chain))
+            .collect();
+
+        Ok(Miner {
             reader: Reader::new(
                 drive_id_to_plots,
                 total_size,
                 reader_thread_count,
-                rx_empty_buffers,
+                rx_empty_buffers.clone(),
                 tx_read_replies_cpu,
                 tx_read_replies_gpu,
                 cfg.show_progress,
                 cfg.show_drive_stats,
+                metrics.clone(),
+                Arc::new(Quarantine::new(
+                    cfg.hdd_quarantine_after,
+                    cfg.hdd_quarantine_backoff,
+                )),
             ),
+            chains,
             rx_nonce_data,
-            target_deadline: cfg.target_deadline,
-            request_handler: RequestHandler::new(
-                cfg.url,
-                cfg.account_id_to_secret_phrase,
-                cfg.timeout,
-                core.handle(),
-            ),
-            state: Arc::new(Mutex::new(State {
-                height: 0,
-                account_id_to_best_deadline: HashMap::new(),
-                base_target: 1,
-                processed_reader_tasks: 0,
-                sw: Stopwatch::new(),
-                scanning: false,
-            })),
+            state: Arc::new(Mutex::new(State::new(chain_states))),
+            plot_scanner,
             get_mining_info_interval: cfg.get_mining_info_interval,
             core,
             wakeup_after: cfg.hdd_wakeup_after * 1000, // ms -> s
-        }
+            deadline_verification,
+            metrics,
+            journal,
+            api_address,
+            rx_empty_buffers,
+            rx_read_replies_cpu,
+            rx_read_replies_gpu,
+            config_reload: ConfigReload {
+                requested: Arc::new(AtomicBool::new(false)),
+                load: self.load,
+                log_handle: self.log_handle,
+                cfg,
+                sources,
+                sinks,
+            },
+        })
+    }
+}
+
+impl Miner {
+    pub fn run(self) {
+        self.run_until(future::empty::<(), ()>());
     }
 
-    pub fn run(mut self) {
+    // mines until shutdown completes or fails, e.g. the receiver of a oneshot channel
+    pub fn run_until<F: Future>(mut self, shutdown: F) {
         let handle = self.core.handle();
-        let request_handler = self.request_handler.clone();
+
+        if let Some(addr) = self.api_address {
+            let metrics = self.metrics.clone();
+            let rx_empty_buffers = self.rx_empty_buffers.clone();
+            let rx_read_replies_cpu = self.rx_read_replies_cpu.clone();
+            let rx_read_replies_gpu = self.rx_read_replies_gpu.clone();
+            let status_metrics = metrics.clone();
+            metrics::serve(
+                &handle,
+                &addr,
+                move || {
+                    metrics.render(&[
+                        ("empty", rx_empty_buffers.len()),
+                        ("cpu", rx_read_replies_cpu.len()),
+                        ("gpu", rx_read_replies_gpu.len()),
+                    ])
+                },
+                move || status_metrics.status(),
+            );
+        }
 
         // you left me no choice!!! at least not one that I could have worked out in two weeks...
         let reader = Rc::new(RefCell::new(self.reader));
 
-        let state = self.state.clone();
+        #[cfg(unix)]
+        {
+            for flag in &[
+                self.plot_scanner.rescan_requested.clone(),
+                self.config_reload.requested.clone(),
+            ] {
+                if let Err(e) = signal_hook::flag::register(signal_hook::SIGHUP, flag.clone()) {
+                    warn!("can't register SIGHUP handler: {}", e);
+                }
+            }
+        }
+        let plot_scanner = Rc::new(RefCell::new(self.plot_scanner));
+        let config_reload = Rc::new(RefCell::new(self.config_reload));
+        let chains = Rc::new(RefCell::new(self.chains));
+
         // there might be a way to solve this without two nested moves
         let get_mining_info_interval = self.get_mining_info_interval;
         let wakeup_after = self.wakeup_after;
-        handle.spawn(
-            Interval::new(
-                Instant::now(),
-                Duration::from_millis(get_mining_info_interval),
-            ).for_each(move |_| {
-                let state = state.clone();
-                let reader = reader.clone();
-                request_handler.get_mining_info().then(move |mining_info| {
-                    match mining_info {
-                        Ok(mining_info) => {
-                            let mut state = state.lock().unwrap();
-                            if mining_info.height > state.height {
-                                for best_deadlines in state.account_id_to_best_deadline.values_mut()
-                                {
-                                    *best_deadlines = u64::MAX;
+        for id in 0..chains.borrow().len() {
+            let metrics = self.metrics.clone();
+            let journal = self.journal.clone();
+            let state = self.state.clone();
+            let reader = reader.clone();
+            let plot_scanner = plot_scanner.clone();
+            let config_reload = config_reload.clone();
+            let chains = chains.clone();
+            let inner_handle = handle.clone();
+            handle.spawn(
+                Interval::new(
+                    Instant::now(),
+                    Duration::from_millis(get_mining_info_interval),
+                ).for_each(move |_| {
+                    let metrics = metrics.clone();
+                    let journal = journal.clone();
+                    let state = state.clone();
+                    let reader = reader.clone();
+                    let plot_scanner = plot_scanner.clone();
+                    let config_reload = config_reload.clone();
+                    let chains = chains.clone();
+                    let handle = inner_handle.clone();
+                    let source = chains.borrow()[id].source.clone();
+                    source.get_mining_info().then(move |mining_info| {
+                        match mining_info {
+                            Ok(mining_info) => {
+                                let mut state = state.lock().unwrap();
+                                if state.scanning.is_none() {
+                                    config_reload.borrow_mut().reload(
+                                        &mut chains.borrow_mut(),
+                                        &mut state,
+                                        &mut plot_scanner.borrow_mut(),
+                                        &metrics,
+                                        &journal,
+                                        &handle,
+                                    );
+                                    plot_scanner.borrow_mut().rescan(
+                                        &mut reader.borrow_mut(),
+                                        &metrics,
+                                        false,
+                                    );
                                 }
-                                state.height = mining_info.height;
-                                state.base_target = mining_info.base_target;
+                                let chain = chains.borrow()[id].clone();
+                                if mining_info.height > state.chains[id].height {
+                                    let gensig =
+                                        burstmath::decode_gensig(&mining_info.generation_signature);
+                                    let scoop =
+                                        burstmath::calculate_scoop(mining_info.height, &gensig);
+                                    let poc2 = mining_info.height >= chain.poc2_start_height;
+                                    metrics.new_block(
+                                        &chain.name,
+                                        mining_info.height,
+                                        mining_info.base_target,
+                                        scoop,
+                                        &mining_info.generation_signature,
+                                    );
+                                    journal.record(&Event::block(
+                                        &chain.name,
+                                        mining_info.height,
+                                        mining_info.base_target,
+                                        scoop,
+                                    ));
+                                    {
+                                        let chain_state = &mut state.chains[id];
+                                        for best_deadlines in
+                                            chain_state.account_id_to_best_deadline.values_mut()
+                                        {
+                                            *best_deadlines = u64::MAX;
+                                        }
+                                        chain_state.height = mining_info.height;
+                                        chain_state.base_target = mining_info.base_target;
+                                        chain_state.target_deadline =
+                                            chain.target_deadline_source.target_deadline(
+                                                chain.target_deadline,
+                                                mining_info.target_deadline,
+                                            );
+                                        chain_state.endpoint = mining_info.endpoint.clone();
+                                        chain_state.gensig = gensig;
+                                        chain_state.scoop = scoop;
+                                        chain_state.poc2 = poc2;
 
-                                let gensig =
-                                    burstmath::decode_gensig(&mining_info.generation_signature);
-                                let scoop = burstmath::calculate_scoop(mining_info.height, &gensig);
-                                info!(
-                                    "{: <80}",
-                                    format!(
-                                        "new block: height={}, scoop={}",
-                                        mining_info.height, scoop
-                                    )
-                                );
+                                        let _event = logger::event(
+                                            "new_block",
+                                            &[
+                                                ("chain", chain.name.clone()),
+                                                ("height", mining_info.height.to_string()),
+                                                ("scoop", scoop.to_string()),
+                                                ("base_target", mining_info.base_target.to_string()),
+                                            ],
+                                        );
+                                        info!(
+                                            "{: <80}",
+                                            format!(
+                                                "{}new block: height={}, scoop={}, poc={}",
+                                                chain_state.prefix,
+                                                mining_info.height,
+                                                scoop,
+                                                if poc2 { 2 } else { 1 }
+                                            )
+                                        );
+                                        if mining_info.target_deadline != u64::MAX {
+                                            debug!(
+                                                "{}target deadline: pool={}, config={}, effective={}",
+                                                chain_state.prefix,
+                                                mining_info.target_deadline,
+                                                chain.target_deadline,
+                                                chain_state.target_deadline
+                                            );
+                                        }
+                                    }
 
-                                reader.borrow_mut().start_reading(
-                                    mining_info.height,
-                                    scoop,
-                                    &Arc::new(gensig),
-                                );
-                                state.sw.restart();
-                                state.processed_reader_tasks = 0;
-                                state.scanning = true;
-                            } else if !state.scanning
-                                && wakeup_after != 0
-                                && state.sw.elapsed_ms() > wakeup_after
-                            {
-                                info!("HDD, wakeup!");
-                                reader.borrow_mut().wakeup();
-                                state.sw.restart();
+                                    if state.schedule(id) {
+                                        state.start_round(&mut reader.borrow_mut(), &metrics, id);
+                                    } else {
+                                        info!(
+                                            "{: <80}",
+                                            format!(
+                                                "{}reader busy, round queued",
+                                                state.chains[id].prefix
+                                            )
+                                        );
+                                    }
+                                } else if state.scanning.is_none()
+                                    && wakeup_after != 0
+                                    && state.sw.elapsed_ms() > wakeup_after
+                                {
+                                    info!("HDD, wakeup!");
+                                    reader.borrow_mut().wakeup();
+                                    state.sw.restart();
+                                }
                             }
+                            // nothing to mine yet, e.g. a replay that hasn't started
+                            Err(FetchError::Empty) => (),
+                            Err(_) => warn!("{: <80}", "error getting mining info"),
                         }
-                        _ => warn!("{: <80}", "error getting mining info"),
-                    }
-                    future::ok(())
-                })
-            }).map_err(|e| panic!("interval errored: err={:?}", e)),
-        );
+                        future::ok(())
+                    })
+                }).map_err(|e| panic!("interval errored: err={:?}", e)),
+            );
+        }
 
-        let target_deadline = self.target_deadline;
-        let request_handler = self.request_handler.clone();
+        let metrics = self.metrics.clone();
+        let journal = self.journal.clone();
         let inner_handle = handle.clone();
         let state = self.state.clone();
-        let reader_task_count = self.reader_task_count;
+        let state_verified = self.state.clone();
+        let deadline_verification = self.deadline_verification;
         handle.spawn(
             self.rx_nonce_data
                 .for_each(move |nonce_data| {
                     let mut state = state.lock().unwrap();
-                    let deadline = nonce_data.deadline / state.base_target;
-                    let best_deadline = *state
-                        .account_id_to_best_deadline
-                        .get(&nonce_data.account_id)
-                        .unwrap_or(&u64::MAX);
-                    if best_deadline > deadline && deadline < target_deadline {
-                        state
-                            .account_id_to_best_deadline
-                            .insert(nonce_data.account_id, deadline);
-                        request_handler.submit_nonce(
-                            &inner_handle,
-                            nonce_data.account_id,
-                            nonce_data.nonce,
-                            nonce_data.height,
-                            deadline,
-                            0,
-                        );
-
-                        info!(
-                            "deadline found: account={}, nonce={}, deadline={}",
-                            nonce_data.account_id, nonce_data.nonce, deadline
-                        );
+                    let chain = chains.borrow()[nonce_data.chain].clone();
+                    // results of an interrupted or outdated round
+                    if nonce_data.height != state.chains[nonce_data.chain].height {
+                        return Ok(());
                     }
-                    if nonce_data.reader_task_processed {
-                        state.processed_reader_tasks += 1;
-                        if state.processed_reader_tasks == reader_task_count {
-                            info!(
-                                "{: <80}",
-                                format!("round finished: roundtime={}ms", state.sw.elapsed_ms())
+
+                    // u64::MAX marks replies without a deadline, e.g. a skipped plot
+                    if nonce_data.deadline != u64::MAX {
+                        let chain_state = &mut state.chains[nonce_data.chain];
+                        let deadline = nonce_data.deadline / chain_state.base_target;
+                        let solo = chain.solo_accounts.contains(&nonce_data.account_id);
+                        if !chain_state.improves(&chain, &nonce_data, deadline) {
+                            // not better than what was submitted
+                        } else if solo && deadline_verification != DeadlineVerification::Off {
+                            verify_and_submit(
+                                deadline_verification,
+                                &state_verified,
+                                chain_state.clone(),
+                                chain.clone(),
+                                nonce_data.clone(),
+                                &metrics,
+                                &inner_handle,
+                            );
+                        } else {
+                            chain_state.submit(
+                                &chain,
+                                &nonce_data,
+                                deadline,
+                                &metrics,
+                                &inner_handle,
                             );
+                        }
+                    }
+
+                    if nonce_data.reader_task_processed && state.scanning == Some(nonce_data.chain)
+                    {
+                        state.processed_reader_tasks += 1;
+                        if state.processed_reader_tasks == reader.borrow().drive_count() {
+                            metrics.round_finished(&chain.name, state.sw.elapsed_ms());
+                            journal.record(&Event::round(
+                                &chain.name,
+                                nonce_data.height,
+                                state.sw.elapsed_ms(),
+                            ));
+                            {
+                                let _event = logger::event(
+                                    "round_finished",
+                                    &[
+                                        ("chain", chain.name.clone()),
+                                        ("height", nonce_data.height.to_string()),
+                                        ("roundtime_ms", state.sw.elapsed_ms().to_string()),
+                                    ],
+                                );
+                                info!(
+                                    "{: <80}",
+                                    format!(
+                                        "{}round finished: roundtime={}ms",
+                                        state.chains[nonce_data.chain].prefix,
+                                        state.sw.elapsed_ms()
+                                    )
+                                );
+                            }
                             state.sw.restart();
-                            state.scanning = false;
+                            state.scanning = None;
+                            config_reload.borrow_mut().reload(
+                                &mut chains.borrow_mut(),
+                                &mut state,
+                                &mut plot_scanner.borrow_mut(),
+                                &metrics,
+                                &journal,
+                                &inner_handle,
+                            );
+                            plot_scanner.borrow_mut().rescan(
+                                &mut reader.borrow_mut(),
+                                &metrics,
+                                true,
+                            );
+                            if let Some(next) = state.next_queued() {
+                                state.start_round(&mut reader.borrow_mut(), &metrics, next);
+                            }
                         }
                     }
                     Ok(())
                 }).map_err(|e| panic!("interval errored: err={:?}", e)),
         );
 
-        self.core.run(future::empty::<(), ()>()).unwrap();
+        self.core
+            .run(shutdown.then(|_| future::ok::<(), ()>(())))
+            .unwrap();
     }
 }
-
 #[cfg(test)]
 mod test {
+    use super::*;
+    use mock_wallet::{MockWallet, Reply, Submit};
+    use plot::read_test_scoop;
+    use pool::{ChannelSink, ChannelSource};
+    use requests::MiningInfo;
+    use serde_yaml;
+    use shabal_portable;
+
+    const TEST_ACCOUNT: u64 = 10_282_355_196_851_764_065;
+    const TEST_SECRET_PHRASE: &str =
+        "glad suffer red during single glow shut slam hill death lust although";
+
+    fn test_cfg(extra: &str) -> Cfg {
+        serde_yaml::from_str(&format!(
+            "plot_dirs: ['test_data']
+hdd_use_direct_io: false
+hdd_wakeup_after: 0
+cpu_worker_thread_count: 1
+cpu_nonces_per_cache: 8
+get_mining_info_interval: 50
+timeout: 500
+show_progress: false
+poc2_start_height: 0
+target_deadline: 18446744073709551615
+{}",
+            extra
+        )).unwrap()
+    }
+
+    // the best (nonce, deadline) of the test plot
+    fn best_deadline(height: u64, gensig: &str, base_target: u64) -> (u64, u64) {
+        let gensig = burstmath::decode_gensig(gensig);
+        let scoop = burstmath::calculate_scoop(height, &gensig);
+        let bs = read_test_scoop(scoop);
+        let (deadline, offset) = shabal_portable::find_best_deadline(&bs, 8, &gensig);
+        (offset, deadline / base_target)
+    }
+
+    #[test]
+    fn test_new_miner() {
+        let cfg = test_cfg("url: 'http://localhost:1'");
+        let gensig = "0123456789abcdef".repeat(4);
+        let (height, base_target) = (10, 3);
+
+        let (tx_mining_info, source) = ChannelSource::new();
+        let (sink, rx_nonces) = ChannelSink::new();
+        let (tx_shutdown, rx_shutdown) = oneshot::channel::<()>();
+        let miner = thread::spawn(move || {
+            MinerBuilder::new(cfg)
+                .mining_info_source("default", Rc::new(source))
+                .nonce_sink("default", Rc::new(sink))
+                .build()
+                .unwrap()
+                .run_until(rx_shutdown);
+        });
+        tx_mining_info
+            .send(MiningInfo {
+                generation_signature: gensig.clone(),
+                base_target,
+                height,
+                target_deadline: u64::MAX,
+                endpoint: String::new(),
+            }).unwrap();
+
+        // every improvement is submitted, the last one has to be the best nonce of the plot
+        let expected = best_deadline(height, &gensig, base_target);
+        let mut best = None;
+        while let Ok(submitted) = rx_nonces.recv_timeout(Duration::from_secs(30)) {
+            assert_eq!(submitted.account_id, TEST_ACCOUNT);
+            assert_eq!(submitted.height, height);
+            best = Some((submitted.nonce, submitted.deadline));
+            if best == Some(expected) {
+                break;
+            }
+        }
+        assert_eq!(best, Some(expected));
+
+        tx_shutdown.send(()).unwrap();
+        miner.join().unwrap();
+    }
+
+    #[test]
+    fn test_mine_against_mock_pool() {
+        let wallet = MockWallet::start();
+        let gensig = "fedcba9876543210".repeat(4);
+        // the miner has to get past errors, timeouts and int encoded numbers
+        wallet.mining_info(vec![
+            Reply::PoolError(5, "busy".to_owned()),
+            Reply::Delay(1000, Box::new(Reply::mining_info(1, 1, &gensig))),
+            Reply::Status(502),
+            Reply::pool_mining_info(20, 7, &gensig, u64::MAX),
+        ]);
+        let cfg = test_cfg(&format!("url: '{}'", wallet.url()));
+        let (tx_shutdown, rx_shutdown) = oneshot::channel::<()>();
+        let miner = thread::spawn(move || {
+            MinerBuilder::new(cfg)
+                .build()
+                .unwrap()
+                .run_until(rx_shutdown)
+        });
+
+        let expected = best_deadline(20, &gensig, 7);
+        let is_best =
+            |s: &Submit| s.height == 20 && (s.nonce, s.deadline) == (expected.0, Some(expected.1));
+        let submits = wallet.wait_for_submits(Duration::from_secs(30), |submits| {
+            submits.iter().any(&is_best)
+        });
+        assert!(submits.iter().any(&is_best), "{:?}", submits);
+        for submit in &submits {
+            assert_eq!(submit.account_id, TEST_ACCOUNT);
+            assert_eq!(submit.secret_phrase, None);
+            assert!(submit.deadline.unwrap() >= expected.1);
+        }
+        // the timed out block was never mined
+        assert!(submits.iter().all(|s| s.height == 20));
+
+        // next block
+        wallet.mining_info(vec![Reply::mining_info(21, 7, &gensig)]);
+        let expected = best_deadline(21, &gensig, 7);
+        let submits = wallet.wait_for_submits(Duration::from_secs(30), |submits| {
+            submits
+                .iter()
+                .any(|s| s.height == 21 && s.nonce == expected.0)
+        });
+        assert_eq!(
+            submits.last().map(|s| (s.height, s.nonce, s.deadline)),
+            Some((21, expected.0, Some(expected.1)))
+        );
+
+        tx_shutdown.send(()).unwrap();
+        miner.join().unwrap();
+    }
+
+    #[test]
+    fn test_mine_against_mock_wallet() {
+        let wallet = MockWallet::start();
+        let gensig = "00112233445566778899aabbccddeeff".repeat(2);
+        wallet.mining_info(vec![Reply::mining_info(30, 1, &gensig)]);
+        // the first submission fails, the miner retries
+        wallet.submit_replies(vec![Reply::Delay(1000, Box::new(Reply::Status(500)))]);
+        // solo mining with the account id derived from the passphrase
+        let cfg = test_cfg(&format!(
+            "url: '{}'\nsecret_phrases: ['{}']",
+            wallet.url(),
+            TEST_SECRET_PHRASE
+        ));
+        let (tx_shutdown, rx_shutdown) = oneshot::channel::<()>();
+        let miner = thread::spawn(move || {
+            MinerBuilder::new(cfg)
+                .build()
+                .unwrap()
+                .run_until(rx_shutdown)
+        });
+
+        let expected = best_deadline(30, &gensig, 1);
+        let submits = wallet.wait_for_submits(Duration::from_secs(30), |submits| {
+            submits.iter().filter(|s| s.nonce == expected.0).count() == 2
+        });
+        let best: Vec<_> = submits.iter().filter(|s| s.nonce == expected.0).collect();
+        assert_eq!(best.len(), 2, "{:?}", submits);
+        for submit in best {
+            assert_eq!(submit.height, 30);
+            assert_eq!(submit.secret_phrase, Some(TEST_SECRET_PHRASE.to_owned()));
+            // solo mining leaves the deadline to the wallet
+            assert_eq!(submit.deadline, None);
+        }
+
+        tx_shutdown.send(()).unwrap();
+        miner.join().unwrap();
+    }
+
+    #[test]
+    fn test_plot_scanner() {
+        use std::env;
+        use std::fs;
+
+        let plot_dir = env::temp_dir().join("scavenger_test_rescan");
+        let _ = fs::remove_dir_all(&plot_dir);
+        fs::create_dir_all(&plot_dir).unwrap();
+
+        let mut plot_scanner = PlotScanner {
+            plot_dirs: vec![plot_dir.to_str().unwrap().to_owned()],
+            use_direct_io: false,
+            dummy: false,
+            rescan_after_round: true,
+            listing: Vec::new(),
+            rescan_requested: Arc::new(AtomicBool::new(false)),
+        };
+        assert_eq!(plot_scanner.scan().0.len(), 0);
+
+        // plots that are still being written are skipped
+        let plot = plot_dir.join("10282355196851764065_0_8");
+        let progress = plot_dir.join("10282355196851764065_0_8.progress");
+        fs::copy("test_data/10282355196851764065_0_8", &plot).unwrap();
+        fs::write(&progress, b"4").unwrap();
+        assert_ne!(plot_scanner.list_files(), plot_scanner.listing);
+        assert_eq!(plot_scanner.scan().0.len(), 0);
+
+        fs::remove_file(&progress).unwrap();
+        assert_ne!(plot_scanner.list_files(), plot_scanner.listing);
+        let (drive_id_to_plots, total_size) = plot_scanner.scan();
+        assert_eq!(drive_id_to_plots.len(), 1);
+        assert_eq!(total_size, 8 * 64);
+        assert_eq!(plot_scanner.list_files(), plot_scanner.listing);
+
+        fs::remove_dir_all(&plot_dir).unwrap();
+    }
+
+    #[test]
+    fn test_chain_unknown_source_or_sink() {
+        let core = Core::new().unwrap();
+        let metrics = Arc::new(Metrics::default());
+        let journal = Arc::new(Journal::disabled());
+        let cfg = test_cfg("url: 'http://localhost:1'");
+        let handle = core.handle();
+        let new_chain = |chain_cfg: &ChainCfg| {
+            Chain::new(chain_cfg, &cfg, &metrics, &journal, &handle, None, None)
+        };
+        let mut chain_cfg = cfg.chains().remove(0);
+        for source in &["htp", "replay", "replay:/nonexistent/replay.jsonl"] {
+            chain_cfg.mining_info_source = source.to_string();
+            assert!(new_chain(&chain_cfg).is_err());
+        }
+        chain_cfg.mining_info_source = "http".to_owned();
+        chain_cfg.nonce_sink = "mok".to_owned();
+        assert!(new_chain(&chain_cfg).is_err());
+        chain_cfg.nonce_sink = "mock".to_owned();
+        assert!(new_chain(&chain_cfg).is_ok());
+    }
 
     #[test]
-    fn test_new_miner() {}
+    fn test_config_reload() {
+        let core = Core::new().unwrap();
+        let handle = core.handle();
+        let metrics = Arc::new(Metrics::default());
+        let journal = Arc::new(Journal::disabled());
+        let cfg = test_cfg("url: 'http://localhost:1'");
+        let chain_cfgs = cfg.chains();
+        let mut chains: Vec<Chain> = chain_cfgs
+            .iter()
+            .map(|chain_cfg| {
+                Chain::new(chain_cfg, &cfg, &metrics, &journal, &handle, None, None).unwrap()
+            })
+            .collect();
+        let mut state = State::new(
+            chain_cfgs
+                .iter()
+                .map(|chain_cfg| ChainState::new(chain_cfg, false))
+                .collect(),
+        );
+        let mut plot_scanner = PlotScanner {
+            plot_dirs: cfg.plot_dirs.clone(),
+            use_direct_io: false,
+            dummy: false,
+            rescan_after_round: false,
+            listing: Vec::new(),
+            rescan_requested: Arc::new(AtomicBool::new(false)),
+        };
+        // url, target_deadline and mining_info_source of the next reload
+        let next = Rc::new(RefCell::new(("http://localhost:1", 1000, "http")));
+        let loaded = next.clone();
+        let mut config_reload = ConfigReload {
+            requested: Arc::new(AtomicBool::new(false)),
+            load: Some(Box::new(move || {
+                let (url, target_deadline, source) = *loaded.borrow();
+                let mut cfg = test_cfg(&format!("url: '{}'", url));
+                cfg.target_deadline = target_deadline;
+                cfg.mining_info_source = source.to_owned();
+                cfg.plot_dirs = vec!["test_data".to_owned(), "reloaded".to_owned()];
+                cfg.rescan_plot_dirs = true;
+                Ok(cfg)
+            })),
+            log_handle: None,
+            cfg,
+            sources: HashMap::new(),
+            sinks: HashMap::new(),
+        };
+        let source = chains[0].source.clone();
+
+        // nothing happens without a SIGHUP
+        config_reload.reload(
+            &mut chains,
+            &mut state,
+            &mut plot_scanner,
+            &metrics,
+            &journal,
+            &handle,
+        );
+        assert_eq!(chains[0].target_deadline, u64::MAX);
+
+        config_reload.requested.store(true, Ordering::Relaxed);
+        config_reload.reload(
+            &mut chains,
+            &mut state,
+            &mut plot_scanner,
+            &metrics,
+            &journal,
+            &handle,
+        );
+        assert!(!config_reload.requested.load(Ordering::Relaxed));
+        assert_eq!(chains[0].target_deadline, 1000);
+        assert_eq!(plot_scanner.plot_dirs, vec!["test_data", "reloaded"]);
+        assert!(plot_scanner.rescan_after_round);
+        // same url, the source and its failover state are kept
+        assert!(Rc::ptr_eq(&chains[0].source, &source));
+
+        *next.borrow_mut() = ("http://localhost:2", u64::MAX, "http");
+        config_reload.requested.store(true, Ordering::Relaxed);
+        config_reload.reload(
+            &mut chains,
+            &mut state,
+            &mut plot_scanner,
+            &metrics,
+            &journal,
+            &handle,
+        );
+        assert!(!Rc::ptr_eq(&chains[0].source, &source));
+        assert_eq!(chains[0].target_deadline, u64::MAX);
+
+        // a chain that can't be built keeps the old one and its settings
+        let source = chains[0].source.clone();
+        *next.borrow_mut() = (
+            "http://localhost:3",
+            500,
+            "replay:/nonexistent/replay.jsonl",
+        );
+        config_reload.requested.store(true, Ordering::Relaxed);
+        config_reload.reload(
+            &mut chains,
+            &mut state,
+            &mut plot_scanner,
+            &metrics,
+            &journal,
+            &handle,
+        );
+        assert!(Rc::ptr_eq(&chains[0].source, &source));
+        assert_eq!(chains[0].target_deadline, u64::MAX);
+        assert_eq!(config_reload.cfg.url, "http://localhost:2");
+        assert_eq!(config_reload.cfg.mining_info_source, "http");
+    }
+
+    fn chain_state(priority: u8) -> ChainState {
+        ChainState {
+            name: "".to_owned(),
+            prefix: "".to_owned(),
+            priority,
+            height: 0,
+            account_id_to_best_deadline: HashMap::new(),
+            base_target: 1,
+            target_deadline: u64::MAX,
+            gensig: [0; 32],
+            scoop: 0,
+            poc2: false,
+            endpoint: String::new(),
+        }
+    }
+
+    #[test]
+    fn test_schedule() {
+        let mut state = State::new(vec![chain_state(0), chain_state(1), chain_state(0)]);
+        assert!(state.schedule(0));
+        state.scanning = Some(0);
+
+        // same chain restarts, lower or equal priority is queued
+        assert!(state.schedule(0));
+        assert!(!state.schedule(2));
+        assert!(!state.schedule(2));
+        assert_eq!(state.queue, vec![2]);
+
+        // higher priority interrupts and requeues the interrupted chain
+        assert!(state.schedule(1));
+        state.scanning = Some(1);
+        assert_eq!(state.queue, vec![2, 0]);
+
+        assert_eq!(state.next_queued(), Some(2));
+        assert_eq!(state.next_queued(), Some(0));
+        assert_eq!(state.next_queued(), None);
+
+        state.queue = vec![0, 1, 2];
+        assert_eq!(state.next_queued(), Some(1));
+    }
+
+    #[test]
+    fn test_target_deadline_source() {
+        let source = TargetDeadlineSource::from_str("Min").unwrap();
+        assert_eq!(source, TargetDeadlineSource::Min);
+        assert_eq!(source.target_deadline(1000, 500), 500);
+        assert_eq!(source.target_deadline(1000, u64::MAX), 1000);
+
+        let source = TargetDeadlineSource::from_str("pool").unwrap();
+        assert_eq!(source.target_deadline(1000, 5000), 5000);
+
+        let source = TargetDeadlineSource::from_str("CONFIG").unwrap();
+        assert_eq!(source.target_deadline(1000, 500), 1000);
+
+        assert_eq!(
+            TargetDeadlineSource::from_str("foo"),
+            Err("unknown value foo, options: min, pool, config".to_owned())
+        );
+    }
+    #[test]
+    fn test_secret_phrase_check() {
+        let cfg = test_cfg(&format!(
+            "url: 'http://localhost:1'\naccount_id_to_secret_phrase:\n  {}: '{}'\n  42: '{}'",
+            TEST_ACCOUNT, TEST_SECRET_PHRASE, TEST_SECRET_PHRASE
+        ));
+        let chain_cfg = &cfg.chains()[0];
+        assert!(SecretPhraseCheck::Off.check(chain_cfg));
+        assert!(SecretPhraseCheck::Warn.check(chain_cfg));
+        assert!(!SecretPhraseCheck::Refuse.check(chain_cfg));
+
+        let cfg = test_cfg(&format!(
+            "url: 'http://localhost:1'\nsecret_phrases: ['{}']",
+            TEST_SECRET_PHRASE
+        ));
+        assert!(SecretPhraseCheck::Refuse.check(&cfg.chains()[0]));
+    }
+
+    #[test]
+    fn test_deadline_verification() {
+        let gensig = "0123456789abcdef".repeat(4);
+        let mut chain_state = chain_state(0);
+        chain_state.height = 10;
+        chain_state.base_target = 3;
+        chain_state.gensig = burstmath::decode_gensig(&gensig);
+        chain_state.scoop = burstmath::calculate_scoop(10, &chain_state.gensig);
+        chain_state.poc2 = true;
+        let (nonce, deadline) = best_deadline(10, &gensig, 3);
+        let nonce_data = NonceData {
+            chain: 0,
+            height: 10,
+            deadline: deadline * 3,
+            nonce,
+            reader_task_processed: false,
+            account_id: TEST_ACCOUNT,
+        };
+
+        for verification in &["off", "log", "refuse"] {
+            let verification = DeadlineVerification::from_str(verification).unwrap();
+            assert!(verification.check(&chain_state, &nonce_data, deadline));
+        }
+        assert!(DeadlineVerification::Off.check(&chain_state, &nonce_data, 1));
+        assert!(DeadlineVerification::Log.check(&chain_state, &nonce_data, 1));
+        assert!(!DeadlineVerification::Refuse.check(&chain_state, &nonce_data, 1));
+        assert!(DeadlineVerification::from_str("foo").is_err());
+    }
 }
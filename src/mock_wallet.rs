@@ -0,0 +1,232 @@
+// scripted Burst wallet/pool for the tests, serves getMiningInfo and records submitNonce calls
+
+use futures::future;
+use futures::sync::oneshot;
+use futures::{Future, Stream};
+use hyper::server::conn::Http;
+use hyper::service::service_fn;
+use hyper::{Body, Request, Response, StatusCode, Uri};
+use std::collections::{HashMap, VecDeque};
+use std::net;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+use tokio::net::TcpListener;
+use tokio::reactor;
+use tokio::timer::Delay;
+use tokio_core::reactor::Core;
+use url::form_urlencoded;
+
+#[derive(Clone, Debug)]
+pub enum Reply {
+    // body sent with 200 OK
+    Json(String),
+    // {"error": {...}} as pools send it
+    PoolError(i32, String),
+    Status(u16),
+    // answer only after the delay, longer than the miner's timeout makes a timeout
+    Delay(u64, Box<Reply>),
+}
+
+impl Reply {
+    // getMiningInfo the way the wallet sends it, numbers as strings
+    pub fn mining_info(height: u64, base_target: u64, gensig: &str) -> Reply {
+        Reply::Json(format!(
+            "{{\"generationSignature\":\"{}\",\"baseTarget\":\"{}\",\"height\":\"{}\"}}",
+            gensig, base_target, height
+        ))
+    }
+
+    // getMiningInfo the way some pools send it, numbers as ints and with a target deadline
+    pub fn pool_mining_info(
+        height: u64,
+        base_target: u64,
+        gensig: &str,
+        target_deadline: u64,
+    ) -> Reply {
+        Reply::Json(format!(
+            "{{\"generationSignature\":\"{}\",\"baseTarget\":{},\"height\":{},\
+             \"targetDeadline\":{}}}",
+            gensig, base_target, height, target_deadline
+        ))
+    }
+}
+
+// a recorded submitNonce call
+#[derive(Clone, Debug)]
+pub struct Submit {
+    pub account_id: u64,
+    pub nonce: u64,
+    pub height: u64,
+    pub deadline: Option<u64>,
+    pub secret_phrase: Option<String>,
+    pub uri: String,
+}
+
+#[derive(Default)]
+struct State {
+    // the last one stays once the others are served
+    mining_info: VecDeque<Reply>,
+    // accepted with the submitted deadline once this runs dry
+    submit: VecDeque<Reply>,
+    mining_info_requests: usize,
+    submits: Vec<Submit>,
+}
+
+pub struct MockWallet {
+    addr: SocketAddr,
+    state: Arc<Mutex<State>>,
+    _shutdown: oneshot::Sender<()>,
+}
+
+impl MockWallet {
+    pub fn start() -> MockWallet {
+        let listener = net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let state = Arc::new(Mutex::new(State::default()));
+        let (tx_shutdown, rx_shutdown) = oneshot::channel::<()>();
+
+        let server_state = state.clone();
+        thread::spawn(move || {
+            let mut core = Core::new().unwrap();
+            let handle = core.handle();
+            let listener = TcpListener::from_std(listener, &reactor::Handle::default()).unwrap();
+            let inner_handle = handle.clone();
+            handle.spawn(
+                listener
+                    .incoming()
+                    .for_each(move |socket| {
+                        let state = server_state.clone();
+                        let service = service_fn(move |req: Request<Body>| {
+                            let state = state.clone();
+                            let uri = req.uri().clone();
+                            req.into_body()
+                                .concat2()
+                                .and_then(move |body| respond(handle_request(&state, &uri, &body)))
+                        });
+                        inner_handle.spawn(
+                            Http::new()
+                                .serve_connection(socket, service)
+                                .map_err(|_| ()),
+                        );
+                        Ok(())
+                    })
+                    .map_err(|_| ()),
+            );
+            // dropping the MockWallet ends the server
+            let _ = core.run(rx_shutdown);
+        });
+
+        MockWallet {
+            addr,
+            state,
+            _shutdown: tx_shutdown,
+        }
+    }
+
+    pub fn url(&self) -> String {
+        format!("http://{}", self.addr)
+    }
+
+    // replaces the scripted getMiningInfo replies
+    pub fn mining_info(&self, replies: Vec<Reply>) {
+        self.state.lock().unwrap().mining_info = replies.into_iter().collect();
+    }
+
+    // replies for the next submitNonce calls
+    pub fn submit_replies(&self, replies: Vec<Reply>) {
+        self.state.lock().unwrap().submit = replies.into_iter().collect();
+    }
+
+    pub fn mining_info_requests(&self) -> usize {
+        self.state.lock().unwrap().mining_info_requests
+    }
+
+    pub fn submits(&self) -> Vec<Submit> {
+        self.state.lock().unwrap().submits.clone()
+    }
+
+    // polls until f is satisfied by the recorded submits
+    pub fn wait_for_submits<F>(&self, timeout: Duration, f: F) -> Vec<Submit>
+    where
+        F: Fn(&[Submit]) -> bool,
+    {
+        let step = Duration::from_millis(20);
+        let mut waited = Duration::from_millis(0);
+        loop {
+            let submits = self.submits();
+            if f(&submits) || waited >= timeout {
+                return submits;
+            }
+            thread::sleep(step);
+            waited += step;
+        }
+    }
+}
+
+// parameters are taken from the query and a form body alike
+fn handle_request(state: &Mutex<State>, uri: &Uri, body: &[u8]) -> Reply {
+    let query: HashMap<String, String> =
+        form_urlencoded::parse(uri.query().unwrap_or("").as_bytes())
+            .chain(form_urlencoded::parse(body))
+            .map(|(k, v)| (k.into_owned(), v.into_owned()))
+            .collect();
+    let mut state = state.lock().unwrap();
+    match query.get("requestType").map(|s| s.as_str()) {
+        Some("getMiningInfo") => {
+            state.mining_info_requests += 1;
+            if state.mining_info.len() > 1 {
+                state.mining_info.pop_front().unwrap()
+            } else {
+                match state.mining_info.front() {
+                    Some(reply) => reply.clone(),
+                    None => Reply::PoolError(1, "no block yet".to_owned()),
+                }
+            }
+        }
+        Some("submitNonce") => {
+            let param = |name: &str| query.get(name).and_then(|v| v.parse::<u64>().ok());
+            let submit = Submit {
+                account_id: param("accountId").unwrap_or(0),
+                nonce: param("nonce").unwrap_or(0),
+                height: param("blockheight").unwrap_or(0),
+                deadline: param("deadline"),
+                // pool mining sends an empty one
+                secret_phrase: query.get("secretPhrase").filter(|s| !s.is_empty()).cloned(),
+                uri: uri.to_string(),
+            };
+            let deadline = submit.deadline.unwrap_or(0);
+            state.submits.push(submit);
+            state
+                .submit
+                .pop_front()
+                .unwrap_or_else(|| Reply::Json(format!("{{\"deadline\":{}}}", deadline)))
+        }
+        _ => Reply::Status(404),
+    }
+}
+
+fn respond(reply: Reply) -> Box<Future<Item = Response<Body>, Error = ::hyper::Error> + Send> {
+    match reply {
+        Reply::Json(body) => Box::new(future::ok(
+            Response::builder()
+                .header("Content-Type", "application/json")
+                .body(Body::from(body))
+                .unwrap(),
+        )),
+        Reply::PoolError(code, message) => respond(Reply::Json(format!(
+            "{{\"error\":{{\"code\":{},\"message\":\"{}\"}}}}",
+            code, message
+        ))),
+        Reply::Status(status) => Box::new(future::ok(
+            Response::builder()
+                .status(StatusCode::from_u16(status).unwrap())
+                .body(Body::empty())
+                .unwrap(),
+        )),
+        Reply::Delay(ms, reply) => Box::new(
+            Delay::new(Instant::now() + Duration::from_millis(ms)).then(move |_| respond(*reply)),
+        ),
+    }
+}
@@ -0,0 +1,55 @@
+use plot::{NONCE_SIZE, SCOOPS_IN_NONCE, SCOOP_SIZE, SHABAL256_HASH_SIZE};
+use shabals::shabal256;
+
+const HASH_CAP: usize = 4096;
+const SEED_SIZE: usize = 16;
+
+// generate a single nonce (scalar), the result is laid out scoop by scoop as it is in a plot file
+pub fn generate_nonce(account_id: u64, nonce: u64, poc2: bool) -> Vec<u8> {
+    let nonce_size = NONCE_SIZE as usize;
+    let hash_size = SHABAL256_HASH_SIZE as usize;
+
+    let mut gendata = vec![0u8; nonce_size + SEED_SIZE];
+    gendata[nonce_size..nonce_size + 8].clone_from_slice(&u64_to_be_bytes(account_id));
+    gendata[nonce_size + 8..].clone_from_slice(&u64_to_be_bytes(nonce));
+
+    let mut i = nonce_size;
+    while i > 0 {
+        let len = (nonce_size + SEED_SIZE - i).min(HASH_CAP);
+        let hash = shabal256(&gendata[i..i + len]);
+        gendata[i - hash_size..i].clone_from_slice(&hash);
+        i -= hash_size;
+    }
+
+    let final_hash = shabal256(&gendata);
+    gendata.truncate(nonce_size);
+    for (i, b) in gendata.iter_mut().enumerate() {
+        *b ^= final_hash[i % hash_size];
+    }
+
+    if poc2 {
+        to_poc2(&mut gendata);
+    }
+    gendata
+}
+
+// PoC2 swaps the second hash of scoop n with the second hash of scoop 4095 - n
+fn to_poc2(nonce: &mut [u8]) {
+    let scoop_size = SCOOP_SIZE as usize;
+    let hash_size = SHABAL256_HASH_SIZE as usize;
+    for scoop in 0..SCOOPS_IN_NONCE as usize / 2 {
+        let a = scoop * scoop_size + hash_size;
+        let b = (SCOOPS_IN_NONCE as usize - 1 - scoop) * scoop_size + hash_size;
+        for j in 0..hash_size {
+            nonce.swap(a + j, b + j);
+        }
+    }
+}
+
+fn u64_to_be_bytes(x: u64) -> [u8; 8] {
+    let mut bytes = [0u8; 8];
+    for (i, b) in bytes.iter_mut().enumerate() {
+        *b = (x >> (56 - i * 8)) as u8;
+    }
+    bytes
+}
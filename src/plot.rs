@@ -8,15 +8,16 @@ use std::io::{Read, Seek, SeekFrom};
 use std::path::{Path, PathBuf};
 use utils::get_sector_size;
 
-const SCOOPS_IN_NONCE: u64 = 4096;
-const SHABAL256_HASH_SIZE: u64 = 32;
+pub const SCOOPS_IN_NONCE: u64 = 4096;
+pub const SHABAL256_HASH_SIZE: u64 = 32;
+pub const POC2_START_HEIGHT: u64 = 502_000;
 pub const SCOOP_SIZE: u64 = SHABAL256_HASH_SIZE * 2;
-const NONCE_SIZE: u64 = SCOOP_SIZE * SCOOPS_IN_NONCE;
+pub const NONCE_SIZE: u64 = SCOOP_SIZE * SCOOPS_IN_NONCE;
 
 // TODO: mining for multiple accounts
 pub struct Plot {
     pub account_id: u64,
-    start_nonce: u64,
+    pub start_nonce: u64,
     pub nonces: u64,
     pub fh: File,
     read_offset: u64,
@@ -24,6 +25,7 @@ pub struct Plot {
     pub name: String,
     sector_size: u64,
     dummy: bool,
+    pub poc2: bool,
 }
 
 cfg_if! {
@@ -38,6 +40,13 @@ cfg_if! {
                 .custom_flags(O_DIRECT)
                 .open(path)
         }
+
+        pub fn open_for_writing_using_direct_io<P: AsRef<Path>>(path: P) -> io::Result<File> {
+            OpenOptions::new()
+                .write(true)
+                .custom_flags(O_DIRECT)
+                .open(path)
+        }
     } else {
         use std::os::windows::fs::OpenOptionsExt;
 
@@ -49,6 +58,13 @@ cfg_if! {
                 .custom_flags(FILE_FLAG_NO_BUFFERING)
                 .open(path)
         }
+
+        pub fn open_for_writing_using_direct_io<P: AsRef<Path>>(path: P) -> io::Result<File> {
+            OpenOptions::new()
+                .write(true)
+                .custom_flags(FILE_FLAG_NO_BUFFERING)
+                .open(path)
+        }
     }
 }
 
@@ -61,9 +77,11 @@ impl Plot {
             )));
         }
 
+        // PoC2: account_start_nonces
+        // PoC1: account_start_nonces_stagger (optimized only, stagger == nonces)
         let plot_file = path.file_name().unwrap().to_str().unwrap();
         let parts: Vec<&str> = plot_file.split('_').collect();
-        if parts.len() != 3 {
+        if parts.len() != 3 && parts.len() != 4 {
             return Err(From::from("plot file has wrong format"));
         }
 
@@ -71,6 +89,17 @@ impl Plot {
         let start_nonce = parts[1].parse::<u64>()?;
         let nonces = parts[2].parse::<u64>()?;
 
+        let poc2 = parts.len() == 3;
+        if !poc2 {
+            let stagger = parts[3].parse::<u64>()?;
+            if stagger != nonces {
+                return Err(From::from(format!(
+                    "plot file {} is not optimized (stagger={}, nonces={})",
+                    plot_file, stagger, nonces
+                )));
+            }
+        }
+
         let size = fs::metadata(path)?.len();
         let exp_size = nonces * NONCE_SIZE;
         if size != exp_size as u64 {
@@ -106,6 +135,7 @@ impl Plot {
             sector_size,
             name: plot_file_name,
             dummy,
+            poc2,
         })
     }
 
@@ -121,7 +151,14 @@ impl Plot {
         self.fh.seek(SeekFrom::Start(seek_addr))
     }
 
-    pub fn read(&mut self, bs: &mut Vec<u8>, scoop: u32) -> Result<(usize, u64, bool), io::Error> {
+    // poc2 tells whether the current block needs PoC2 scoops, if the plot has the other layout the
+    // second hashes are fetched from the mirrored scoop (4095 - scoop)
+    pub fn read(
+        &mut self,
+        bs: &mut Vec<u8>,
+        scoop: u32,
+        poc2: bool,
+    ) -> Result<(usize, u64, bool), io::Error> {
         let read_offset = self.read_offset;
         let buffer_cap = bs.capacity();
         let start_nonce = self.start_nonce + self.read_offset / 64;
@@ -149,12 +186,51 @@ impl Plot {
         if !self.dummy {
             self.fh.seek(seek_addr)?;
             self.fh.read_exact(&mut bs[0..bytes_to_read])?;
+            if self.poc2 != poc2 {
+                self.read_mirrored_hashes(&mut bs[0..bytes_to_read], offset, scoop)?;
+            }
         }
         self.read_offset += bytes_to_read as u64;
 
         Ok((bytes_to_read, start_nonce, finished))
     }
 
+    // swap the second hash of every scoop in bs with the one of the mirrored scoop
+    fn read_mirrored_hashes(&mut self, bs: &mut [u8], offset: u64, scoop: u32) -> io::Result<()> {
+        let mirror_scoop = SCOOPS_IN_NONCE - 1 - u64::from(scoop);
+        let mut seek_addr = offset + mirror_scoop * self.nonces * SCOOP_SIZE;
+
+        // the mirrored scoop doesn't necessarily share the alignment of the scoop we just read
+        let (skip, len, align) = if self.use_direct_io {
+            let skip = seek_addr % self.sector_size;
+            seek_addr -= skip;
+            let mut len = skip + bs.len() as u64;
+            let r = len % self.sector_size;
+            if r != 0 {
+                len += self.sector_size - r;
+            }
+            (skip as usize, len as usize, self.sector_size as usize)
+        } else {
+            (0, bs.len(), 1)
+        };
+
+        let mut mirror = vec![0u8; len + align];
+        let start = (align - mirror.as_ptr() as usize % align) % align;
+        let mirror = &mut mirror[start..start + len];
+
+        self.fh.seek(SeekFrom::Start(seek_addr))?;
+        self.fh.read_exact(mirror)?;
+
+        let hash_size = SHABAL256_HASH_SIZE as usize;
+        for (scoop, mirror_scoop) in bs
+            .chunks_mut(SCOOP_SIZE as usize)
+            .zip(mirror[skip..].chunks(SCOOP_SIZE as usize))
+        {
+            scoop[hash_size..].clone_from_slice(&mirror_scoop[hash_size..]);
+        }
+        Ok(())
+    }
+
     pub fn seek_random(&mut self) -> io::Result<u64> {
         let mut rng = thread_rng();
         let rand_scoop = rng.gen_range(0, SCOOPS_IN_NONCE);
@@ -196,3 +272,53 @@ impl Plot {
         }
     }
 }
+
+// one scoop of all 8 nonces of the test plot
+#[cfg(test)]
+pub fn read_test_scoop(scoop: u32) -> Vec<u8> {
+    let path = PathBuf::from("test_data/10282355196851764065_0_8");
+    let mut plot = Plot::new(&path, false, false).unwrap();
+    let mut bs = vec![0u8; 8 * 64];
+    plot.prepare(scoop).unwrap();
+    plot.read(&mut bs, scoop, true).unwrap();
+    bs
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_new_plot() {
+        let path = PathBuf::from("test_data/10282355196851764065_0_8");
+        let plot = Plot::new(&path, false, false).unwrap();
+        assert_eq!(plot.account_id, 10282355196851764065);
+        assert_eq!(plot.nonces, 8);
+        assert!(plot.poc2);
+    }
+
+    #[test]
+    fn test_read_mirrored() {
+        let path = PathBuf::from("test_data/10282355196851764065_0_8");
+        let mut plot = Plot::new(&path, false, false).unwrap();
+        let scoop_len = (SCOOP_SIZE * plot.nonces) as usize;
+
+        let mut scoop = vec![0u8; scoop_len];
+        plot.prepare(7).unwrap();
+        plot.read(&mut scoop, 7, true).unwrap();
+
+        let mut mirror_scoop = vec![0u8; scoop_len];
+        plot.prepare(4088).unwrap();
+        plot.read(&mut mirror_scoop, 4088, true).unwrap();
+
+        let mut poc1_scoop = vec![0u8; scoop_len];
+        plot.prepare(7).unwrap();
+        plot.read(&mut poc1_scoop, 7, false).unwrap();
+
+        for i in 0..plot.nonces as usize {
+            let o = i * SCOOP_SIZE as usize;
+            assert_eq!(poc1_scoop[o..o + 32], scoop[o..o + 32]);
+            assert_eq!(poc1_scoop[o + 32..o + 64], mirror_scoop[o + 32..o + 64]);
+        }
+    }
+}
@@ -0,0 +1,476 @@
+extern crate pbr;
+extern crate rayon;
+
+use self::pbr::{ProgressBar, Units};
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+use libc::{c_void, uint64_t};
+#[cfg(not(any(target_arch = "x86", target_arch = "x86_64")))]
+use noncegen::generate_nonce;
+use plot::{open_for_writing_using_direct_io, NONCE_SIZE, SCOOPS_IN_NONCE, SCOOP_SIZE};
+use std::cmp::min;
+use std::error::Error;
+use std::fs;
+use std::fs::{File, OpenOptions};
+use std::io::{Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use utils::get_sector_size;
+
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+extern "C" {
+    pub fn noncegen_avx512f(
+        cache: *mut c_void,
+        cache_size: uint64_t,
+        cache_offset: uint64_t,
+        numeric_id: uint64_t,
+        local_startnonce: uint64_t,
+        local_nonces: uint64_t,
+    ) -> ();
+
+    pub fn noncegen_avx2(
+        cache: *mut c_void,
+        cache_size: uint64_t,
+        cache_offset: uint64_t,
+        numeric_id: uint64_t,
+        local_startnonce: uint64_t,
+        local_nonces: uint64_t,
+    ) -> ();
+
+    pub fn noncegen_avx(
+        cache: *mut c_void,
+        cache_size: uint64_t,
+        cache_offset: uint64_t,
+        numeric_id: uint64_t,
+        local_startnonce: uint64_t,
+        local_nonces: uint64_t,
+    ) -> ();
+
+    pub fn noncegen_sse2(
+        cache: *mut c_void,
+        cache_size: uint64_t,
+        cache_offset: uint64_t,
+        numeric_id: uint64_t,
+        local_startnonce: uint64_t,
+        local_nonces: uint64_t,
+    ) -> ();
+}
+
+// the number of nonces each thread gets is a multiple of the widest SIMD lane count
+const NONCES_PER_THREAD_ALIGN: u64 = 16;
+
+pub struct PlotterTask {
+    pub numeric_id: u64,
+    pub start_nonce: u64,
+    pub nonces: u64,
+    pub output_path: String,
+    pub mem: u64,
+    pub cpu_threads: usize,
+    pub direct_io: bool,
+    pub show_progress: bool,
+}
+
+// generate nonces into a scoop ordered cache holding cache_size nonces
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+fn noncegen(
+    cache: *mut u8,
+    cache_size: u64,
+    cache_offset: u64,
+    numeric_id: u64,
+    start_nonce: u64,
+    nonces: u64,
+) {
+    let cache = cache as *mut c_void;
+    unsafe {
+        if is_x86_feature_detected!("avx512f") {
+            noncegen_avx512f(
+                cache,
+                cache_size,
+                cache_offset,
+                numeric_id,
+                start_nonce,
+                nonces,
+            );
+        } else if is_x86_feature_detected!("avx2") {
+            noncegen_avx2(
+                cache,
+                cache_size,
+                cache_offset,
+                numeric_id,
+                start_nonce,
+                nonces,
+            );
+        } else if is_x86_feature_detected!("avx") {
+            noncegen_avx(
+                cache,
+                cache_size,
+                cache_offset,
+                numeric_id,
+                start_nonce,
+                nonces,
+            );
+        } else {
+            noncegen_sse2(
+                cache,
+                cache_size,
+                cache_offset,
+                numeric_id,
+                start_nonce,
+                nonces,
+            );
+        }
+    }
+}
+
+// scalar fallback for cpus without the SIMD kernels
+#[cfg(not(any(target_arch = "x86", target_arch = "x86_64")))]
+fn noncegen(
+    cache: *mut u8,
+    cache_size: u64,
+    cache_offset: u64,
+    numeric_id: u64,
+    start_nonce: u64,
+    nonces: u64,
+) {
+    let scoop_size = SCOOP_SIZE as usize;
+    for i in 0..nonces {
+        let nonce = generate_nonce(numeric_id, start_nonce + i, true);
+        for (scoop, data) in nonce.chunks(scoop_size).enumerate() {
+            let o = (scoop as u64 * cache_size + cache_offset + i) as usize * scoop_size;
+            unsafe {
+                ::std::ptr::copy_nonoverlapping(data.as_ptr(), cache.add(o), scoop_size);
+            }
+        }
+    }
+}
+
+// parse sizes like "512MiB", "4G" or "1073741824"
+pub fn parse_size(s: &str) -> Result<u64, String> {
+    let s = s.trim();
+    let split = s
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(s.len());
+    let (number, unit) = s.split_at(split);
+    let number = number
+        .parse::<u64>()
+        .map_err(|_| format!("invalid size: {}", s))?;
+    let factor = match unit.trim().to_lowercase().as_str() {
+        "" | "b" => 1,
+        "k" | "kb" | "kib" => 1 << 10,
+        "m" | "mb" | "mib" => 1 << 20,
+        "g" | "gb" | "gib" => 1 << 30,
+        "t" | "tb" | "tib" => 1 << 40,
+        _ => return Err(format!("invalid size unit: {}", s)),
+    };
+    Ok(number * factor)
+}
+
+fn progress_path(plot_path: &Path) -> PathBuf {
+    let mut file_name = plot_path.file_name().unwrap().to_os_string();
+    file_name.push(".progress");
+    plot_path.with_file_name(file_name)
+}
+
+fn read_progress(progress_path: &Path) -> Result<u64, Box<Error>> {
+    let progress = fs::read_to_string(progress_path)?;
+    Ok(progress.trim().parse::<u64>()?)
+}
+
+fn write_progress(progress_path: &Path, nonces_written: u64) -> Result<(), Box<Error>> {
+    let mut file = File::create(progress_path)?;
+    file.write_all(nonces_written.to_string().as_bytes())?;
+    file.sync_all()?;
+    Ok(())
+}
+
+// the cache has to be aligned to the sector size for direct io
+fn aligned_cache(size: usize, align: usize) -> (Vec<u8>, usize) {
+    let cache = vec![0u8; size + align];
+    let start = (align - cache.as_ptr() as usize % align) % align;
+    (cache, start)
+}
+
+pub fn plot(task: &PlotterTask) -> Result<PathBuf, Box<Error>> {
+    let plot_path = Path::new(&task.output_path).join(format!(
+        "{}_{}_{}",
+        task.numeric_id, task.start_nonce, task.nonces
+    ));
+    let progress_path = progress_path(&plot_path);
+
+    let mut nonces_written = if plot_path.exists() {
+        if !progress_path.exists() {
+            return Err(From::from(format!(
+                "{} already exists and is complete",
+                plot_path.display()
+            )));
+        }
+        if fs::metadata(&plot_path)?.len() != task.nonces * NONCE_SIZE {
+            return Err(From::from(format!(
+                "{} has the wrong size for resuming",
+                plot_path.display()
+            )));
+        }
+        let nonces_written = read_progress(&progress_path)?;
+        info!(
+            "plotter: resuming {} at nonce {}/{}",
+            plot_path.display(),
+            nonces_written,
+            task.nonces
+        );
+        nonces_written
+    } else {
+        let file = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&plot_path)?;
+        file.set_len(task.nonces * NONCE_SIZE)?;
+        write_progress(&progress_path, 0)?;
+        0
+    };
+
+    let sector_size = get_sector_size(&plot_path.to_str().unwrap().to_owned());
+    let nonces_per_sector = (sector_size / SCOOP_SIZE).max(1);
+    let mut use_direct_io = task.direct_io;
+    // a resumed plot continues where an earlier run, maybe with --ddio or another --mem, stopped
+    if use_direct_io
+        && (task.nonces % nonces_per_sector != 0 || nonces_written % nonces_per_sector != 0)
+    {
+        warn!(
+            "plotter: nonces and resumed progress must be multiples of {} for direct io, \
+             falling back to buffered io",
+            nonces_per_sector
+        );
+        use_direct_io = false;
+    }
+
+    let cache_align = if use_direct_io { nonces_per_sector } else { 1 };
+    let cache_nonces = min(task.mem / NONCE_SIZE, task.nonces) / cache_align * cache_align;
+    if cache_nonces == 0 {
+        return Err(From::from(format!(
+            "not enough memory, need at least {} bytes",
+            cache_align * NONCE_SIZE
+        )));
+    }
+
+    let mut file = if use_direct_io {
+        open_for_writing_using_direct_io(&plot_path)?
+    } else {
+        OpenOptions::new().write(true).open(&plot_path)?
+    };
+
+    info!(
+        "plotter: file={}, threads={}, cache={} nonces, direct io={}",
+        plot_path.display(),
+        task.cpu_threads,
+        cache_nonces,
+        use_direct_io
+    );
+
+    let (mut cache, cache_start) =
+        aligned_cache((cache_nonces * NONCE_SIZE) as usize, sector_size as usize);
+    let cache = &mut cache[cache_start..cache_start + (cache_nonces * NONCE_SIZE) as usize];
+
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(task.cpu_threads)
+        .build()?;
+
+    let mut pb = if task.show_progress {
+        let mut pb = ProgressBar::new(task.nonces * NONCE_SIZE);
+        pb.format("│██░│");
+        pb.set_width(Some(80));
+        pb.set_units(Units::Bytes);
+        pb.message("Plotting: ");
+        pb.add(nonces_written * NONCE_SIZE);
+        Some(pb)
+    } else {
+        None
+    };
+
+    while nonces_written < task.nonces {
+        let nonces = min(cache_nonces, task.nonces - nonces_written);
+        let start_nonce = task.start_nonce + nonces_written;
+
+        let per_thread = (nonces / task.cpu_threads as u64 + NONCES_PER_THREAD_ALIGN - 1)
+            / NONCES_PER_THREAD_ALIGN
+            * NONCES_PER_THREAD_ALIGN;
+        let cache_ptr = cache.as_mut_ptr() as usize;
+        pool.scope(|s| {
+            let mut offset = 0;
+            while offset < nonces {
+                let count = min(per_thread, nonces - offset);
+                s.spawn(move |_| {
+                    noncegen(
+                        cache_ptr as *mut u8,
+                        cache_nonces,
+                        offset,
+                        task.numeric_id,
+                        start_nonce + offset,
+                        count,
+                    );
+                });
+                offset += count;
+            }
+        });
+
+        let len = (nonces * SCOOP_SIZE) as usize;
+        for scoop in 0..SCOOPS_IN_NONCE {
+            let cache_offset = (scoop * cache_nonces * SCOOP_SIZE) as usize;
+            file.seek(SeekFrom::Start(
+                (scoop * task.nonces + nonces_written) * SCOOP_SIZE,
+            ))?;
+            file.write_all(&cache[cache_offset..cache_offset + len])?;
+        }
+        file.sync_data()?;
+
+        nonces_written += nonces;
+        write_progress(&progress_path, nonces_written)?;
+        if let Some(pb) = &mut pb {
+            pb.add(nonces * NONCE_SIZE);
+        }
+    }
+
+    fs::remove_file(&progress_path)?;
+    if let Some(pb) = &mut pb {
+        pb.finish_print("");
+    }
+    info!("plotter: {} finished", plot_path.display());
+    Ok(plot_path)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use noncegen::generate_nonce;
+    use std::env;
+    use std::io::Read;
+
+    #[test]
+    fn test_parse_size() {
+        assert_eq!(parse_size("1024"), Ok(1024));
+        assert_eq!(parse_size("2KiB"), Ok(2048));
+        assert_eq!(parse_size("3 MiB"), Ok(3 << 20));
+        assert_eq!(parse_size("1g"), Ok(1 << 30));
+        assert!(parse_size("1 parsec").is_err());
+        assert!(parse_size("GiB").is_err());
+    }
+
+    #[test]
+    #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+    fn test_noncegen_simd() {
+        let numeric_id = 10282355196851764065;
+        let nonces = 5;
+        let expected: Vec<Vec<u8>> = (0..nonces)
+            .map(|i| generate_nonce(numeric_id, 100 + i, true))
+            .collect();
+
+        let mut variants: Vec<unsafe extern "C" fn(*mut c_void, u64, u64, u64, u64, u64)> =
+            vec![noncegen_sse2];
+        if is_x86_feature_detected!("avx") {
+            variants.push(noncegen_avx);
+        }
+        if is_x86_feature_detected!("avx2") {
+            variants.push(noncegen_avx2);
+        }
+        if is_x86_feature_detected!("avx512f") {
+            variants.push(noncegen_avx512f);
+        }
+
+        for variant in variants {
+            let mut cache = vec![0u8; (nonces * NONCE_SIZE) as usize];
+            unsafe {
+                variant(
+                    cache.as_mut_ptr() as *mut c_void,
+                    nonces,
+                    0,
+                    numeric_id,
+                    100,
+                    nonces,
+                )
+            };
+            for (i, nonce) in expected.iter().enumerate() {
+                for scoop in 0..SCOOPS_IN_NONCE as usize {
+                    let o = (scoop * nonces as usize + i) * SCOOP_SIZE as usize;
+                    let s = scoop * SCOOP_SIZE as usize;
+                    assert_eq!(cache[o..o + 64], nonce[s..s + 64]);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_plot() {
+        let output_path = env::temp_dir().join("scavenger_test_plot");
+        let _ = fs::remove_dir_all(&output_path);
+        fs::create_dir_all(&output_path).unwrap();
+
+        let task = PlotterTask {
+            numeric_id: 10282355196851764065,
+            start_nonce: 3,
+            nonces: 21,
+            output_path: output_path.to_str().unwrap().to_owned(),
+            mem: 8 * NONCE_SIZE,
+            cpu_threads: 2,
+            direct_io: false,
+            show_progress: false,
+        };
+        let plot_path = plot(&task).unwrap();
+        assert!(!progress_path(&plot_path).exists());
+
+        let mut data = Vec::new();
+        File::open(&plot_path)
+            .unwrap()
+            .read_to_end(&mut data)
+            .unwrap();
+        for &offset in &[0, 7, 8, 20] {
+            let nonce = generate_nonce(task.numeric_id, task.start_nonce + offset, true);
+            for scoop in 0..SCOOPS_IN_NONCE {
+                let o = ((scoop * task.nonces + offset) * SCOOP_SIZE) as usize;
+                let s = (scoop * SCOOP_SIZE) as usize;
+                assert_eq!(data[o..o + 64], nonce[s..s + 64]);
+            }
+        }
+
+        // a second run refuses to overwrite the finished plot
+        assert!(plot(&task).is_err());
+        fs::remove_dir_all(&output_path).unwrap();
+    }
+
+    #[test]
+    fn test_resume_unaligned_direct_io() {
+        let output_path = env::temp_dir().join("scavenger_test_plot_resume");
+        let _ = fs::remove_dir_all(&output_path);
+        fs::create_dir_all(&output_path).unwrap();
+
+        let task = PlotterTask {
+            numeric_id: 10282355196851764065,
+            start_nonce: 0,
+            nonces: 64,
+            output_path: output_path.to_str().unwrap().to_owned(),
+            mem: 64 * NONCE_SIZE,
+            cpu_threads: 2,
+            direct_io: true,
+            show_progress: false,
+        };
+        // left behind by a buffered run, direct io can't continue at nonce 3
+        let plot_path = output_path.join("10282355196851764065_0_64");
+        File::create(&plot_path)
+            .unwrap()
+            .set_len(task.nonces * NONCE_SIZE)
+            .unwrap();
+        write_progress(&progress_path(&plot_path), 3).unwrap();
+        assert_eq!(plot(&task).unwrap(), plot_path);
+
+        let mut data = Vec::new();
+        File::open(&plot_path)
+            .unwrap()
+            .read_to_end(&mut data)
+            .unwrap();
+        for &offset in &[3, 63] {
+            let nonce = generate_nonce(task.numeric_id, task.start_nonce + offset, true);
+            for scoop in 0..SCOOPS_IN_NONCE {
+                let o = ((scoop * task.nonces + offset) * SCOOP_SIZE) as usize;
+                let s = (scoop * SCOOP_SIZE) as usize;
+                assert_eq!(data[o..o + 64], nonce[s..s + 64]);
+            }
+        }
+        fs::remove_dir_all(&output_path).unwrap();
+    }
+}
@@ -0,0 +1,307 @@
+use futures::future;
+use futures::Future;
+use requests::{FetchError, MiningInfo};
+use serde_json;
+use std::cell::RefCell;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use tokio_core::reactor::Handle;
+
+// where new blocks come from, polled every get_mining_info_interval
+pub trait MiningInfoSource {
+    fn get_mining_info(&self) -> Box<Future<Item = MiningInfo, Error = FetchError>>;
+}
+
+// where found deadlines go, endpoint is the one of the MiningInfo the round was started with
+// (empty if the block didn't come from a pool)
+pub trait NonceSink {
+    fn submit_nonce(
+        &self,
+        handle: &Handle,
+        endpoint: &str,
+        account_id: u64,
+        nonce: u64,
+        height: u64,
+        deadline: u64,
+    );
+}
+
+// a deadline handed to a NonceSink
+#[derive(Clone, Debug, PartialEq)]
+pub struct SubmittedNonce {
+    pub endpoint: String,
+    pub account_id: u64,
+    pub nonce: u64,
+    pub height: u64,
+    pub deadline: u64,
+}
+
+// checks a mining_info_source or nonce_sink setting of the config
+pub fn is_valid_source(source: &str) -> bool {
+    source == "http" || (source.starts_with("replay:") && source.len() > "replay:".len())
+}
+
+pub fn is_valid_sink(sink: &str) -> bool {
+    sink == "http" || sink == "mock"
+}
+
+// blocks sent from another thread, every poll takes the next one, the last one is repeated once
+// the channel ran dry
+pub struct ChannelSource {
+    rx: Receiver<MiningInfo>,
+    last: RefCell<Option<MiningInfo>>,
+}
+
+impl ChannelSource {
+    pub fn new() -> (Sender<MiningInfo>, ChannelSource) {
+        let (tx, rx) = mpsc::channel();
+        (
+            tx,
+            ChannelSource {
+                rx,
+                last: RefCell::new(None),
+            },
+        )
+    }
+}
+
+impl MiningInfoSource for ChannelSource {
+    fn get_mining_info(&self) -> Box<Future<Item = MiningInfo, Error = FetchError>> {
+        let mut last = self.last.borrow_mut();
+        if let Ok(mining_info) = self.rx.try_recv() {
+            *last = Some(mining_info);
+        }
+        match *last {
+            Some(ref mining_info) => Box::new(future::ok(mining_info.clone())),
+            None => Box::new(future::err(FetchError::Empty)),
+        }
+    }
+}
+
+// replays getMiningInfo responses, one json object per line, from a file or stdin ("-")
+pub fn replay_source(path: &str) -> io::Result<ChannelSource> {
+    let input: Box<BufRead + Send> = if path == "-" {
+        Box::new(BufReader::new(io::stdin()))
+    } else {
+        Box::new(BufReader::new(File::open(path)?))
+    };
+    let (tx, source) = ChannelSource::new();
+    let path = path.to_owned();
+    thread::spawn(move || {
+        for (i, line) in input.lines().enumerate() {
+            let line = match line {
+                Ok(line) => line,
+                Err(e) => {
+                    error!("replay {}: {}", path, e);
+                    return;
+                }
+            };
+            if line.trim().is_empty() {
+                continue;
+            }
+            match serde_json::from_str(&line) {
+                Ok(mining_info) => {
+                    if tx.send(mining_info).is_err() {
+                        return;
+                    }
+                }
+                Err(e) => warn!("replay {}: line {}: {}", path, i + 1, e),
+            }
+        }
+    });
+    Ok(source)
+}
+
+// forwards found deadlines to another thread
+pub struct ChannelSink {
+    tx: Sender<SubmittedNonce>,
+}
+
+impl ChannelSink {
+    pub fn new() -> (ChannelSink, Receiver<SubmittedNonce>) {
+        let (tx, rx) = mpsc::channel();
+        (ChannelSink { tx }, rx)
+    }
+}
+
+impl NonceSink for ChannelSink {
+    fn submit_nonce(
+        &self,
+        _handle: &Handle,
+        endpoint: &str,
+        account_id: u64,
+        nonce: u64,
+        height: u64,
+        deadline: u64,
+    ) {
+        // the receiving end is gone, nobody cares anymore
+        let _ = self.tx.send(SubmittedNonce {
+            endpoint: endpoint.to_owned(),
+            account_id,
+            nonce,
+            height,
+            deadline,
+        });
+    }
+}
+
+// local stand-in for a pool: serves the block set with new_block and accepts every deadline,
+// clones share their state so one can stay with the test while the other is handed to the miner
+#[derive(Clone, Default)]
+pub struct MockPool {
+    state: Arc<Mutex<MockState>>,
+}
+
+#[derive(Default)]
+struct MockState {
+    mining_info: Option<MiningInfo>,
+    submissions: Vec<SubmittedNonce>,
+}
+
+impl MockPool {
+    pub fn new() -> MockPool {
+        MockPool::default()
+    }
+
+    pub fn new_block(&self, mining_info: MiningInfo) {
+        self.state.lock().unwrap().mining_info = Some(mining_info);
+    }
+
+    pub fn submissions(&self) -> Vec<SubmittedNonce> {
+        self.state.lock().unwrap().submissions.clone()
+    }
+}
+
+impl MiningInfoSource for MockPool {
+    fn get_mining_info(&self) -> Box<Future<Item = MiningInfo, Error = FetchError>> {
+        match self.state.lock().unwrap().mining_info {
+            Some(ref mining_info) => Box::new(future::ok(mining_info.clone())),
+            None => Box::new(future::err(FetchError::Empty)),
+        }
+    }
+}
+
+impl NonceSink for MockPool {
+    fn submit_nonce(
+        &self,
+        _handle: &Handle,
+        endpoint: &str,
+        account_id: u64,
+        nonce: u64,
+        height: u64,
+        deadline: u64,
+    ) {
+        debug!(
+            "mock pool: accepted height={}, account={}, nonce={}, deadline={}",
+            height, account_id, nonce, deadline
+        );
+        self.state.lock().unwrap().submissions.push(SubmittedNonce {
+            endpoint: endpoint.to_owned(),
+            account_id,
+            nonce,
+            height,
+            deadline,
+        });
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::env;
+    use std::fs;
+    use std::time::Duration;
+    use tokio_core::reactor::Core;
+
+    fn mining_info(height: u64) -> MiningInfo {
+        MiningInfo {
+            generation_signature: "00".repeat(32),
+            base_target: 1,
+            height,
+            target_deadline: u64::MAX,
+            endpoint: String::new(),
+        }
+    }
+
+    #[test]
+    fn test_channel_source() {
+        let (tx, source) = ChannelSource::new();
+        assert!(source.get_mining_info().wait().is_err());
+        tx.send(mining_info(1)).unwrap();
+        tx.send(mining_info(2)).unwrap();
+        assert_eq!(source.get_mining_info().wait().ok().unwrap().height, 1);
+        assert_eq!(source.get_mining_info().wait().ok().unwrap().height, 2);
+        drop(tx);
+        assert_eq!(source.get_mining_info().wait().ok().unwrap().height, 2);
+    }
+
+    #[test]
+    fn test_replay_source() {
+        let path = env::temp_dir().join("scavenger_test_replay.jsonl");
+        let gensig = "00".repeat(32);
+        fs::write(
+            &path,
+            format!(
+                "{{\"generationSignature\":\"{0}\",\"baseTarget\":\"70000\",\"height\":\"5\"}}\n\
+                 \n\
+                 not json\n\
+                 {{\"generationSignature\":\"{0}\",\"baseTarget\":70000,\"height\":6,\
+                 \"targetDeadline\":100}}\n",
+                gensig
+            ),
+        ).unwrap();
+        let source = replay_source(path.to_str().unwrap()).unwrap();
+        // the lines are parsed on another thread
+        let mut heights = Vec::new();
+        for _ in 0..500 {
+            if let Ok(mining_info) = source.get_mining_info().wait() {
+                if heights.last() != Some(&mining_info.height) {
+                    heights.push(mining_info.height);
+                }
+                if mining_info.height == 6 {
+                    assert_eq!(mining_info.target_deadline, 100);
+                    break;
+                }
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        assert_eq!(heights, vec![5, 6]);
+        fs::remove_file(&path).unwrap();
+
+        assert!(replay_source("/nonexistent/replay.jsonl").is_err());
+    }
+
+    #[test]
+    fn test_sinks() {
+        let core = Core::new().unwrap();
+        let (sink, rx) = ChannelSink::new();
+        sink.submit_nonce(&core.handle(), "", 1, 2, 3, 4);
+        assert_eq!(
+            rx.recv().unwrap(),
+            SubmittedNonce {
+                endpoint: String::new(),
+                account_id: 1,
+                nonce: 2,
+                height: 3,
+                deadline: 4,
+            }
+        );
+
+        let pool = MockPool::new();
+        assert!(pool.get_mining_info().wait().is_err());
+        pool.new_block(mining_info(7));
+        let miner_side = pool.clone();
+        assert_eq!(miner_side.get_mining_info().wait().ok().unwrap().height, 7);
+        miner_side.submit_nonce(&core.handle(), "", 1, 2, 7, 4);
+        assert_eq!(pool.submissions().len(), 1);
+
+        assert!(is_valid_source("http"));
+        assert!(is_valid_source("replay:-"));
+        assert!(!is_valid_source("replay:"));
+        assert!(is_valid_sink("mock"));
+        assert!(!is_valid_sink("file"));
+    }
+}
@@ -0,0 +1,124 @@
+use std::cmp::min;
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+// the back-off doubles with every failed probe up to 2^MAX_BACKOFF_EXP times the base
+const MAX_BACKOFF_EXP: u32 = 6;
+
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum Kind {
+    Drive,
+    Plot,
+}
+
+impl fmt::Display for Kind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Kind::Drive => write!(f, "drive"),
+            Kind::Plot => write!(f, "plot"),
+        }
+    }
+}
+
+#[derive(Default)]
+struct Entry {
+    // consecutive read errors
+    errors: u32,
+    // consecutive quarantines without a successful read in between
+    strikes: u32,
+    until: Option<Instant>,
+}
+
+// keeps failing plots and drives out of the scan for a while
+pub struct Quarantine {
+    after: u32,
+    backoff: Duration,
+    entries: Mutex<HashMap<(Kind, String), Entry>>,
+}
+
+impl Quarantine {
+    // after = 0 disables the quarantine
+    pub fn new(after: u32, backoff_secs: u64) -> Quarantine {
+        Quarantine {
+            after,
+            backoff: Duration::from_secs(backoff_secs),
+            entries: Mutex::new(HashMap::new()),
+        }
+    }
+
+    // once the back-off expired the next read is a probe
+    pub fn is_quarantined(&self, kind: Kind, name: &str) -> bool {
+        let entries = self.entries.lock().unwrap();
+        match entries.get(&(kind, name.to_owned())).and_then(|e| e.until) {
+            Some(until) => Instant::now() < until,
+            None => false,
+        }
+    }
+
+    // returns the end of the quarantine if this error caused one
+    pub fn failed(&self, kind: Kind, name: &str) -> Option<Instant> {
+        if self.after == 0 {
+            return None;
+        }
+        let mut entries = self.entries.lock().unwrap();
+        let entry = entries.entry((kind, name.to_owned())).or_default();
+        entry.errors += 1;
+
+        // a failed probe sends it straight back
+        if entry.errors < self.after && entry.strikes == 0 {
+            return None;
+        }
+        let until = Instant::now() + self.backoff * 2u32.pow(min(entry.strikes, MAX_BACKOFF_EXP));
+        entry.errors = 0;
+        entry.strikes += 1;
+        entry.until = Some(until);
+        Some(until)
+    }
+
+    // returns true if this read lifted a quarantine
+    pub fn succeeded(&self, kind: Kind, name: &str) -> bool {
+        let mut entries = self.entries.lock().unwrap();
+        match entries.remove(&(kind, name.to_owned())) {
+            Some(entry) => entry.strikes > 0,
+            None => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_quarantine() {
+        let quarantine = Quarantine::new(2, 0);
+        assert_eq!(quarantine.failed(Kind::Plot, "a"), None);
+        assert!(!quarantine.is_quarantined(Kind::Plot, "a"));
+        assert!(quarantine.failed(Kind::Plot, "a").is_some());
+        // a zero back-off has expired right away
+        assert!(!quarantine.is_quarantined(Kind::Plot, "a"));
+
+        // failed probe
+        assert!(quarantine.failed(Kind::Plot, "a").is_some());
+        assert!(!quarantine.succeeded(Kind::Drive, "a"));
+        assert!(quarantine.succeeded(Kind::Plot, "a"));
+        assert!(!quarantine.succeeded(Kind::Plot, "a"));
+        assert_eq!(quarantine.failed(Kind::Plot, "a"), None);
+
+        let quarantine = Quarantine::new(1, 60);
+        let now = Instant::now();
+        let until = quarantine.failed(Kind::Drive, "sda").unwrap();
+        assert!(until >= now + Duration::from_secs(60));
+        assert!(quarantine.is_quarantined(Kind::Drive, "sda"));
+        assert!(!quarantine.is_quarantined(Kind::Plot, "sda"));
+        let until = quarantine.failed(Kind::Drive, "sda").unwrap();
+        assert!(until >= now + Duration::from_secs(120));
+
+        let quarantine = Quarantine::new(0, 60);
+        for _ in 0..10 {
+            assert_eq!(quarantine.failed(Kind::Drive, "sda"), None);
+        }
+    }
+}
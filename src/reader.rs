@@ -4,19 +4,23 @@ extern crate rayon;
 use self::pbr::{ProgressBar, Units};
 use chan;
 use filetime::FileTime;
+use metrics::Metrics;
 use miner::Buffer;
 use plot::Plot;
+use quarantine::{Kind, Quarantine};
 use reader::rayon::prelude::*;
 use std::collections::HashMap;
 use std::io::Stdout;
 use std::sync::mpsc::{channel, Sender, TryRecvError};
 use std::sync::RwLock;
 use std::sync::{Arc, Mutex};
+use std::time::Instant;
 use stopwatch::Stopwatch;
 
 pub struct ReadReply {
     pub buffer: Box<Buffer + Send>,
     pub len: usize,
+    pub chain: usize,
     pub height: u64,
     pub gensig: Arc<[u8; 32]>,
     pub start_nonce: u64,
@@ -26,6 +30,10 @@ pub struct ReadReply {
 
 pub struct Reader {
     drive_id_to_plots: HashMap<String, Arc<Mutex<Vec<RwLock<Plot>>>>>,
+    // bytes read per round, cached as running tasks hold the plot locks
+    drive_sizes: HashMap<String, u64>,
+    // drive -> bytes per second of the previous rounds
+    throughputs: Arc<Mutex<HashMap<String, u64>>>,
     total_size: u64,
     pool: rayon::ThreadPool,
     rx_empty_buffers: chan::Receiver<Box<Buffer + Send>>,
@@ -34,6 +42,8 @@ pub struct Reader {
     interupts: Vec<Sender<()>>,
     show_progress: bool,
     show_drive_stats: bool,
+    metrics: Arc<Metrics>,
+    quarantine: Arc<Quarantine>,
 }
 
 impl Reader {
@@ -46,19 +56,15 @@ impl Reader {
         tx_read_replies_gpu: chan::Sender<ReadReply>,
         show_progress: bool,
         show_drive_stats: bool,
+        metrics: Arc<Metrics>,
+        quarantine: Arc<Quarantine>,
     ) -> Reader {
-        for plots in drive_id_to_plots.values() {
-            let mut plots = plots.lock().unwrap();
-            plots.sort_by_key(|p| {
-                let m = p.read().unwrap().fh.metadata().unwrap();
-                -FileTime::from_last_modification_time(&m).unix_seconds()
-            });
-        }
-
-        check_overlap(&drive_id_to_plots);
+        prepare_plots(&drive_id_to_plots);
 
         Reader {
+            drive_sizes: drive_sizes(&drive_id_to_plots),
             drive_id_to_plots,
+            throughputs: Arc::new(Mutex::new(HashMap::new())),
             total_size,
             pool: rayon::ThreadPoolBuilder::new()
                 .num_threads(num_threads)
@@ -70,10 +76,19 @@ impl Reader {
             interupts: Vec::new(),
             show_progress,
             show_drive_stats,
+            metrics,
+            quarantine,
         }
     }
 
-    pub fn start_reading(&mut self, height: u64, scoop: u32, gensig: &Arc<[u8; 32]>) {
+    pub fn start_reading(
+        &mut self,
+        chain: usize,
+        height: u64,
+        scoop: u32,
+        poc2: bool,
+        gensig: &Arc<[u8; 32]>,
+    ) {
         for interupt in &self.interupts {
             interupt.send(()).ok();
         }
@@ -85,17 +100,37 @@ impl Reader {
         pb.message("Scavenging: ");
         let pb = Arc::new(Mutex::new(pb));
 
-        self.interupts = self
-            .drive_id_to_plots
+        // slow and large drives first, so that none of them is left alone at the end of the round
+        let drives: Vec<(String, u64)> = self
+            .drive_sizes
             .iter()
-            .map(|(drive, plots)| {
+            .map(|(drive, &size)| (drive.clone(), size))
+            .collect();
+        let (order, predicted_ms) = schedule(
+            &drives,
+            &self.throughputs.lock().unwrap(),
+            self.pool.current_num_threads(),
+        );
+        if let Some(predicted_ms) = predicted_ms {
+            info!(
+                "{: <80}",
+                format!("reader: predicted roundtime={}ms", predicted_ms)
+            );
+        }
+
+        self.interupts = order
+            .iter()
+            .map(|drive| {
+                let plots = &self.drive_id_to_plots[drive];
                 let (interupt, task) = if self.show_progress {
                     self.create_read_task(
                         Some(pb.clone()),
                         drive.clone(),
                         plots.clone(),
+                        chain,
                         height,
                         scoop,
+                        poc2,
                         gensig.clone(),
                         self.show_drive_stats,
                     )
@@ -104,8 +139,10 @@ impl Reader {
                         None,
                         drive.clone(),
                         plots.clone(),
+                        chain,
                         height,
                         scoop,
+                        poc2,
                         gensig.clone(),
                         self.show_drive_stats,
                     )
@@ -116,6 +153,22 @@ impl Reader {
             }).collect();
     }
 
+    // must not be called while a scan is in progress
+    pub fn update_plots(
+        &mut self,
+        drive_id_to_plots: HashMap<String, Arc<Mutex<Vec<RwLock<Plot>>>>>,
+        total_size: u64,
+    ) {
+        prepare_plots(&drive_id_to_plots);
+        self.drive_sizes = drive_sizes(&drive_id_to_plots);
+        self.drive_id_to_plots = drive_id_to_plots;
+        self.total_size = total_size;
+    }
+
+    pub fn drive_count(&self) -> usize {
+        self.drive_id_to_plots.len()
+    }
+
     pub fn wakeup(&mut self) {
         for plots in self.drive_id_to_plots.values() {
             let plots = plots.clone();
@@ -138,8 +191,10 @@ impl Reader {
         pb: Option<Arc<Mutex<pbr::ProgressBar<Stdout>>>>,
         drive: String,
         plots: Arc<Mutex<Vec<RwLock<Plot>>>>,
+        chain: usize,
         height: u64,
         scoop: u32,
+        poc2: bool,
         gensig: Arc<[u8; 32]>,
         show_drive_stats: bool,
     ) -> (Sender<()>, impl FnOnce()) {
@@ -150,73 +205,81 @@ impl Reader {
         let tx_read_replies_gpu = self.tx_read_replies_gpu.clone();
         #[cfg(not(feature = "opencl"))]
         let _tx_read_replies_gpu = self.tx_read_replies_gpu.clone();
+        let metrics = self.metrics.clone();
+        let quarantine = self.quarantine.clone();
+        let throughputs = self.throughputs.clone();
         (tx_interupt, move || {
+            let send_reply = |read_reply: ReadReply| {
+                #[cfg(feature = "opencl")]
+                match read_reply.buffer.get_gpu_context() {
+                    None => tx_read_replies_cpu.send(read_reply),
+                    Some(_context) => tx_read_replies_gpu.send(read_reply),
+                }
+                #[cfg(not(feature = "opencl"))]
+                tx_read_replies_cpu.send(read_reply);
+            };
+
             let mut sw = Stopwatch::new();
             let mut elapsed = 0i64;
             let mut nonces_processed = 0u64;
+            let mut finished_sent = false;
             let plots = plots.lock().unwrap();
             let plot_count = plots.len();
+            let drive_size: u64 = plots.iter().map(|p| p.read().unwrap().nonces * 64).sum();
+            metrics.drive_started(&drive, drive_size);
+
+            let drive_quarantined = quarantine.is_quarantined(Kind::Drive, &drive);
+            if drive_quarantined {
+                warn!("{: <80}", format!("reader: drive {} is quarantined", drive));
+            }
+
             'outer: for (i_p, p) in plots.iter().enumerate() {
+                if drive_quarantined {
+                    break 'outer;
+                }
                 let mut p = p.write().unwrap();
+                if quarantine.is_quarantined(Kind::Plot, &p.name) {
+                    continue 'outer;
+                }
                 if let Err(e) = p.prepare(scoop) {
                     error!(
                         "reader: error preparing {} for reading: {} -> skip one round",
                         p.name, e
                     );
+                    read_failed(&quarantine, &metrics, &drive, &p.name);
                     continue 'outer;
                 }
 
+                let mut plot_ok = false;
                 'inner: for mut buffer in rx_empty_buffers.clone() {
-                    if show_drive_stats {
-                        sw.restart();
-                    }
+                    sw.restart();
                     let mut_bs = &*buffer.get_buffer_for_writing();
                     let mut bs = mut_bs.lock().unwrap();
-                    let (bytes_read, start_nonce, next_plot) = match p.read(&mut *bs, scoop) {
-                        Ok(x) => x,
+                    let (bytes_read, start_nonce, next_plot) = match p.read(&mut *bs, scoop, poc2) {
+                        Ok(x) => {
+                            if !plot_ok {
+                                read_succeeded(&quarantine, &metrics, &drive, &p.name);
+                                plot_ok = true;
+                            }
+                            x
+                        }
                         Err(e) => {
                             error!(
                                 "reader: error reading chunk from {}: {} -> skip one round",
                                 p.name, e
                             );
+                            read_failed(&quarantine, &metrics, &drive, &p.name);
                             (0, 0, true)
                         }
                     };
 
                     let finished = i_p == (plot_count - 1) && next_plot;
+                    finished_sent |= finished;
                     //fork
-
-                    #[cfg(feature = "opencl")]
-                    let gpu_context = buffer.get_gpu_context();
-                    #[cfg(feature = "opencl")]
-                    match &gpu_context {
-                        None => {
-                            tx_read_replies_cpu.send(ReadReply {
-                                buffer,
-                                len: bytes_read,
-                                height,
-                                gensig: gensig.clone(),
-                                start_nonce,
-                                finished,
-                                account_id: p.account_id,
-                            });
-                        }
-                        Some(_context) => {
-                            tx_read_replies_gpu.send(ReadReply {
-                                buffer,
-                                len: bytes_read,
-                                height,
-                                gensig: gensig.clone(),
-                                start_nonce,
-                                finished,
-                                account_id: p.account_id,
-                            });
-                        }
-                    }
-                    #[cfg(not(feature = "opencl"))]
-                    tx_read_replies_cpu.send(ReadReply {
+                    send_reply(ReadReply {
                         buffer,
                         len: bytes_read,
+                        chain,
                         height,
                         gensig: gensig.clone(),
                         start_nonce,
@@ -225,6 +288,7 @@ impl Reader {
                     });
 
                     nonces_processed += bytes_read as u64 / 64;
+                    metrics.drive_read(&drive, bytes_read as u64);
 
                     match &pb {
                         Some(pb) => {
@@ -234,33 +298,160 @@ impl Reader {
                         None => (),
                     }
 
-                    if show_drive_stats {
-                        elapsed += sw.elapsed_ms();
-                    }
-
-                    if finished && show_drive_stats {
-                        info!(
-                            "{: <80}",
-                            format!(
-                                "drive {} finished, speed={} MiB/s",
-                                drive,
-                                nonces_processed * 1000 / (elapsed + 1) as u64 * 64 / 1024 / 1024,
-                            )
-                        );
-                    }
+                    elapsed += sw.elapsed_ms();
 
                     if next_plot {
                         break 'inner;
                     }
                     if rx_interupt.try_recv() != Err(TryRecvError::Empty) {
-                        break 'outer;
+                        return;
                     }
                 }
             }
+
+            // the last plot was skipped, the round still needs to know this drive is done
+            if !finished_sent {
+                if let Some(buffer) = rx_empty_buffers.recv() {
+                    send_reply(ReadReply {
+                        buffer,
+                        len: 0,
+                        chain,
+                        height,
+                        gensig: gensig.clone(),
+                        start_nonce: 0,
+                        finished: true,
+                        account_id: 0,
+                    });
+                }
+            }
+
+            if nonces_processed == 0 {
+                return;
+            }
+            metrics.drive_finished(&drive, nonces_processed * 64, elapsed);
+            let throughput = nonces_processed * 64 * 1000 / (elapsed + 1) as u64;
+            throughputs
+                .lock()
+                .unwrap()
+                .entry(drive.clone())
+                .and_modify(|average| *average = (*average + throughput) / 2)
+                .or_insert(throughput);
+            if show_drive_stats {
+                info!(
+                    "{: <80}",
+                    format!(
+                        "drive {} finished, speed={} MiB/s",
+                        drive,
+                        nonces_processed * 1000 / (elapsed + 1) as u64 * 64 / 1024 / 1024,
+                    )
+                );
+            }
         })
     }
 }
 
+fn read_failed(quarantine: &Quarantine, metrics: &Metrics, drive: &str, plot: &str) {
+    metrics.read_error(drive);
+    for &(kind, name) in &[(Kind::Plot, plot), (Kind::Drive, drive)] {
+        if let Some(until) = quarantine.failed(kind, name) {
+            warn!(
+                "{: <80}",
+                format!(
+                    "reader: too many errors on {} {}, quarantined for {}s",
+                    kind,
+                    name,
+                    until.duration_since(Instant::now()).as_secs()
+                )
+            );
+            metrics.quarantined(kind, name, until);
+        }
+    }
+}
+
+fn read_succeeded(quarantine: &Quarantine, metrics: &Metrics, drive: &str, plot: &str) {
+    for &(kind, name) in &[(Kind::Plot, plot), (Kind::Drive, drive)] {
+        if quarantine.succeeded(kind, name) {
+            info!(
+                "{: <80}",
+                format!("reader: {} {} recovered, quarantine lifted", kind, name)
+            );
+            metrics.released(kind, name);
+        }
+    }
+}
+
+fn drive_sizes(
+    drive_id_to_plots: &HashMap<String, Arc<Mutex<Vec<RwLock<Plot>>>>>,
+) -> HashMap<String, u64> {
+    drive_id_to_plots
+        .iter()
+        .map(|(drive, plots)| {
+            let plots = plots.lock().unwrap();
+            let size = plots.iter().map(|p| p.read().unwrap().nonces * 64).sum();
+            (drive.clone(), size)
+        }).collect()
+}
+
+// orders (drive, size) by expected read time, longest first, and predicts the round time of
+// reading them with a number of threads. drives without a measured throughput are assumed to be
+// as fast as the average, without any measurement there is only the size and no prediction.
+fn schedule(
+    drives: &[(String, u64)],
+    throughputs: &HashMap<String, u64>,
+    threads: usize,
+) -> (Vec<String>, Option<u64>) {
+    let known: Vec<u64> = drives
+        .iter()
+        .filter_map(|(drive, _)| throughputs.get(drive))
+        .cloned()
+        .filter(|&throughput| throughput > 0)
+        .collect();
+    let average = if known.is_empty() {
+        None
+    } else {
+        Some(known.iter().sum::<u64>() / known.len() as u64)
+    };
+
+    let mut expected: Vec<(u64, u64, &str)> = drives
+        .iter()
+        .map(|(drive, size)| {
+            let throughput = throughputs.get(drive).cloned().filter(|&t| t > 0);
+            let ms = match throughput.or(average) {
+                Some(throughput) => size * 1000 / throughput,
+                None => 0,
+            };
+            (ms, *size, drive.as_str())
+        }).collect();
+    expected.sort_by(|a, b| b.cmp(a));
+
+    // each drive goes to the thread that becomes free first
+    let mut loads = vec![0u64; threads.max(1)];
+    for &(ms, _, _) in &expected {
+        *loads.iter_mut().min().unwrap() += ms;
+    }
+    let predicted_ms = average.map(|_| loads.into_iter().max().unwrap());
+
+    (
+        expected
+            .into_iter()
+            .map(|(_, _, drive)| drive.to_owned())
+            .collect(),
+        predicted_ms,
+    )
+}
+
+fn prepare_plots(drive_id_to_plots: &HashMap<String, Arc<Mutex<Vec<RwLock<Plot>>>>>) {
+    for plots in drive_id_to_plots.values() {
+        let mut plots = plots.lock().unwrap();
+        plots.sort_by_key(|p| {
+            let m = p.read().unwrap().fh.metadata().unwrap();
+            -FileTime::from_last_modification_time(&m).unix_seconds()
+        });
+    }
+
+    check_overlap(drive_id_to_plots);
+}
+
 // Don't waste your time striving for perfection; instead, strive for excellence - doing your best.
 // let my_best = perfection;
 pub fn check_overlap(drive_id_to_plots: &HashMap<String, Arc<Mutex<Vec<RwLock<Plot>>>>>) -> bool {
@@ -300,3 +491,38 @@ pub fn check_overlap(drive_id_to_plots: &HashMap<String, Arc<Mutex<Vec<RwLock<Pl
     }
     result
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn drives() -> Vec<(String, u64)> {
+        vec![
+            ("a".to_owned(), 1000),
+            ("b".to_owned(), 4000),
+            ("c".to_owned(), 2000),
+            ("d".to_owned(), 2000),
+        ]
+    }
+
+    #[test]
+    fn test_schedule() {
+        // nothing measured yet, the largest drives go first
+        let (order, predicted_ms) = schedule(&drives(), &HashMap::new(), 2);
+        assert_eq!(order, vec!["b", "d", "c", "a"]);
+        assert_eq!(predicted_ms, None);
+
+        // b turned out to be fast and c slow, a is assumed to have the average of 2000 B/s
+        let mut throughputs = HashMap::new();
+        throughputs.insert("b".to_owned(), 4000);
+        throughputs.insert("c".to_owned(), 500);
+        throughputs.insert("d".to_owned(), 1500);
+        let (order, predicted_ms) = schedule(&drives(), &throughputs, 2);
+        assert_eq!(order, vec!["c", "d", "b", "a"]);
+        // c: 4000ms on one thread, d: 1333ms + b: 1000ms + a: 500ms on the other
+        assert_eq!(predicted_ms, Some(4000));
+
+        let (_, predicted_ms) = schedule(&drives(), &throughputs, 1);
+        assert_eq!(predicted_ms, Some(4000 + 1333 + 1000 + 500));
+    }
+}
@@ -3,33 +3,97 @@ extern crate hyper_rustls;
 extern crate serde_json;
 extern crate url;
 
+use burstmath::to_rs_address;
 use futures::future;
 use hyper::client::HttpConnector;
 use hyper::rt::{Future, Stream};
 use hyper::{Client, Request};
+use journal::{Event, Journal};
+use metrics::{Metrics, Submission};
+use pool::{MiningInfoSource, NonceSink};
 use serde::de::{self, DeserializeOwned};
 use std::collections::HashMap;
 use std::fmt;
 use std::io;
+use std::sync::{Arc, Mutex};
 use std::time::Duration;
 use std::u64;
 use tokio_core::reactor::{Handle, Timeout};
 use url::form_urlencoded::byte_serialize;
 
+const MINING_INFO_PATH: &str = "/burst?requestType=getMiningInfo";
+
 #[derive(Clone)]
 pub struct RequestHandler {
+    chain: String,
     account_id_to_secret_phrase: HashMap<u64, String>,
-    base_uri: String,
+    endpoints: Arc<Mutex<Endpoints>>,
+    failover_after: usize,
+    metrics: Arc<Metrics>,
+    journal: Arc<Journal>,
     client: Client<hyper_rustls::HttpsConnector<HttpConnector>>,
     timeout: Duration,
     handle: Handle,
     ua: String,
 }
 
+// ordered pool/wallet urls, the first one is the primary
+struct Endpoints {
+    uris: Vec<String>,
+    // consecutive failed getMiningInfo requests per endpoint
+    failures: Vec<usize>,
+    active: usize,
+}
+
+impl Endpoints {
+    fn new(uris: Vec<String>) -> Endpoints {
+        Endpoints {
+            failures: vec![0; uris.len()],
+            uris,
+            active: 0,
+        }
+    }
+
+    fn active(&self) -> (usize, String) {
+        (self.active, self.uris[self.active].clone())
+    }
+
+    fn succeeded(&mut self, endpoint: usize) {
+        self.failures[endpoint] = 0;
+        if endpoint == 0 && self.active != 0 {
+            info!(
+                "{: <80}",
+                format!("primary {} recovered, failing back", self.uris[0])
+            );
+            self.active = 0;
+        }
+    }
+
+    fn failed(&mut self, endpoint: usize, failover_after: usize) {
+        self.failures[endpoint] += 1;
+        if endpoint == self.active
+            && self.uris.len() > 1
+            && self.failures[endpoint] >= failover_after
+        {
+            self.active = (self.active + 1) % self.uris.len();
+            self.failures[self.active] = 0;
+            warn!(
+                "{: <80}",
+                format!(
+                    "{} failed {} times, failing over to {}",
+                    self.uris[endpoint], failover_after, self.uris[self.active]
+                )
+            );
+        }
+    }
+}
+
 pub enum FetchError {
     Http(hyper::Error),
     Pool(PoolError),
     Timeout(io::Error),
+    // the source has no block yet
+    Empty,
 }
 
 impl From<hyper::Error> for FetchError {
@@ -50,7 +114,7 @@ impl From<io::Error> for FetchError {
     }
 }
 
-#[derive(Deserialize)]
+#[derive(Clone, Debug, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct MiningInfo {
     pub generation_signature: String,
@@ -63,6 +127,10 @@ pub struct MiningInfo {
 
     #[serde(default = "default_target_deadline", deserialize_with = "from_str_or_int")]
     pub target_deadline: u64,
+
+    // the url that issued this block
+    #[serde(skip)]
+    pub endpoint: String,
 }
 
 fn default_target_deadline() -> u64 {
@@ -116,9 +184,13 @@ where
 
 impl RequestHandler {
     pub fn new(
-        base_uri: String,
+        chain: &str,
+        uris: Vec<String>,
         mut secret_phrases: HashMap<u64, String>,
         timeout: u64,
+        failover_after: usize,
+        metrics: Arc<Metrics>,
+        journal: Arc<Journal>,
         handle: Handle,
     ) -> RequestHandler {
         for secret_phrase in secret_phrases.values_mut() {
@@ -128,8 +200,12 @@ impl RequestHandler {
         let client: Client<_, hyper::Body> = Client::builder().build(https);
 
         RequestHandler {
+            chain: chain.to_owned(),
             account_id_to_secret_phrase: secret_phrases,
-            base_uri,
+            endpoints: Arc::new(Mutex::new(Endpoints::new(uris))),
+            failover_after,
+            metrics,
+            journal,
             client,
             timeout: Duration::from_millis(timeout),
             handle,
@@ -138,12 +214,51 @@ impl RequestHandler {
     }
 
     pub fn get_mining_info(&self) -> Box<Future<Item = MiningInfo, Error = FetchError>> {
-        Box::new(self.do_req(self.get_req("/burst?requestType=getMiningInfo")))
+        let (active, uri) = self.endpoints.lock().unwrap().active();
+        if active != 0 {
+            self.probe_primary();
+        }
+
+        let endpoints = self.endpoints.clone();
+        let failover_after = self.failover_after;
+        Box::new(
+            self.do_req(self.get_req(&uri, MINING_INFO_PATH))
+                .then(move |result: Result<MiningInfo, FetchError>| {
+                    let mut endpoints = endpoints.lock().unwrap();
+                    match result {
+                        Ok(mut mining_info) => {
+                            endpoints.succeeded(active);
+                            mining_info.endpoint = uri;
+                            Ok(mining_info)
+                        }
+                        Err(e) => {
+                            endpoints.failed(active, failover_after);
+                            Err(e)
+                        }
+                    }
+                }),
+        )
+    }
+
+    // check whether the primary is back while we are mining on a fallback
+    fn probe_primary(&self) {
+        let endpoints = self.endpoints.clone();
+        let uri = endpoints.lock().unwrap().uris[0].clone();
+        self.handle.spawn(
+            self.do_req(self.get_req(&uri, MINING_INFO_PATH))
+                .then(move |result: Result<MiningInfo, FetchError>| {
+                    if result.is_ok() {
+                        endpoints.lock().unwrap().succeeded(0);
+                    }
+                    future::ok(())
+                }),
+        );
     }
 
     pub fn submit_nonce(
         &self,
         handle: &Handle,
+        endpoint: &str,
         account_id: u64,
         nonce: u64,
         height: u64,
@@ -156,36 +271,56 @@ impl RequestHandler {
             .get(&account_id)
             .unwrap_or(&empty);
 
-        let mut path = format!(
-            "/burst?requestType=submitNonce&accountId={}&nonce={}&secretPhrase={}&blockheight={}",
+        let params = format!(
+            "requestType=submitNonce&accountId={}&nonce={}&secretPhrase={}&blockheight={}",
             account_id, nonce, secret_phrase_encoded, height
         );
-        // if pool mining also send the deadline (usefull for proxies)
-        if secret_phrase_encoded == "" {
-            path += &format!("&deadline={}", d);
-        }
-
-        let req = self.post_req(&path);
+        let req = if secret_phrase_encoded == "" {
+            // if pool mining also send the deadline (usefull for proxies)
+            self.post_req(endpoint, &format!("/burst?{}&deadline={}", params, d))
+        } else {
+            // urls end up in proxy logs, the passphrase goes into the body
+            self.post_form_req(endpoint, "/burst", params)
+        };
 
         let rh = self.clone();
         let inner_handle = handle.clone();
+        let endpoint = endpoint.to_owned();
         handle.spawn(self.do_req(req).then(
             move |result: Result<SubmitNonceResonse, FetchError>| {
                 match result {
                     Ok(result) => {
+                        let mut submission =
+                            Submission::new(&rh.chain, &endpoint, account_id, height, nonce, d);
+                        submission.accepted = true;
+                        submission.pool_deadline = Some(result.deadline);
+                        rh.submitted(submission);
                         if d != result.deadline {
                             error!(
                                 "submit: deadlines mismatch, height={}, account={}, nonce={}, \
                                  deadline_miner={}, deadline_pool={}",
-                                height, account_id, nonce, d, result.deadline
+                                height,
+                                to_rs_address(account_id),
+                                nonce,
+                                d,
+                                result.deadline
                             );
                         }
                     }
                     Err(FetchError::Pool(e)) => {
+                        let mut submission =
+                            Submission::new(&rh.chain, &endpoint, account_id, height, nonce, d);
+                        submission.error = Some(format!("{}: {}", e.code, e.message));
+                        rh.submitted(submission);
                         error!(
                             "submit: error submitting nonce, height={}, account={}, nonce={}, \
                              deadline={}\n\tcode: {}\n\tmessage: {}",
-                            height, account_id, nonce, d, e.code, e.message,
+                            height,
+                            to_rs_address(account_id),
+                            nonce,
+                            d,
+                            e.code,
+                            e.message,
                         );
                     }
                     Err(_) => {
@@ -196,6 +331,7 @@ impl RequestHandler {
                         if retried < 3 {
                             rh.submit_nonce(
                                 &inner_handle,
+                                &endpoint,
                                 account_id,
                                 nonce,
                                 height,
@@ -203,6 +339,10 @@ impl RequestHandler {
                                 retried + 1,
                             );
                         } else {
+                            let mut submission =
+                                Submission::new(&rh.chain, &endpoint, account_id, height, nonce, d);
+                            submission.error = Some("exhausted retries".to_owned());
+                            rh.submitted(submission);
                             error!(
                                 "{: <80}",
                                 "submit: error submitting nonce, exhausted retries"
@@ -215,19 +355,32 @@ impl RequestHandler {
         ));
     }
 
-    fn uri_for(&self, path: &str) -> hyper::Uri {
-        (self.base_uri.clone() + path).parse().unwrap()
+    fn submitted(&self, submission: Submission) {
+        self.journal.record(&Event::submission(&submission));
+        self.metrics.submitted(submission);
     }
 
-    fn post_req(&self, path: &str) -> Request<hyper::Body> {
-        Request::post(self.uri_for(path))
+    fn uri_for(&self, base_uri: &str, path: &str) -> hyper::Uri {
+        (base_uri.to_owned() + path).parse().unwrap()
+    }
+
+    fn post_req(&self, base_uri: &str, path: &str) -> Request<hyper::Body> {
+        Request::post(self.uri_for(base_uri, path))
             .header("User-Agent", self.ua.to_owned())
             .body(hyper::Body::empty())
             .unwrap()
     }
 
-    fn get_req(&self, path: &str) -> Request<hyper::Body> {
-        Request::get(self.uri_for(path))
+    fn post_form_req(&self, base_uri: &str, path: &str, form: String) -> Request<hyper::Body> {
+        Request::post(self.uri_for(base_uri, path))
+            .header("User-Agent", self.ua.to_owned())
+            .header("Content-Type", "application/x-www-form-urlencoded")
+            .body(hyper::Body::from(form))
+            .unwrap()
+    }
+
+    fn get_req(&self, base_uri: &str, path: &str) -> Request<hyper::Body> {
+        Request::get(self.uri_for(base_uri, path))
             .header("User-Agent", self.ua.to_owned())
             .body(hyper::Body::empty())
             .unwrap()
@@ -259,6 +412,34 @@ impl RequestHandler {
     }
 }
 
+impl MiningInfoSource for RequestHandler {
+    fn get_mining_info(&self) -> Box<Future<Item = MiningInfo, Error = FetchError>> {
+        RequestHandler::get_mining_info(self)
+    }
+}
+
+impl NonceSink for RequestHandler {
+    fn submit_nonce(
+        &self,
+        handle: &Handle,
+        endpoint: &str,
+        account_id: u64,
+        nonce: u64,
+        height: u64,
+        deadline: u64,
+    ) {
+        // the block came from another source, e.g. a replay
+        let endpoint = if endpoint.is_empty() {
+            self.endpoints.lock().unwrap().active().1
+        } else {
+            endpoint.to_owned()
+        };
+        RequestHandler::submit_nonce(
+            self, handle, &endpoint, account_id, nonce, height, deadline, 0,
+        );
+    }
+}
+
 fn parse_json_result<T: DeserializeOwned>(c: &hyper::Chunk) -> Result<T, PoolError> {
     match serde_json::from_slice(c) {
         Ok(x) => Ok(x),
@@ -274,3 +455,175 @@ fn parse_json_result<T: DeserializeOwned>(c: &hyper::Chunk) -> Result<T, PoolErr
         },
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use mock_wallet::{MockWallet, Reply};
+    use tokio_core::reactor::Core;
+
+    fn request_handler(core: &Core, urls: Vec<String>) -> RequestHandler {
+        let mut secret_phrases = HashMap::new();
+        secret_phrases.insert(1, "glad suffer red".to_owned());
+        RequestHandler::new(
+            "burst",
+            urls,
+            secret_phrases,
+            300,
+            2,
+            Arc::new(Metrics::default()),
+            Arc::new(Journal::disabled()),
+            core.handle(),
+        )
+    }
+
+    #[test]
+    fn test_get_mining_info() {
+        let wallet = MockWallet::start();
+        let gensig = "ab".repeat(32);
+        wallet.mining_info(vec![
+            Reply::mining_info(500, 70_000, &gensig),
+            Reply::pool_mining_info(501, 60_000, &gensig, 1000),
+            Reply::PoolError(7, "no mining info".to_owned()),
+            Reply::Delay(1000, Box::new(Reply::mining_info(502, 1, &gensig))),
+            Reply::Json("garbage".to_owned()),
+        ]);
+        let mut core = Core::new().unwrap();
+        let rh = request_handler(&core, vec![wallet.url()]);
+
+        // numbers as strings and as ints
+        let mining_info = core.run(rh.get_mining_info()).ok().unwrap();
+        assert_eq!(mining_info.height, 500);
+        assert_eq!(mining_info.base_target, 70_000);
+        assert_eq!(mining_info.generation_signature, gensig);
+        assert_eq!(mining_info.target_deadline, u64::MAX);
+        assert_eq!(mining_info.endpoint, wallet.url());
+        let mining_info = core.run(rh.get_mining_info()).ok().unwrap();
+        assert_eq!(mining_info.height, 501);
+        assert_eq!(mining_info.base_target, 60_000);
+        assert_eq!(mining_info.target_deadline, 1000);
+
+        match core.run(rh.get_mining_info()) {
+            Err(FetchError::Pool(e)) => {
+                assert_eq!((e.code, e.message.as_str()), (7, "no mining info"))
+            }
+            _ => panic!("expected a pool error"),
+        }
+        match core.run(rh.get_mining_info()) {
+            Err(FetchError::Timeout(_)) => (),
+            _ => panic!("expected a timeout"),
+        }
+        match core.run(rh.get_mining_info()) {
+            Err(FetchError::Pool(e)) => assert_eq!((e.code, e.message.as_str()), (0, "garbage")),
+            _ => panic!("expected an unparsable response"),
+        }
+        assert_eq!(wallet.mining_info_requests(), 5);
+    }
+
+    #[test]
+    fn test_get_mining_info_failover() {
+        let primary = MockWallet::start();
+        let secondary = MockWallet::start();
+        let gensig = "cd".repeat(32);
+        primary.mining_info(vec![Reply::Status(503)]);
+        secondary.mining_info(vec![Reply::mining_info(7, 1, &gensig)]);
+        let mut core = Core::new().unwrap();
+        let rh = request_handler(&core, vec![primary.url(), secondary.url()]);
+
+        assert!(core.run(rh.get_mining_info()).is_err());
+        assert!(core.run(rh.get_mining_info()).is_err());
+        let mining_info = core.run(rh.get_mining_info()).ok().unwrap();
+        assert_eq!(mining_info.endpoint, secondary.url());
+
+        // the primary is probed in the background and taken back once it answers
+        primary.mining_info(vec![Reply::mining_info(7, 1, &gensig)]);
+        let mut endpoint = String::new();
+        for _ in 0..50 {
+            endpoint = core.run(rh.get_mining_info()).ok().unwrap().endpoint;
+            if endpoint == primary.url() {
+                break;
+            }
+        }
+        assert_eq!(endpoint, primary.url());
+    }
+
+    #[test]
+    fn test_submit_nonce() {
+        let wallet = MockWallet::start();
+        let mut core = Core::new().unwrap();
+        let rh = request_handler(&core, vec![wallet.url()]);
+        let turn_until = |core: &mut Core, submits: usize| {
+            for _ in 0..200 {
+                if wallet.submits().len() >= submits {
+                    break;
+                }
+                core.turn(Some(Duration::from_millis(10)));
+            }
+            // let the responses arrive
+            core.turn(Some(Duration::from_millis(50)));
+        };
+
+        // pool mining sends the deadline
+        rh.submit_nonce(&core.handle(), &wallet.url(), 2, 10, 500, 1234, 0);
+        turn_until(&mut core, 1);
+        let submit = &wallet.submits()[0];
+        assert_eq!(
+            (submit.account_id, submit.nonce, submit.height),
+            (2, 10, 500)
+        );
+        assert_eq!(submit.deadline, Some(1234));
+        assert_eq!(submit.secret_phrase, None);
+
+        // solo mining sends the passphrase instead
+        rh.submit_nonce(&core.handle(), &wallet.url(), 1, 11, 500, 99, 0);
+        turn_until(&mut core, 2);
+        let submit = &wallet.submits()[1];
+        assert_eq!(submit.secret_phrase, Some("glad suffer red".to_owned()));
+        assert_eq!(submit.deadline, None);
+        assert!(!submit.uri.contains("glad"), "{}", submit.uri);
+
+        // timeouts are retried, pool errors aren't
+        wallet.submit_replies(vec![
+            Reply::Delay(1000, Box::new(Reply::Json("{}".to_owned()))),
+            Reply::PoolError(1004, "deadline exceeded".to_owned()),
+        ]);
+        rh.submit_nonce(&core.handle(), &wallet.url(), 2, 12, 501, 5, 0);
+        turn_until(&mut core, 4);
+        core.turn(Some(Duration::from_millis(200)));
+        let submits = wallet.submits();
+        assert_eq!(submits.len(), 4);
+        assert!(submits[2..].iter().all(|s| s.nonce == 12));
+    }
+
+    #[test]
+    fn test_endpoints_failover() {
+        let mut endpoints = Endpoints::new(vec![
+            "http://primary".to_owned(),
+            "http://secondary".to_owned(),
+        ]);
+        assert_eq!(endpoints.active(), (0, "http://primary".to_owned()));
+
+        endpoints.failed(0, 2);
+        assert_eq!(endpoints.active().0, 0);
+        endpoints.failed(0, 2);
+        assert_eq!(endpoints.active(), (1, "http://secondary".to_owned()));
+
+        // failures of the secondary don't count for the primary
+        endpoints.failed(1, 2);
+        endpoints.succeeded(1);
+        endpoints.failed(1, 2);
+        assert_eq!(endpoints.active().0, 1);
+
+        endpoints.succeeded(0);
+        assert_eq!(endpoints.active().0, 0);
+    }
+
+    #[test]
+    fn test_endpoints_single() {
+        let mut endpoints = Endpoints::new(vec!["http://primary".to_owned()]);
+        for _ in 0..10 {
+            endpoints.failed(0, 3);
+        }
+        assert_eq!(endpoints.active().0, 0);
+    }
+}
@@ -0,0 +1,334 @@
+// secret phrases that don't sit in the config in clear text: "env:NAME" is read from the
+// environment, "file:PATH" from a file only its owner may access and "keystore:PATH" from an
+// encrypted keystore that is unlocked with a passphrase, prompted for once per keystore
+
+extern crate ring;
+
+use self::ring::rand::{SecureRandom, SystemRandom};
+use self::ring::{aead, digest, pbkdf2};
+use burstmath::to_rs_address;
+use hex;
+use serde_json;
+use std::collections::HashMap;
+use std::env;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+#[cfg(unix)]
+use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+#[cfg(unix)]
+use std::process::{Command, Stdio};
+
+// unlocks keystores without a prompt, e.g. when running as a service
+pub const KEYSTORE_PASSPHRASE_VAR: &str = "SCAVENGER_KEYSTORE_PASSPHRASE";
+
+const KDF: &str = "pbkdf2-sha256";
+const KDF_ITERATIONS: u32 = 100_000;
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 12;
+
+#[derive(Serialize, Deserialize)]
+struct KeystoreFile {
+    kdf: String,
+    iterations: u32,
+    salt: String,
+    nonce: String,
+    // the account id to secret phrase map as json, sealed with ChaCha20-Poly1305
+    ciphertext: String,
+}
+
+// resolves the secret phrase references of a config, keystores are only unlocked once
+pub struct Secrets {
+    keystores: HashMap<String, HashMap<u64, String>>,
+    prompt: bool,
+}
+
+impl Default for Secrets {
+    fn default() -> Secrets {
+        Secrets {
+            keystores: HashMap::new(),
+            prompt: true,
+        }
+    }
+}
+
+impl Secrets {
+    pub fn new() -> Secrets {
+        Secrets::default()
+    }
+
+    // keystores that aren't unlocked yet fail instead of prompting, e.g. on a reload
+    pub fn disable_prompt(&mut self) {
+        self.prompt = false;
+    }
+
+    // the value of an account_id_to_secret_phrase entry
+    pub fn secret_phrase(&mut self, account_id: u64, value: &str) -> Result<String, String> {
+        if value.starts_with("keystore:") {
+            let path = &value["keystore:".len()..];
+            return self
+                .keystore(path)?
+                .get(&account_id)
+                .cloned()
+                .ok_or_else(|| {
+                    format!(
+                        "keystore {}: no secret phrase for {}",
+                        path,
+                        to_rs_address(account_id)
+                    )
+                });
+        }
+        resolve(value)
+    }
+
+    // an entry of secret_phrases, a keystore adds all of its secret phrases
+    pub fn secret_phrases(&mut self, value: &str) -> Result<Vec<String>, String> {
+        if value.starts_with("keystore:") {
+            let keystore = self.keystore(&value["keystore:".len()..])?;
+            return Ok(keystore.values().cloned().collect());
+        }
+        resolve(value).map(|secret_phrase| vec![secret_phrase])
+    }
+
+    fn keystore(&mut self, path: &str) -> Result<&HashMap<u64, String>, String> {
+        if !self.keystores.contains_key(path) {
+            let passphrase = match env::var(KEYSTORE_PASSPHRASE_VAR) {
+                Ok(passphrase) => passphrase,
+                Err(_) if !self.prompt => {
+                    return Err(format!(
+                        "keystore {}: locked, set {} or restart",
+                        path, KEYSTORE_PASSPHRASE_VAR
+                    ))
+                }
+                Err(_) => prompt_passphrase(&format!("passphrase of keystore {}: ", path))
+                    .map_err(|e| format!("keystore {}: {}", path, e))?,
+            };
+            let keystore = open_keystore(path, &passphrase)?;
+            self.keystores.insert(path.to_owned(), keystore);
+        }
+        Ok(&self.keystores[path])
+    }
+}
+
+// env: and file: references, anything else is the secret phrase itself
+fn resolve(value: &str) -> Result<String, String> {
+    if value.starts_with("env:") {
+        let name = &value["env:".len()..];
+        env::var(name).map_err(|e| format!("env {}: {}", name, e))
+    } else if value.starts_with("file:") {
+        read_secret_file(&value["file:".len()..])
+    } else {
+        Ok(value.to_owned())
+    }
+}
+
+pub fn read_secret_file(path: &str) -> Result<String, String> {
+    check_permissions(path)?;
+    fs::read_to_string(path)
+        .map(|secret_phrase| secret_phrase.trim().to_owned())
+        .map_err(|e| format!("{}: {}", path, e))
+}
+
+// group and others must not have any access (chmod 600)
+#[cfg(unix)]
+fn check_permissions(path: &str) -> Result<(), String> {
+    let mode = fs::metadata(path)
+        .map_err(|e| format!("{}: {}", path, e))?
+        .permissions()
+        .mode();
+    if mode & 0o077 != 0 {
+        return Err(format!(
+            "{}: permissions {:o} are too open, use chmod 600",
+            path,
+            mode & 0o777
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn check_permissions(_path: &str) -> Result<(), String> {
+    Ok(())
+}
+
+fn derive_key(passphrase: &str, salt: &[u8], iterations: u32) -> [u8; 32] {
+    let mut key = [0u8; 32];
+    pbkdf2::derive(
+        &digest::SHA256,
+        iterations,
+        salt,
+        passphrase.as_bytes(),
+        &mut key,
+    );
+    key
+}
+
+pub fn open_keystore(path: &str, passphrase: &str) -> Result<HashMap<u64, String>, String> {
+    let err = |e: &str| format!("keystore {}: {}", path, e);
+    let keystore = fs::read_to_string(path).map_err(|e| err(&e.to_string()))?;
+    let keystore: KeystoreFile =
+        serde_json::from_str(&keystore).map_err(|e| err(&e.to_string()))?;
+    if keystore.kdf != KDF || keystore.iterations == 0 {
+        return Err(err(&format!("unsupported kdf {}", keystore.kdf)));
+    }
+    let salt = hex::decode(&keystore.salt).map_err(|e| err(&e.to_string()))?;
+    let nonce = hex::decode(&keystore.nonce).map_err(|e| err(&e.to_string()))?;
+    let mut ciphertext = hex::decode(&keystore.ciphertext).map_err(|e| err(&e.to_string()))?;
+
+    let key = derive_key(passphrase, &salt, keystore.iterations);
+    let key = aead::OpeningKey::new(&aead::CHACHA20_POLY1305, &key).unwrap();
+    let plaintext = aead::open_in_place(&key, &nonce, &[], 0, &mut ciphertext)
+        .map_err(|_| err("wrong passphrase or corrupted keystore"))?;
+    serde_json::from_slice(plaintext).map_err(|e| err(&e.to_string()))
+}
+
+// creates a new keystore, an existing one isn't overwritten
+pub fn write_keystore(
+    path: &str,
+    secret_phrases: &HashMap<u64, String>,
+    passphrase: &str,
+) -> io::Result<()> {
+    let rng = SystemRandom::new();
+    let mut salt = [0u8; SALT_LEN];
+    let mut nonce = [0u8; NONCE_LEN];
+    rng.fill(&mut salt)
+        .and_then(|_| rng.fill(&mut nonce))
+        .map_err(|_| io::Error::new(io::ErrorKind::Other, "no randomness"))?;
+
+    let key = derive_key(passphrase, &salt, KDF_ITERATIONS);
+    let key = aead::SealingKey::new(&aead::CHACHA20_POLY1305, &key).unwrap();
+    let tag_len = aead::CHACHA20_POLY1305.tag_len();
+    let mut in_out = serde_json::to_vec(secret_phrases)?;
+    in_out.extend(vec![0u8; tag_len]);
+    let len = aead::seal_in_place(&key, &nonce, &[], &mut in_out, tag_len).unwrap();
+    in_out.truncate(len);
+
+    let keystore = KeystoreFile {
+        kdf: KDF.to_owned(),
+        iterations: KDF_ITERATIONS,
+        salt: hex::encode(salt),
+        nonce: hex::encode(nonce),
+        ciphertext: hex::encode(in_out),
+    };
+    let mut options = OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    options.mode(0o600);
+    options
+        .open(path)?
+        .write_all(serde_json::to_string_pretty(&keystore)?.as_bytes())
+}
+
+// reads a line from stdin, not echoed if stdin is a terminal
+pub fn prompt_passphrase(prompt: &str) -> io::Result<String> {
+    eprint!("{}", prompt);
+    io::stderr().flush()?;
+    let echo_off = set_echo(false);
+    let mut passphrase = String::new();
+    let read = io::stdin().read_line(&mut passphrase);
+    if echo_off {
+        set_echo(true);
+        eprintln!();
+    }
+    read?;
+    Ok(passphrase.trim_end_matches(&['\r', '\n'][..]).to_owned())
+}
+
+// true if the echo of the terminal could be switched
+#[cfg(unix)]
+fn set_echo(on: bool) -> bool {
+    Command::new("stty")
+        .arg(if on { "echo" } else { "-echo" })
+        .stdin(Stdio::inherit())
+        .stderr(Stdio::null())
+        .status()
+        .map(|status| status.success())
+        .unwrap_or(false)
+}
+
+#[cfg(not(unix))]
+fn set_echo(_on: bool) -> bool {
+    false
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn temp_file(name: &str) -> PathBuf {
+        let path = env::temp_dir().join(name);
+        let _ = fs::remove_file(&path);
+        path
+    }
+
+    #[test]
+    fn test_env_and_file() {
+        env::set_var("SCAVENGER_TEST_SECRET_PHRASE", "glad suffer red");
+        let mut secrets = Secrets::new();
+        assert_eq!(
+            secrets.secret_phrase(1, "env:SCAVENGER_TEST_SECRET_PHRASE"),
+            Ok("glad suffer red".to_owned())
+        );
+        assert!(secrets
+            .secret_phrase(1, "env:SCAVENGER_TEST_UNSET")
+            .is_err());
+        assert_eq!(
+            secrets.secret_phrases("glad suffer red"),
+            Ok(vec!["glad suffer red".to_owned()])
+        );
+
+        let path = temp_file("scavenger_test_secret_phrase");
+        fs::write(&path, "stand rude those door\n").unwrap();
+        let value = format!("file:{}", path.display());
+        #[cfg(unix)]
+        {
+            fs::set_permissions(&path, fs::Permissions::from_mode(0o644)).unwrap();
+            assert!(secrets.secret_phrase(1, &value).is_err());
+            fs::set_permissions(&path, fs::Permissions::from_mode(0o600)).unwrap();
+        }
+        assert_eq!(
+            secrets.secret_phrase(1, &value),
+            Ok("stand rude those door".to_owned())
+        );
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_keystore() {
+        let path = temp_file("scavenger_test_keystore.json");
+        let path = path.to_str().unwrap();
+        let mut secret_phrases = HashMap::new();
+        secret_phrases.insert(1, "glad suffer red".to_owned());
+        secret_phrases.insert(2, "stand rude those door".to_owned());
+        write_keystore(path, &secret_phrases, "unlock").unwrap();
+        // never overwritten
+        assert!(write_keystore(path, &secret_phrases, "unlock").is_err());
+        #[cfg(unix)]
+        assert_eq!(
+            fs::metadata(path).unwrap().permissions().mode() & 0o777,
+            0o600
+        );
+        assert!(!fs::read_to_string(path).unwrap().contains("glad"));
+
+        assert_eq!(open_keystore(path, "unlock"), Ok(secret_phrases));
+        assert!(open_keystore(path, "wrong").is_err());
+
+        let mut secrets = Secrets::new();
+        secrets
+            .keystores
+            .insert(path.to_owned(), open_keystore(path, "unlock").unwrap());
+        let value = format!("keystore:{}", path);
+        assert_eq!(
+            secrets.secret_phrase(2, &value),
+            Ok("stand rude those door".to_owned())
+        );
+        assert!(secrets.secret_phrase(3, &value).is_err());
+        assert_eq!(secrets.secret_phrases(&value).unwrap().len(), 2);
+
+        // unlocked keystores stay usable, others fail without a prompt
+        secrets.disable_prompt();
+        assert_eq!(secrets.secret_phrases(&value).unwrap().len(), 2);
+        assert!(secrets.secret_phrases("keystore:nonexistent.json").is_err());
+        fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,263 @@
+// portable shabal256 and deadline search, mirrors sph_shabal.c for cpus without the SIMD kernels
+
+const A_INIT: [u32; 12] = [
+    0x52F8_4552,
+    0xE54B_7999,
+    0x2D8E_E3EC,
+    0xB964_5191,
+    0xE007_8B86,
+    0xBB7C_44C9,
+    0xD2B5_C1CA,
+    0xB0D2_EB8C,
+    0x14CE_5A45,
+    0x22AF_50DC,
+    0xEFFD_BC6B,
+    0xEB21_B74A,
+];
+
+const B_INIT: [u32; 16] = [
+    0xB555_C6EE,
+    0x3E71_0596,
+    0xA72A_652F,
+    0x9301_515F,
+    0xDA28_C1FA,
+    0x696F_D868,
+    0x9CB6_BF72,
+    0x0AFE_4002,
+    0xA6E0_3615,
+    0x5138_C1D4,
+    0xBE21_6306,
+    0xB38B_8890,
+    0x3EA8_B96B,
+    0x3299_ACE4,
+    0x3092_4DD4,
+    0x55CB_34A5,
+];
+
+const C_INIT: [u32; 16] = [
+    0xB405_F031,
+    0xC423_3EBA,
+    0xB373_3979,
+    0xC0DD_9D55,
+    0xC51C_28AE,
+    0xA327_B8E1,
+    0x56C5_6167,
+    0xED61_4433,
+    0x88B5_9D60,
+    0x60E2_CEBA,
+    0x758B_4B8B,
+    0x83E8_2A7F,
+    0xBC96_8828,
+    0xE6E0_0BF7,
+    0xBA83_9E55,
+    0x9B49_1C60,
+];
+
+#[derive(Clone)]
+pub struct Shabal256 {
+    a: [u32; 12],
+    b: [u32; 16],
+    c: [u32; 16],
+    w: u64,
+    buf: [u8; 64],
+    ptr: usize,
+}
+
+impl Default for Shabal256 {
+    fn default() -> Shabal256 {
+        Shabal256 {
+            a: A_INIT,
+            b: B_INIT,
+            c: C_INIT,
+            w: 1,
+            buf: [0; 64],
+            ptr: 0,
+        }
+    }
+}
+
+impl Shabal256 {
+    pub fn new() -> Shabal256 {
+        Shabal256::default()
+    }
+
+    pub fn update(&mut self, mut data: &[u8]) {
+        while !data.is_empty() {
+            let len = (64 - self.ptr).min(data.len());
+            self.buf[self.ptr..self.ptr + len].copy_from_slice(&data[..len]);
+            self.ptr += len;
+            data = &data[len..];
+            if self.ptr == 64 {
+                let m = decode_block(&self.buf);
+                self.input_block(&m);
+                self.ptr = 0;
+            }
+        }
+    }
+
+    pub fn finish(mut self) -> [u8; 32] {
+        self.buf[self.ptr] = 0x80;
+        for b in &mut self.buf[self.ptr + 1..] {
+            *b = 0;
+        }
+        let m = decode_block(&self.buf);
+        for (b, m) in self.b.iter_mut().zip(m.iter()) {
+            *b = b.wrapping_add(*m);
+        }
+        self.xor_w();
+        self.apply_p(&m);
+        for _ in 0..3 {
+            self.swap_bc();
+            self.xor_w();
+            self.apply_p(&m);
+        }
+
+        let mut out = [0u8; 32];
+        for (i, word) in self.b[8..].iter().enumerate() {
+            out[i * 4..i * 4 + 4].copy_from_slice(&u32_to_le_bytes(*word));
+        }
+        out
+    }
+
+    fn input_block(&mut self, m: &[u32; 16]) {
+        for (b, m) in self.b.iter_mut().zip(m.iter()) {
+            *b = b.wrapping_add(*m);
+        }
+        self.xor_w();
+        self.apply_p(m);
+        for (c, m) in self.c.iter_mut().zip(m.iter()) {
+            *c = c.wrapping_sub(*m);
+        }
+        self.swap_bc();
+        self.w = self.w.wrapping_add(1);
+    }
+
+    fn xor_w(&mut self) {
+        self.a[0] ^= self.w as u32;
+        self.a[1] ^= (self.w >> 32) as u32;
+    }
+
+    fn swap_bc(&mut self) {
+        ::std::mem::swap(&mut self.b, &mut self.c);
+    }
+
+    fn apply_p(&mut self, m: &[u32; 16]) {
+        let a = &mut self.a;
+        let b = &mut self.b;
+        let c = &self.c;
+
+        for x in b.iter_mut() {
+            *x = x.rotate_left(17);
+        }
+        for i in 0..48 {
+            let ia0 = i % 12;
+            let ia1 = (i + 11) % 12;
+            let ib = i % 16;
+            a[ia0] = (a[ia0] ^ a[ia1].rotate_left(15).wrapping_mul(5) ^ c[(24 - ib) % 16])
+                .wrapping_mul(3)
+                ^ b[(ib + 13) % 16]
+                ^ (b[(ib + 9) % 16] & !b[(ib + 6) % 16])
+                ^ m[ib];
+            b[ib] = !(b[ib].rotate_left(1) ^ a[ia0]);
+        }
+        for j in 0..36 {
+            a[11 - j % 12] = a[11 - j % 12].wrapping_add(c[(54 - j) % 16]);
+        }
+    }
+}
+
+fn decode_block(buf: &[u8; 64]) -> [u32; 16] {
+    let mut m = [0u32; 16];
+    for (i, word) in m.iter_mut().enumerate() {
+        *word = u32::from(buf[i * 4])
+            | u32::from(buf[i * 4 + 1]) << 8
+            | u32::from(buf[i * 4 + 2]) << 16
+            | u32::from(buf[i * 4 + 3]) << 24;
+    }
+    m
+}
+
+fn u32_to_le_bytes(x: u32) -> [u8; 4] {
+    [x as u8, (x >> 8) as u8, (x >> 16) as u8, (x >> 24) as u8]
+}
+
+#[cfg(test)]
+fn shabal256(data: &[u8]) -> [u8; 32] {
+    let mut hasher = Shabal256::new();
+    hasher.update(data);
+    hasher.finish()
+}
+
+// same contract as the find_best_deadline_* C kernels, the first nonce wins ties
+pub fn find_best_deadline(scoops: &[u8], nonce_count: u64, gensig: &[u8; 32]) -> (u64, u64) {
+    let mut best_deadline = u64::MAX;
+    let mut best_offset = 0;
+
+    let mut prefix = Shabal256::new();
+    prefix.update(gensig);
+    for (i, scoop) in scoops.chunks(64).take(nonce_count as usize).enumerate() {
+        let mut hasher = prefix.clone();
+        hasher.update(scoop);
+        let hash = hasher.finish();
+        let deadline = hash[..8]
+            .iter()
+            .rev()
+            .fold(0u64, |acc, &b| acc << 8 | u64::from(b));
+        if deadline < best_deadline {
+            best_deadline = deadline;
+            best_offset = i as u64;
+        }
+    }
+
+    (best_deadline, best_offset)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use plot::read_test_scoop;
+    use rand::{thread_rng, Rng};
+    use shabals;
+
+    #[test]
+    fn test_shabal256_matches_sph() {
+        let mut rng = thread_rng();
+        for len in 0..300 {
+            let mut data = vec![0u8; len];
+            rng.fill(&mut data[..]);
+            assert_eq!(shabal256(&data), shabals::shabal256(&data), "len={}", len);
+        }
+
+        // streaming in uneven chunks
+        let mut data = vec![0u8; 1000];
+        rng.fill(&mut data[..]);
+        let mut hasher = Shabal256::new();
+        for chunk in data.chunks(37) {
+            hasher.update(chunk);
+        }
+        assert_eq!(hasher.finish(), shabals::shabal256(&data));
+    }
+
+    #[test]
+    fn test_find_best_deadline() {
+        let bs = read_test_scoop(100);
+
+        let gensig = [7u8; 32];
+        let (deadline, offset) = find_best_deadline(&bs, 8, &gensig);
+
+        let mut expected = (u64::MAX, 0);
+        for i in 0..8 {
+            let mut data = gensig.to_vec();
+            data.extend_from_slice(&bs[i * 64..(i + 1) * 64]);
+            let hash = shabals::shabal256(&data);
+            let mut d = 0u64;
+            for b in hash[..8].iter().rev() {
+                d = d << 8 | u64::from(*b);
+            }
+            if d < expected.0 {
+                expected = (d, i as u64);
+            }
+        }
+        assert_eq!((deadline, offset), expected);
+    }
+}
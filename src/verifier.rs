@@ -0,0 +1,171 @@
+use noncegen::generate_nonce;
+use plot::{Plot, SCOOPS_IN_NONCE, SCOOP_SIZE};
+use std::error::Error;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::PathBuf;
+
+#[derive(Debug, PartialEq)]
+pub struct CorruptRange {
+    // first and last corrupt sample, the nonces between them weren't all checked
+    pub first_nonce: u64,
+    pub last_nonce: u64,
+    // sample nonces in the range
+    pub sampled: u64,
+    pub bad_scoops: u64,
+}
+
+// sample nonces regenerated at once, 256 KiB each
+const BATCH_NONCES: usize = 128;
+// the scoops of a batch are read at once if they lie this close together
+const MAX_SPAN: u64 = 1024 * 1024;
+
+// regenerate sample nonces of the plot and compare them with the scoops on disk,
+// consecutive corrupt sample nonces are merged into ranges
+pub fn verify_plot(path: &PathBuf, sample: u64) -> Result<Vec<CorruptRange>, Box<Error>> {
+    let mut plot = Plot::new(path, false, false)?;
+    let sample = if sample == 0 || sample > plot.nonces {
+        plot.nonces
+    } else {
+        sample
+    };
+    let offsets: Vec<u64> = (0..sample).map(|i| i * plot.nonces / sample).collect();
+
+    let mut ranges: Vec<CorruptRange> = Vec::new();
+    let mut last_bad = false;
+    for batch in offsets.chunks(BATCH_NONCES) {
+        let nonces: Vec<Vec<u8>> = batch
+            .iter()
+            .map(|&offset| generate_nonce(plot.account_id, plot.start_nonce + offset, plot.poc2))
+            .collect();
+        let bad_scoops = count_bad_scoops(&mut plot, batch, &nonces, MAX_SPAN)?;
+
+        for (&offset, bad_scoops) in batch.iter().zip(bad_scoops) {
+            if bad_scoops == 0 {
+                last_bad = false;
+                continue;
+            }
+            if last_bad {
+                let range = ranges.last_mut().unwrap();
+                range.last_nonce = plot.start_nonce + offset;
+                range.sampled += 1;
+                range.bad_scoops += bad_scoops;
+            } else {
+                ranges.push(CorruptRange {
+                    first_nonce: plot.start_nonce + offset,
+                    last_nonce: plot.start_nonce + offset,
+                    sampled: 1,
+                    bad_scoops,
+                });
+            }
+            last_bad = true;
+        }
+    }
+
+    Ok(ranges)
+}
+
+// the scoops of ascending sample offsets are compared scoop by scoop, so the file is read front
+// to back once per batch instead of jumping across it for every nonce
+fn count_bad_scoops(
+    plot: &mut Plot,
+    offsets: &[u64],
+    nonces: &[Vec<u8>],
+    max_span: u64,
+) -> Result<Vec<u64>, Box<Error>> {
+    let scoop_size = SCOOP_SIZE as usize;
+    let first = offsets[0];
+    let span = (offsets[offsets.len() - 1] - first + 1) * SCOOP_SIZE;
+    let dense = span <= max_span;
+    let mut buffer = vec![0u8; if dense { span as usize } else { scoop_size }];
+
+    let mut bad_scoops = vec![0; offsets.len()];
+    for n in 0..SCOOPS_IN_NONCE {
+        let row = n * plot.nonces;
+        if dense {
+            plot.fh.seek(SeekFrom::Start((row + first) * SCOOP_SIZE))?;
+            plot.fh.read_exact(&mut buffer)?;
+        }
+        let expected = n as usize * scoop_size..(n as usize + 1) * scoop_size;
+        for (i, &offset) in offsets.iter().enumerate() {
+            let start = if dense {
+                ((offset - first) * SCOOP_SIZE) as usize
+            } else {
+                plot.fh.seek(SeekFrom::Start((row + offset) * SCOOP_SIZE))?;
+                plot.fh.read_exact(&mut buffer)?;
+                0
+            };
+            if buffer[start..start + scoop_size] != nonces[i][expected.clone()] {
+                bad_scoops[i] += 1;
+            }
+        }
+    }
+    Ok(bad_scoops)
+}
+
+pub fn verify(file: &str, sample: u64) -> bool {
+    let path = PathBuf::from(file);
+    info!("verify: file={}, sample={} nonces", file, sample);
+    match verify_plot(&path, sample) {
+        Ok(ranges) => {
+            for range in &ranges {
+                error!(
+                    "verify: corrupt samples between nonces {}..={}, sampled nonces={}, bad \
+                     scoops={}/{}",
+                    range.first_nonce,
+                    range.last_nonce,
+                    range.sampled,
+                    range.bad_scoops,
+                    range.sampled * SCOOPS_IN_NONCE
+                );
+            }
+            if ranges.is_empty() {
+                info!("verify: {} ok", file);
+            } else {
+                error!("verify: {} is corrupt", file);
+            }
+            ranges.is_empty()
+        }
+        Err(e) => {
+            error!("verify: failed to verify {}: {}", file, e);
+            false
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_verify_plot() {
+        let path = PathBuf::from("test_data/10282355196851764065_0_8");
+        let ranges = verify_plot(&path, 0).unwrap();
+        assert_eq!(
+            ranges,
+            vec![CorruptRange {
+                first_nonce: 7,
+                last_nonce: 7,
+                sampled: 1,
+                bad_scoops: 1,
+            }]
+        );
+    }
+
+    #[test]
+    fn test_count_bad_scoops() {
+        let path = PathBuf::from("test_data/10282355196851764065_0_8");
+        let mut plot = Plot::new(&path, false, false).unwrap();
+        let offsets = vec![1, 3, 7];
+        let nonces: Vec<Vec<u8>> = offsets
+            .iter()
+            .map(|&offset| generate_nonce(plot.account_id, plot.start_nonce + offset, plot.poc2))
+            .collect();
+        // read as one span and scoop by scoop
+        for &max_span in &[MAX_SPAN, 0] {
+            assert_eq!(
+                count_bad_scoops(&mut plot, &offsets, &nonces, max_span).unwrap(),
+                vec![0, 0, 1]
+            );
+        }
+    }
+}
@@ -1,14 +1,30 @@
 use chan;
 use futures::sync::mpsc;
 use futures::{Future, Sink};
+#[cfg(any(target_arch = "x86", target_arch = "x86_64", target_arch = "aarch64"))]
 use libc::{c_void, uint64_t};
 use miner::Buffer;
 #[cfg(feature = "opencl")]
 use ocl;
 
 use reader::ReadReply;
+use shabal_portable;
+use std::fmt;
 use std::u64;
+
+// the widest kernel works on this many nonces at once
+const SIMD_NONCES: usize = 16;
+
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
 extern "C" {
+    pub fn init_shabal_avx512f() -> ();
+
+    pub fn init_shabal_avx2() -> ();
+
+    pub fn init_shabal_avx() -> ();
+
+    pub fn init_shabal_sse2() -> ();
+
     pub fn find_best_deadline_avx512f(
         scoops: *mut c_void,
         nonce_count: uint64_t,
@@ -42,7 +58,102 @@ extern "C" {
     ) -> ();
 }
 
+#[cfg(target_arch = "aarch64")]
+extern "C" {
+    pub fn init_shabal_neon() -> ();
+
+    pub fn find_best_deadline_neon(
+        scoops: *mut c_void,
+        nonce_count: uint64_t,
+        gensig: *const c_void,
+        best_deadline: *mut uint64_t,
+        best_offset: *mut uint64_t,
+    ) -> ();
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SimdExtension {
+    Avx512f,
+    Avx2,
+    Avx,
+    Sse2,
+    #[cfg_attr(not(target_arch = "aarch64"), allow(dead_code))]
+    Neon,
+    // pure rust, runs everywhere
+    Portable,
+}
+
+impl SimdExtension {
+    #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+    pub fn detect() -> SimdExtension {
+        if is_x86_feature_detected!("avx512f") {
+            SimdExtension::Avx512f
+        } else if is_x86_feature_detected!("avx2") {
+            SimdExtension::Avx2
+        } else if is_x86_feature_detected!("avx") {
+            SimdExtension::Avx
+        } else if is_x86_feature_detected!("sse2") {
+            SimdExtension::Sse2
+        } else {
+            SimdExtension::Portable
+        }
+    }
+
+    // NEON is mandatory on arm64
+    #[cfg(target_arch = "aarch64")]
+    pub fn detect() -> SimdExtension {
+        SimdExtension::Neon
+    }
+
+    #[cfg(not(any(target_arch = "x86", target_arch = "x86_64", target_arch = "aarch64")))]
+    pub fn detect() -> SimdExtension {
+        SimdExtension::Portable
+    }
+
+    // the SIMD kernels share a precomputed shabal state which has to be set up once
+    pub fn init(self) {
+        unsafe {
+            match self {
+                #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+                SimdExtension::Avx512f => init_shabal_avx512f(),
+                #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+                SimdExtension::Avx2 => init_shabal_avx2(),
+                #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+                SimdExtension::Avx => init_shabal_avx(),
+                #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+                SimdExtension::Sse2 => init_shabal_sse2(),
+                #[cfg(target_arch = "aarch64")]
+                SimdExtension::Neon => init_shabal_neon(),
+                _ => (),
+            }
+        }
+    }
+
+    // cpu_simd_extension from the config, anything but portable means auto-detect
+    pub fn from_cfg(s: &str) -> SimdExtension {
+        match s.to_lowercase().as_ref() {
+            "portable" => SimdExtension::Portable,
+            _ => SimdExtension::detect(),
+        }
+    }
+}
+
+impl fmt::Display for SimdExtension {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SimdExtension::Avx512f => write!(f, "AVX512F"),
+            SimdExtension::Avx2 => write!(f, "AVX2"),
+            SimdExtension::Avx => write!(f, "AVX"),
+            SimdExtension::Sse2 => write!(f, "SSE2"),
+            SimdExtension::Neon => write!(f, "NEON"),
+            SimdExtension::Portable => write!(f, "none (portable)"),
+        }
+    }
+}
+
+#[derive(Clone)]
 pub struct NonceData {
+    pub chain: usize,
     pub height: u64,
     pub deadline: u64,
     pub nonce: u64,
@@ -52,6 +163,7 @@ pub struct NonceData {
 
 pub fn create_worker_task(
     benchmark: bool,
+    simd_ext: SimdExtension,
     rx_read_replies: chan::Receiver<ReadReply>,
     tx_empty_buffers: chan::Sender<Box<Buffer + Send>>,
     tx_nonce_data: mpsc::Sender<NonceData>,
@@ -60,6 +172,20 @@ pub fn create_worker_task(
         for read_reply in rx_read_replies {
             let mut buffer = read_reply.buffer;
             if read_reply.len == 0 {
+                // a drive can finish without data if its last plot was skipped
+                if read_reply.finished {
+                    tx_nonce_data
+                        .clone()
+                        .send(NonceData {
+                            chain: read_reply.chain,
+                            height: read_reply.height,
+                            deadline: u64::MAX,
+                            nonce: 0,
+                            reader_task_processed: true,
+                            account_id: read_reply.account_id,
+                        }).wait()
+                        .expect("failed to send nonce data");
+                }
                 tx_empty_buffers.send(buffer);
                 continue;
             }
@@ -74,44 +200,14 @@ pub fn create_worker_task(
                 #[cfg(feature = "opencl")]
                 match &gpu_context {
                     None => {
-                        let mut_bs = buffer.get_buffer();
-                        let mut bs = mut_bs.lock().unwrap();
-                        let padded = pad(&mut bs, read_reply.len, 8 * 64);
-                        unsafe {
-                            if is_x86_feature_detected!("avx512f") {
-                                find_best_deadline_avx512f(
-                                    bs.as_ptr() as *mut c_void,
-                                    (read_reply.len as u64 + padded as u64) / 64,
-                                    read_reply.gensig.as_ptr() as *const c_void,
-                                    &mut deadline,
-                                    &mut offset,
-                                );
-                            } else if is_x86_feature_detected!("avx2") {
-                                find_best_deadline_avx2(
-                                    bs.as_ptr() as *mut c_void,
-                                    (read_reply.len as u64 + padded as u64) / 64,
-                                    read_reply.gensig.as_ptr() as *const c_void,
-                                    &mut deadline,
-                                    &mut offset,
-                                );
-                            } else if is_x86_feature_detected!("avx") {
-                                find_best_deadline_avx(
-                                    bs.as_ptr() as *mut c_void,
-                                    (read_reply.len as u64 + padded as u64) / 64,
-                                    read_reply.gensig.as_ptr() as *const c_void,
-                                    &mut deadline,
-                                    &mut offset,
-                                );
-                            } else {
-                                find_best_deadline_sse2(
-                                    bs.as_ptr() as *mut c_void,
-                                    (read_reply.len as u64 + padded as u64) / 64,
-                                    read_reply.gensig.as_ptr() as *const c_void,
-                                    &mut deadline,
-                                    &mut offset,
-                                );
-                            }
-                        }
+                        let tuple = find_best_deadline_cpu(
+                            simd_ext,
+                            &mut buffer.get_buffer().lock().unwrap(),
+                            read_reply.len,
+                            &read_reply.gensig,
+                        );
+                        deadline = tuple.0;
+                        offset = tuple.1;
                     }
                     Some(_context) => {
                         let tuple = ocl::find_best_deadline_gpu(
@@ -125,50 +221,21 @@ pub fn create_worker_task(
                 }
                 #[cfg(not(feature = "opencl"))]
                 {
-                    let mut_bs = buffer.get_buffer();
-                    let mut bs = mut_bs.lock().unwrap();
-                    let padded = pad(&mut bs, read_reply.len, 8 * 64);
-                    unsafe {
-                        if is_x86_feature_detected!("avx512f") {
-                            find_best_deadline_avx512f(
-                                bs.as_ptr() as *mut c_void,
-                                (read_reply.len as u64 + padded as u64) / 64,
-                                read_reply.gensig.as_ptr() as *const c_void,
-                                &mut deadline,
-                                &mut offset,
-                            );
-                        } else if is_x86_feature_detected!("avx2") {
-                            find_best_deadline_avx2(
-                                bs.as_ptr() as *mut c_void,
-                                (read_reply.len as u64 + padded as u64) / 64,
-                                read_reply.gensig.as_ptr() as *const c_void,
-                                &mut deadline,
-                                &mut offset,
-                            );
-                        } else if is_x86_feature_detected!("avx") {
-                            find_best_deadline_avx(
-                                bs.as_ptr() as *mut c_void,
-                                (read_reply.len as u64 + padded as u64) / 64,
-                                read_reply.gensig.as_ptr() as *const c_void,
-                                &mut deadline,
-                                &mut offset,
-                            );
-                        } else {
-                            find_best_deadline_sse2(
-                                bs.as_ptr() as *mut c_void,
-                                (read_reply.len as u64 + padded as u64) / 64,
-                                read_reply.gensig.as_ptr() as *const c_void,
-                                &mut deadline,
-                                &mut offset,
-                            );
-                        }
-                    }
+                    let tuple = find_best_deadline_cpu(
+                        simd_ext,
+                        &mut buffer.get_buffer().lock().unwrap(),
+                        read_reply.len,
+                        &read_reply.gensig,
+                    );
+                    deadline = tuple.0;
+                    offset = tuple.1;
                 }
             }
 
             tx_nonce_data
                 .clone()
                 .send(NonceData {
+                    chain: read_reply.chain,
                     height: read_reply.height,
                     deadline,
                     nonce: offset + read_reply.start_nonce,
@@ -181,14 +248,144 @@ pub fn create_worker_task(
     }
 }
 
+#[cfg(any(target_arch = "x86", target_arch = "x86_64", target_arch = "aarch64"))]
+fn find_best_deadline_cpu(
+    simd_ext: SimdExtension,
+    bs: &mut [u8],
+    len: usize,
+    gensig: &[u8; 32],
+) -> (u64, u64) {
+    let kernel = match simd_ext {
+        #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+        SimdExtension::Avx512f => find_best_deadline_avx512f,
+        #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+        SimdExtension::Avx2 => find_best_deadline_avx2,
+        #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+        SimdExtension::Avx => find_best_deadline_avx,
+        #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+        SimdExtension::Sse2 => find_best_deadline_sse2,
+        #[cfg(target_arch = "aarch64")]
+        SimdExtension::Neon => find_best_deadline_neon,
+        _ => return shabal_portable::find_best_deadline(bs, len as u64 / 64, gensig),
+    };
+
+    let mut deadline: u64 = u64::MAX;
+    let mut offset: u64 = 0;
+    let padded = pad(bs, len, SIMD_NONCES * 64);
+    let nonce_count = (len as u64 + padded as u64) / 64;
+    unsafe {
+        kernel(
+            bs.as_ptr() as *mut c_void,
+            nonce_count,
+            gensig.as_ptr() as *const c_void,
+            &mut deadline,
+            &mut offset,
+        );
+    }
+    (deadline, offset)
+}
+
+#[cfg(not(any(target_arch = "x86", target_arch = "x86_64", target_arch = "aarch64")))]
+fn find_best_deadline_cpu(
+    _simd_ext: SimdExtension,
+    bs: &mut [u8],
+    len: usize,
+    gensig: &[u8; 32],
+) -> (u64, u64) {
+    shabal_portable::find_best_deadline(bs, len as u64 / 64, gensig)
+}
+
+// nonces a cpu buffer needs for a cache, a full cache gets padded to a multiple of SIMD_NONCES
+pub fn cpu_buffer_nonces(nonces_per_cache: usize) -> usize {
+    (nonces_per_cache + SIMD_NONCES - 1) / SIMD_NONCES * SIMD_NONCES
+}
+
+// repeats the last scoop up to a multiple of p, on equal deadlines the kernels keep the first one
+#[cfg(any(target_arch = "x86", target_arch = "x86_64", target_arch = "aarch64"))]
 pub fn pad(b: &mut [u8], l: usize, p: usize) -> usize {
     let r = p - l % p;
     if r != p {
         for i in 0..r {
-            b[i] = b[0];
+            b[l + i] = b[l - 64 + i % 64];
         }
         r
     } else {
         0
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use plot::read_test_scoop;
+
+    #[test]
+    fn test_find_best_deadline_portable_matches_simd() {
+        let mut bs = read_test_scoop(7);
+        let gensig = [42u8; 32];
+
+        let mut variants = vec![SimdExtension::detect()];
+        #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+        {
+            variants.push(SimdExtension::Sse2);
+            if is_x86_feature_detected!("avx") {
+                variants.push(SimdExtension::Avx);
+            }
+            if is_x86_feature_detected!("avx2") {
+                variants.push(SimdExtension::Avx2);
+            }
+        }
+
+        // the kernels use aligned loads and need room for padding, the reader buffers are page aligned
+        let mut aligned = vec![0u8; 16 * 64 + 64];
+        let start = aligned.as_ptr().align_offset(64);
+        let aligned = &mut aligned[start..start + 16 * 64];
+
+        // also covers a partial chunk which gets padded
+        for nonces in 5..9 {
+            let len = nonces * 64;
+            let expected = find_best_deadline_cpu(SimdExtension::Portable, &mut bs, len, &gensig);
+            assert!(expected.0 < u64::MAX);
+            for &simd_ext in &variants {
+                simd_ext.init();
+                aligned[..len].copy_from_slice(&bs[..len]);
+                assert_eq!(
+                    find_best_deadline_cpu(simd_ext, aligned, len, &gensig),
+                    expected,
+                    "{} nonces, {}",
+                    nonces,
+                    simd_ext
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_pad_full_cache() {
+        assert_eq!(cpu_buffer_nonces(16), 16);
+        assert_eq!(cpu_buffer_nonces(24), 32);
+        assert_eq!(cpu_buffer_nonces(65528), 65536);
+
+        let bs = read_test_scoop(7);
+        let gensig = [42u8; 32];
+
+        // a full read of a cache of 24 nonces, which is no multiple of 16
+        let nonces = 24;
+        let len = nonces * 64;
+        let size = cpu_buffer_nonces(nonces) * 64;
+        let mut aligned = vec![0u8; size + 64];
+        let start = aligned.as_ptr().align_offset(64);
+        let aligned = &mut aligned[start..start + size];
+        for chunk in aligned[..len].chunks_mut(8 * 64) {
+            chunk.copy_from_slice(&bs);
+        }
+        let mut portable = aligned[..len].to_vec();
+        let expected = find_best_deadline_cpu(SimdExtension::Portable, &mut portable, len, &gensig);
+        let simd_ext = SimdExtension::detect();
+        simd_ext.init();
+        assert_eq!(
+            find_best_deadline_cpu(simd_ext, aligned, len, &gensig),
+            expected
+        );
+    }
+}